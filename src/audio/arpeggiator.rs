@@ -0,0 +1,266 @@
+use std::fmt;
+
+use crate::audio::module::clock::{Division, Feel, NoteDivision};
+use crate::audio::module::midi::{MidiUpdate, ALL_NOTES_OFF_CC, ALL_SOUND_OFF_CC};
+use crate::audio::rng::Rng;
+
+const MAX_OCTAVES: u8 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArpOrder {
+    Up,
+    Down,
+    UpDown,
+    Random,
+    AsPlayed,
+}
+
+impl ArpOrder {
+    pub const ALL: [ArpOrder; 5] = [ArpOrder::Up, ArpOrder::Down, ArpOrder::UpDown, ArpOrder::Random, ArpOrder::AsPlayed];
+}
+
+impl fmt::Display for ArpOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArpOrder::Up => write!(f, "Up"),
+            ArpOrder::Down => write!(f, "Down"),
+            ArpOrder::UpDown => write!(f, "Up/down"),
+            ArpOrder::Random => write!(f, "Random"),
+            ArpOrder::AsPlayed => write!(f, "As played"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ArpeggiatorUpdate {
+    Enabled(bool),
+    Order(ArpOrder),
+    Octaves(u8),
+    /// Part of each step the note is held for, from 0 to 1
+    Gate(f32),
+    Division(NoteDivision),
+    /// Keeps playing released notes until a new chord is started
+    Latch(bool),
+}
+
+/// Turns held notes into a pattern stepped by the clock, in front of the `Midi` module
+pub struct Arpeggiator {
+    enabled: bool,
+    order: ArpOrder,
+    octaves: u8,
+    gate: f32,
+    division: NoteDivision,
+    latch: bool,
+    sustain: bool,
+
+    /// Notes in the pattern in the order they were played, with their velocity
+    notes: Vec<(u8, u8)>,
+    /// Keys physically down, tracked while disabled too
+    pressed: [bool; 128],
+    velocities: [u8; 128],
+    pattern: Vec<(u8, u8)>,
+    index: usize,
+    last_step: Option<i64>,
+    playing: Option<u8>,
+    rng: Rng,
+}
+
+impl Arpeggiator {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            order: ArpOrder::Up,
+            octaves: 1,
+            gate: 0.5,
+            division: NoteDivision::new(Division::Sixteenth, Feel::Straight),
+            latch: false,
+            sustain: false,
+
+            notes: Vec::with_capacity(128),
+            pressed: [false; 128],
+            velocities: [0; 128],
+            pattern: Vec::with_capacity(2 * 128 * MAX_OCTAVES as usize),
+            index: 0,
+            last_step: None,
+            playing: None,
+            rng: Rng::new(0x2545_F491),
+        }
+    }
+
+    pub fn update(&mut self, update: ArpeggiatorUpdate, mut emit: impl FnMut(MidiUpdate)) {
+        match update {
+            ArpeggiatorUpdate::Enabled(enabled) => {
+                self.stop(&mut emit);
+                self.notes.clear();
+                // Keys held from before are sounding on the `Midi` module, they move into the pattern instead
+                if enabled && !self.enabled {
+                    if self.sustain {
+                        emit(MidiUpdate::PedalRelease);
+                    }
+                    for key in 0..128 {
+                        if self.pressed[key as usize] {
+                            emit(MidiUpdate::KeyRelease(key));
+                            self.notes.push((key, self.velocities[key as usize]));
+                        }
+                    }
+                }
+                self.enabled = enabled;
+            },
+            ArpeggiatorUpdate::Order(order) => self.order = order,
+            ArpeggiatorUpdate::Octaves(octaves) => self.octaves = octaves.clamp(1, MAX_OCTAVES),
+            ArpeggiatorUpdate::Gate(gate) => self.gate = gate.clamp(0.01, 1.0),
+            ArpeggiatorUpdate::Division(division) => self.division = division,
+            ArpeggiatorUpdate::Latch(latch) => {
+                self.latch = latch;
+                self.drop_released();
+            },
+        }
+        self.build_pattern();
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Takes an update meant for the `Midi` module, note and pedal messages are held back while enabled
+    pub fn input(&mut self, update: MidiUpdate, mut emit: impl FnMut(MidiUpdate)) {
+        if !self.enabled {
+            match update {
+                MidiUpdate::KeyPress(note, velocity) => {
+                    self.pressed[note as usize] = true;
+                    self.velocities[note as usize] = velocity;
+                },
+                MidiUpdate::KeyRelease(note) => self.pressed[note as usize] = false,
+                MidiUpdate::PedalPress => self.sustain = true,
+                MidiUpdate::PedalRelease => self.sustain = false,
+                _ => (),
+            }
+            emit(update);
+            return;
+        }
+
+        match update {
+            MidiUpdate::KeyPress(note, velocity) => {
+                // A new chord replaces the latched one
+                if self.latch && !self.pressed.contains(&true) {
+                    self.notes.clear();
+                }
+                self.pressed[note as usize] = true;
+                self.velocities[note as usize] = velocity;
+                self.notes.retain(|(key, _)| *key != note);
+                self.notes.push((note, velocity));
+            },
+            MidiUpdate::KeyRelease(note) => {
+                self.pressed[note as usize] = false;
+                if !self.latch && !self.sustain {
+                    self.notes.retain(|(key, _)| *key != note);
+                }
+            },
+            MidiUpdate::PedalPress => self.sustain = true,
+            MidiUpdate::PedalRelease => {
+                self.sustain = false;
+                self.drop_released();
+            },
+            MidiUpdate::ControlChange(ALL_SOUND_OFF_CC | ALL_NOTES_OFF_CC, _) => {
+                self.reset(&mut emit);
+                emit(update);
+            },
+            _ => {
+                emit(update);
+                return;
+            },
+        }
+        self.build_pattern();
+    }
+
+    /// Advances by one sample, `position` is the clock position in beats
+    pub fn process(&mut self, position: f32, running: bool, mut emit: impl FnMut(MidiUpdate)) {
+        if !self.enabled {
+            return;
+        }
+        if !running || self.pattern.is_empty() {
+            self.stop(&mut emit);
+            return;
+        }
+
+        let steps = position / self.division.beats();
+        let step = steps.floor() as i64;
+
+        if self.last_step != Some(step) {
+            self.last_step = Some(step);
+            self.release(&mut emit);
+
+            let (note, velocity) = match self.order {
+                ArpOrder::Random => self.pattern[self.rng.below(self.pattern.len())],
+                _ => {
+                    self.index %= self.pattern.len();
+                    self.pattern[self.index]
+                },
+            };
+            self.index += 1;
+            emit(MidiUpdate::KeyPress(note, velocity));
+            self.playing = Some(note);
+        } else if steps.fract() >= self.gate {
+            self.release(&mut emit);
+        }
+    }
+
+    /// Forgets every note, including latched ones, and releases the one playing
+    pub fn reset(&mut self, mut emit: impl FnMut(MidiUpdate)) {
+        self.stop(&mut emit);
+        self.notes.clear();
+        self.pressed = [false; 128];
+        self.sustain = false;
+        self.build_pattern();
+    }
+
+    fn release(&mut self, emit: &mut impl FnMut(MidiUpdate)) {
+        if let Some(note) = self.playing.take() {
+            emit(MidiUpdate::KeyRelease(note));
+        }
+    }
+
+    fn stop(&mut self, emit: &mut impl FnMut(MidiUpdate)) {
+        self.release(emit);
+        self.last_step = None;
+        self.index = 0;
+    }
+
+    /// Forgets notes that are only held by the pedal or latch once neither applies
+    fn drop_released(&mut self) {
+        if !self.latch && !self.sustain {
+            let pressed = self.pressed;
+            self.notes.retain(|(key, _)| pressed[*key as usize]);
+        }
+    }
+
+    fn build_pattern(&mut self) {
+        self.pattern.clear();
+        self.pattern.extend_from_slice(&self.notes);
+        if self.order != ArpOrder::AsPlayed {
+            // Keys are unique, so an unstable sort gives the same order without allocating
+            self.pattern.sort_unstable_by_key(|(key, _)| *key);
+        }
+
+        let len = self.pattern.len();
+        for octave in 1..self.octaves {
+            for i in 0..len {
+                let (key, velocity) = self.pattern[i];
+                if key + octave * 12 < 128 {
+                    self.pattern.push((key + octave * 12, velocity));
+                }
+            }
+        }
+
+        match self.order {
+            ArpOrder::Down => self.pattern.reverse(),
+            // Back down without repeating the top and bottom notes
+            ArpOrder::UpDown if self.pattern.len() > 2 => {
+                for i in (1..self.pattern.len() - 1).rev() {
+                    self.pattern.push(self.pattern[i]);
+                }
+            },
+            _ => (),
+        }
+    }
+}
@@ -1,14 +1,25 @@
+pub mod arpeggiator;
 pub mod module;
+pub mod player;
+mod rng;
+pub mod sfz;
 mod table;
+pub mod tuning;
+pub mod wavetable;
+
+pub use table::ModuleKind;
 
 use std::time::{Duration, Instant};
 
 use crate::synth::Message;
+use crate::synth::midi;
 
 use iced::futures::{SinkExt, Stream};
 use iced::stream;
-use iced::futures::channel::mpsc::{self as iced_mpsc, Receiver};
-use module::ModuleMessage;
+use iced::futures::channel::mpsc::{self as iced_mpsc, Receiver, Sender};
+use arpeggiator::ArpeggiatorUpdate;
+use module::{ModuleMessage, ModuleMessageUnion};
+use player::{Player, PlayerCommand};
 use rodio::buffer::SamplesBuffer;
 use rodio::{OutputStream, Sink, Source};
 use table::ModTable;
@@ -18,11 +29,21 @@ pub enum Input {
     Close,
     UpdateSampleRate(usize),
     ModuleMessage(usize, ModuleMessage),
+    /// Applied together between two buffers, so a preset never plays half loaded
+    ModuleMessages(Vec<(usize, ModuleMessage)>),
+    Player(PlayerCommand),
+    Arpeggiator(ArpeggiatorUpdate),
+    /// Stops every voice and clears the effect tails
+    Panic,
+    /// Where generated notes and the clock are sent
+    MidiOutput(Sender<midi::Input>),
 }
 
 struct AudioState {
     sample_rate: usize,
     table: ModTable,
+    player: Player,
+    midi_output: Option<Sender<midi::Input>>,
 }
 
 impl AudioState {
@@ -32,6 +53,8 @@ impl AudioState {
         Self {
             sample_rate: Self::DEFAULT_SAMPLE_RATE,
             table: ModTable::new(),
+            player: Player::new(),
+            midi_output: None,
         }
     }
 
@@ -43,12 +66,36 @@ impl AudioState {
                 Input::ModuleMessage(id, msg) => {
                     self.table.update(id, msg);
                 }
+                Input::ModuleMessages(messages) => {
+                    for (id, msg) in messages {
+                        self.table.update(id, msg);
+                    }
+                }
+                Input::Player(command) => {
+                    let table = &mut self.table;
+                    self.player.update(command, |update| table.update(0, ModuleMessage::ComponentChange(ModuleMessageUnion {midi: update})));
+                }
+                Input::Arpeggiator(update) => self.table.update_arpeggiator(update),
+                Input::Panic => self.table.panic(),
+                Input::MidiOutput(sender) => self.midi_output = Some(sender),
+            }
+        }
+    }
+
+    /// Empties the table's outgoing MIDI even without an output to send it to
+    fn send_midi_output(&mut self) {
+        let messages = self.table.midi_output();
+        if let Some(sender) = &mut self.midi_output {
+            for message in messages {
+                let _ = sender.try_send(midi::Input::Send(message));
             }
         }
     }
 
-    fn render(&mut self, mut receiver: Receiver<Input>) {
+    fn render(&mut self, mut receiver: Receiver<Input>, mut output: Sender<Message>) {
         const BUFFER_SIZE: usize = 128;
+        // Roughly 10 times a second
+        const POSITION_INTERVAL: usize = 40;
 
         match audio_thread_priority::promote_current_thread_to_real_time(0, 48000) {
             Ok(_) => println!("Upgraded thread to real time"),
@@ -65,17 +112,22 @@ impl AudioState {
         println!("{buffer_time:?}");
 
         let mut dt = Instant::now();
+        let mut buffers_rendered = 0;
 
         loop {
             let mut buffer = Vec::with_capacity(BUFFER_SIZE);
 
             for _ in 0..BUFFER_SIZE {
+                let table = &mut self.table;
+                self.player.process(self.sample_rate, |update| table.update(0, ModuleMessage::ComponentChange(ModuleMessageUnion {midi: update})));
+
                 let (sample1, sample2) = self.table.process();
                 buffer.push(sample1 * 0.1);
                 buffer.push(sample2 * 0.1);
             }
 
             //println!("{:?}", dt.elapsed());
+            self.send_midi_output();
 
             while dt.elapsed() < buffer_time_messages {
                 self.update(&mut receiver);
@@ -86,6 +138,11 @@ impl AudioState {
 
             sink.append(audio_buf);
 
+            buffers_rendered += 1;
+            if buffers_rendered % POSITION_INTERVAL == 0 {
+                let _ = output.try_send(Message::PlayerPosition(self.player.position()));
+            }
+
             dt = Instant::now();
         }
     }
@@ -100,6 +157,6 @@ pub fn render_audio() -> impl Stream<Item = Message> {
 
         let mut state = AudioState::new();
 
-        state.render(receiver);
+        state.render(receiver, output);
     })
 }
\ No newline at end of file
@@ -0,0 +1,245 @@
+use std::f32::consts::TAU;
+
+use crate::audio::module::{Module, ModuleMessage};
+
+pub const MAX_PARTIALS: usize = 256;
+const SINE_TABLE_SIZE: usize = 4096;
+/// Partials fade out over the top of the band instead of dropping out with a click
+const NYQUIST_FADE: f32 = 0.1;
+
+/// Harmonic amplitudes the shaping starts from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Spectrum {
+    Sine,
+    Saw,
+    Square,
+    Triangle,
+    /// Drawbar style, the fundamental with octaves and fifths above it
+    Organ,
+    /// Amplitudes set one partial at a time
+    Custom,
+}
+
+impl Spectrum {
+    pub const ALL: [Spectrum; 6] = [
+        Spectrum::Sine,
+        Spectrum::Saw,
+        Spectrum::Square,
+        Spectrum::Triangle,
+        Spectrum::Organ,
+        Spectrum::Custom,
+    ];
+
+    /// Amplitude of the `harmonic`th partial, counting the fundamental as 1
+    fn amplitude(self, harmonic: usize) -> f32 {
+        let n = harmonic as f32;
+        let odd = harmonic % 2 == 1;
+        match self {
+            Spectrum::Sine => if harmonic == 1 {1.0} else {0.0},
+            Spectrum::Saw => 1.0 / n,
+            Spectrum::Square => if odd {1.0 / n} else {0.0},
+            Spectrum::Triangle => if odd {(if harmonic % 4 == 1 {1.0} else {-1.0}) / (n * n)} else {0.0},
+            Spectrum::Organ => match harmonic {
+                1 => 1.0,
+                2 => 0.8,
+                3 | 4 => 0.6,
+                6 | 8 => 0.4,
+                _ => 0.0,
+            },
+            Spectrum::Custom => 0.0,
+        }
+    }
+}
+
+impl std::fmt::Display for Spectrum {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Spectrum::Sine => write!(f, "Sine spectrum"),
+            Spectrum::Saw => write!(f, "Saw spectrum"),
+            Spectrum::Square => write!(f, "Square spectrum"),
+            Spectrum::Triangle => write!(f, "Triangle spectrum"),
+            Spectrum::Organ => write!(f, "Organ spectrum"),
+            Spectrum::Custom => write!(f, "Custom spectrum"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum AdditiveUpdate {
+    /// How many partials are summed, at most `MAX_PARTIALS`
+    Partials(usize),
+    Spectrum(Spectrum),
+    /// Amplitude of one partial of the custom spectrum, counting from 0 for the fundamental
+    Partial(usize, f32),
+    /// Tilts the spectrum, each partial is scaled by its number to this power
+    Brightness(f32),
+    /// From only odd partials at -1 to only even ones and the fundamental at 1
+    OddEven(f32),
+    /// Raises each partial's number to `1 + stretch`, moving them off the harmonic series
+    Stretch(f32),
+    Gain(f32),
+}
+
+#[derive(Default)]
+struct Inputs {
+    level: f32,
+    frequency: f32,
+}
+
+#[derive(Default)]
+struct Outputs {
+    value: f32,
+}
+
+/// A sum of sine partials, shaped from a harmonic spectrum
+pub struct Additive {
+    id: usize,
+    sample_rate: usize,
+    partial_count: usize,
+    spectrum: Spectrum,
+    custom: [f32; MAX_PARTIALS],
+    brightness: f32,
+    odd_even: f32,
+    stretch: f32,
+    gain: f32,
+    input: Inputs,
+    output: Outputs,
+    // State
+    /// Frequency ratio and amplitude of each partial, worked out when the settings change
+    ratios: [f32; MAX_PARTIALS],
+    amplitudes: [f32; MAX_PARTIALS],
+    phases: [f32; MAX_PARTIALS],
+    sine: Vec<f32>,
+}
+
+impl Additive {
+    pub fn new(id: usize, sample_rate: usize) -> Self {
+        let mut custom = [0.0; MAX_PARTIALS];
+        custom[0] = 1.0;
+
+        let mut additive = Self {
+            id,
+            sample_rate,
+            partial_count: 64,
+            spectrum: Spectrum::Saw,
+            custom,
+            brightness: 0.0,
+            odd_even: 0.0,
+            stretch: 0.0,
+            gain: 0.0,
+            input: Inputs::default(),
+            output: Outputs::default(),
+            ratios: [0.0; MAX_PARTIALS],
+            amplitudes: [0.0; MAX_PARTIALS],
+            phases: [0.0; MAX_PARTIALS],
+            // One extra point so interpolation never wraps
+            sine: (0..=SINE_TABLE_SIZE).map(|i| (TAU * i as f32 / SINE_TABLE_SIZE as f32).sin()).collect(),
+        };
+        additive.shape();
+        additive
+    }
+
+    fn shape(&mut self) {
+        let odd_gain = (1.0 - self.odd_even).min(1.0);
+        let even_gain = (1.0 + self.odd_even).min(1.0);
+        // The fundamental counts as odd but stays in so the pitch doesn't jump an octave
+        let odd_gain = |harmonic: usize| if harmonic == 1 {1.0} else {odd_gain};
+
+        for index in 0..MAX_PARTIALS {
+            let harmonic = index + 1;
+            let n = harmonic as f32;
+
+            let amplitude = match self.spectrum {
+                Spectrum::Custom => self.custom[index],
+                spectrum => spectrum.amplitude(harmonic),
+            };
+            let balance = if harmonic % 2 == 1 {odd_gain(harmonic)} else {even_gain};
+
+            self.ratios[index] = n.powf(1.0 + self.stretch);
+            self.amplitudes[index] = if index < self.partial_count {amplitude * n.powf(self.brightness) * balance} else {0.0};
+        }
+
+        // Scaled so the partials can't add up past full level
+        let total: f32 = self.amplitudes.iter().map(|amplitude| amplitude.abs()).sum();
+        if total > 0.0 {
+            self.amplitudes.iter_mut().for_each(|amplitude| *amplitude /= total);
+        }
+    }
+}
+
+impl Module for Additive {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn process(&mut self) {
+        let level = self.input.level.clamp(0.0, 1.0) * self.gain;
+        if level == 0.0 {
+            self.output.value = 0.0;
+            return;
+        }
+
+        let frequency = self.input.frequency.clamp(0.0, 1.0);
+        let frequency = 2.0_f32.powf(127.0 / 12.0 * frequency) * 8.176; // C-1 (midi note 0)
+        let nyquist = self.sample_rate as f32 / 2.0;
+        let fade_start = nyquist * (1.0 - NYQUIST_FADE);
+
+        let mut value = 0.0;
+        for index in 0..self.partial_count {
+            // Stretching keeps the partials in order, so everything after the first one past Nyquist is too
+            let partial_frequency = frequency * self.ratios[index];
+            if partial_frequency >= nyquist {
+                break;
+            }
+
+            let amplitude = self.amplitudes[index];
+            if amplitude != 0.0 {
+                let fade = ((nyquist - partial_frequency) / (nyquist - fade_start)).min(1.0);
+                let position = self.phases[index] * SINE_TABLE_SIZE as f32;
+                let i = position as usize;
+                let t = position - i as f32;
+                let sine = self.sine[i] + (self.sine[i + 1] - self.sine[i]) * t;
+                value += sine * amplitude * fade;
+            }
+
+            self.phases[index] = (self.phases[index] + partial_frequency / self.sample_rate as f32) % 1.0;
+        }
+
+        self.output.value = value * level;
+    }
+
+    fn update(&mut self, msg: ModuleMessage) {
+        match msg {
+            ModuleMessage::ComponentChange(msg_union) => {
+                match unsafe {msg_union.additive} {
+                    AdditiveUpdate::Partials(count) => self.partial_count = count.clamp(1, MAX_PARTIALS),
+                    AdditiveUpdate::Spectrum(spectrum) => self.spectrum = spectrum,
+                    AdditiveUpdate::Partial(index, amplitude) => if let Some(partial) = self.custom.get_mut(index) {
+                        *partial = amplitude;
+                    },
+                    AdditiveUpdate::Brightness(brightness) => self.brightness = brightness,
+                    AdditiveUpdate::OddEven(balance) => self.odd_even = balance,
+                    AdditiveUpdate::Stretch(stretch) => self.stretch = stretch,
+                    AdditiveUpdate::Gain(gain) => self.gain = gain,
+                }
+                self.shape();
+            },
+            _ => (),
+        }
+    }
+
+    fn get_output(&self, target_output: usize) -> f32 {
+        match target_output {
+            0 => self.output.value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn modulate(&mut self, component: usize, value: f32) {
+        match component {
+            0 => self.input.level = value,
+            1 => self.input.frequency = value,
+            _ => unreachable!(),
+        }
+    }
+}
@@ -63,7 +63,9 @@ impl Module for Allpass {
             ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.allpass} {
                 AllpassUpdate::Gain(gain) => self.gain = gain,
                 AllpassUpdate::DelayTime(delay_time) => self.delay_time = delay_time,
-            }
+            },
+            ModuleMessage::Reset => self.buffer.iter_mut().for_each(|sample| *sample = (0.0, 0.0)),
+            _ => (),
         }
     }
 
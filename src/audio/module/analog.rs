@@ -1,6 +1,11 @@
 use crate::audio::module::{Module, ModuleMessage};
 
-#[derive(Clone, Copy, Debug)]
+/// Keeps both parts of the pulse audible
+const MIN_PULSE_WIDTH: f32 = 0.02;
+/// Linear FM input of 1.0 at full depth moves the frequency by this many Hz
+const FM_HZ_PER_UNIT: f32 = 1000.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum WaveShape {
     Saw,
     Sine,
@@ -8,12 +13,70 @@ pub enum WaveShape {
     Triangle,
 }
 
+impl WaveShape {
+    pub const ALL: [WaveShape; 4] = [WaveShape::Saw, WaveShape::Sine, WaveShape::Square, WaveShape::Triangle];
+
+    fn naive(self, phase: f32, width: f32) -> f32 {
+        let phase = phase.rem_euclid(1.0);
+        match self {
+            WaveShape::Saw => 2.0 * phase - 1.0,
+            WaveShape::Sine => (2.0 * std::f32::consts::PI * phase).sin(),
+            WaveShape::Square => if phase < width {1.0} else {-1.0},
+            WaveShape::Triangle => 1.0 - 4.0 * (phase - (phase + 0.5).floor()).abs(),
+        }
+    }
+
+    /// One sample with the saw and square edges smoothed, `width` is only used by the square.
+    /// The corrections only depend on how far the phase is from an edge, so they hold for a cycle running backwards too
+    pub fn band_limited(self, phase: f32, phase_increment: f32, width: f32) -> f32 {
+        self.naive(phase, width) + match self {
+            WaveShape::Saw => -poly_blep(phase, phase_increment),
+            // The falling edge moves with the width, so its correction does too
+            WaveShape::Square => poly_blep(phase, phase_increment) - poly_blep((phase + 1.0 - width) % 1.0, phase_increment),
+            WaveShape::Sine | WaveShape::Triangle => 0.0,
+        }
+    }
+}
+
+impl std::fmt::Display for WaveShape {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WaveShape::Saw => write!(f, "Saw"),
+            WaveShape::Sine => write!(f, "Sine"),
+            WaveShape::Square => write!(f, "Square"),
+            WaveShape::Triangle => write!(f, "Triangle"),
+        }
+    }
+}
+
+fn poly_blep(phase: f32, phase_increment: f32) -> f32 {
+    if phase < phase_increment {
+        let t = phase / phase_increment;
+        t+t - t*t - 1.0
+    } else if phase > 1.0 - phase_increment {
+        let t = (phase - 1.0) / phase_increment;
+        t*t + t+t + 1.0
+    } else {
+        0.0
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum AnalogOscillatorUpdate {
     SampleRate(usize),
+    /// Level before the level input is added, the oscillator does no work while the sum is 0
+    Level(f32),
     Frequency(f32),
     Phase(f32),
     Shape(WaveShape),
+    /// Part of the square cycle spent high, 0.5 is a square wave
+    PulseWidth(f32),
+    /// How far the pulse width input moves the width
+    PwmDepth(f32),
+    /// Restarts the cycle whenever the sync input rises through zero
+    Sync(bool),
+    /// How far the FM input moves the frequency
+    FmDepth(f32),
 }
 
 #[derive(Default)]
@@ -21,6 +84,9 @@ struct Inputs {
     level: f32,
     frequency: f32,
     phase: f32,
+    pulse_width: f32,
+    sync: f32,
+    fm: f32,
 }
 
 #[derive(Default)]
@@ -35,7 +101,14 @@ pub struct AnalogOscillator {
     level: f32,
     frequency: f32,
     phase: f32,
+    pulse_width: f32,
+    pwm_depth: f32,
+    sync: bool,
+    fm_depth: f32,
     current_phase: f32,
+    last_sync: f32,
+    /// While sync is on, output runs a sample behind so a sync reset can correct the sample before it
+    held: f32,
     input: Inputs,
     output: Outputs,
 }
@@ -50,21 +123,20 @@ impl AnalogOscillator {
             frequency: 0.0,
             shape: WaveShape::Saw,
             phase: 0.0,
+            pulse_width: 0.5,
+            pwm_depth: 0.0,
+            sync: false,
+            fm_depth: 0.0,
+            last_sync: 0.0,
+            held: 0.0,
             input: Inputs::default(),
             output: Outputs::default(),
         }
     }
 
-    fn poly_blep(phase: f32, phase_increment: f32) -> f32 {
-        if phase < phase_increment {
-            let t = phase / phase_increment;
-            t+t - t*t - 1.0
-        } else if phase > 1.0 - phase_increment {
-            let t = (phase - 1.0) / phase_increment;
-            t*t + t+t + 1.0
-        } else {
-            0.0
-        }
+    pub fn level(mut self, level: f32) -> Self {
+        self.level = level;
+        self
     }
 }
 
@@ -74,41 +146,75 @@ impl Module for AnalogOscillator {
     }
 
     fn process(&mut self) {
-        let phase_input = self.input.phase;
-        let frequency_input = self.input.frequency;
-        let level_input = self.input.level;
+        let level = (self.level + self.input.level).min(1.0).max(0.0);
+        if level == 0.0 {
+            self.last_sync = self.input.sync;
+            self.held = 0.0;
+            self.output.value = 0.0;
+            return;
+        }
 
-        let level = (self.level + level_input).min(1.0).max(0.0);
-        let frequency = (self.frequency + frequency_input).min(1.0).max(0.0);
+        let frequency = (self.frequency + self.input.frequency).min(1.0).max(0.0);
         let frequency = 2.0_f32.powf(127.0 / 12.0 * frequency) * 8.176; // C-1 (midi note 0)
-        let phase = (self.current_phase + phase_input) % 1.0;
+        let frequency = frequency + self.input.fm * self.fm_depth * FM_HZ_PER_UNIT;
 
+        // Through-zero FM makes the increment negative and runs the cycle backwards
         let phase_increment = frequency / self.sample_rate as f32;
+        let blep_increment = phase_increment.abs();
+        let offset = self.phase + self.input.phase;
 
-        let raw = match self.shape {
-            WaveShape::Saw => 2.0 * phase - 1.0 - AnalogOscillator::poly_blep(phase, phase_increment),
-            WaveShape::Sine => (2.0 * std::f32::consts::PI * phase).sin(),
-            WaveShape::Square => {
-                let raw = if phase < 0.5 {1.0} else {-1.0};
-                raw + AnalogOscillator::poly_blep(phase, phase_increment) - AnalogOscillator::poly_blep((phase + 0.5) % 1.0, phase_increment)
-            },
-            WaveShape::Triangle => 1.0 - 4.0 * (phase - (phase + 0.5).floor()).abs(),
+        let min_width = MIN_PULSE_WIDTH.max(blep_increment).min(0.5);
+        let width = (self.pulse_width + self.input.pulse_width * self.pwm_depth).clamp(min_width, 1.0 - min_width);
+
+        // Hard sync restarts the cycle where the sync input crossed zero, the jump is smoothed over the samples either side
+        let mut held_correction = 0.0;
+        let mut correction = 0.0;
+        let reset = self.sync && self.last_sync <= 0.0 && self.input.sync > 0.0;
+        if reset {
+            let t = self.input.sync / (self.input.sync - self.last_sync);
+            let before = self.current_phase - phase_increment * t;
+            self.current_phase = (phase_increment * t).rem_euclid(1.0);
+
+            let jump = (self.shape.naive(offset, width) - self.shape.naive(before + offset, width)) / 2.0;
+            held_correction = jump * t*t;
+            correction = jump * (t+t - t*t - 1.0);
+        }
+        self.last_sync = self.input.sync;
+
+        let phase = (self.current_phase + offset).rem_euclid(1.0);
+        // Just after a reset the phase sits next to the cycle's start without having wrapped there, `jump` covers that edge
+        let raw = if reset {
+            self.shape.naive(phase, width)
+        } else {
+            self.shape.band_limited(phase, blep_increment, width)
         };
 
-        self.current_phase = (self.current_phase + phase_increment) % 1.0;
+        self.current_phase = (self.current_phase + phase_increment).rem_euclid(1.0);
 
-        let scaled_raw = raw * level;
-        self.output.value = f32::from(scaled_raw);
+        if self.sync {
+            self.output.value = self.held + held_correction * level;
+            self.held = (raw + correction) * level;
+        } else {
+            // Kept up to date so turning sync on repeats a sample rather than jumping back to an old one
+            self.output.value = raw * level;
+            self.held = self.output.value;
+        }
     }
 
     fn update(&mut self, msg: ModuleMessage) {
         match msg {
             ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.analog} {
                 AnalogOscillatorUpdate::SampleRate(sample_rate) => self.sample_rate = sample_rate,
+                AnalogOscillatorUpdate::Level(level) => self.level = level,
                 AnalogOscillatorUpdate::Frequency(frequency) => self.frequency = frequency,
                 AnalogOscillatorUpdate::Phase(phase) => self.phase = phase,
                 AnalogOscillatorUpdate::Shape(shape) => self.shape = shape,
-            }
+                AnalogOscillatorUpdate::PulseWidth(width) => self.pulse_width = width,
+                AnalogOscillatorUpdate::PwmDepth(depth) => self.pwm_depth = depth,
+                AnalogOscillatorUpdate::Sync(sync) => self.sync = sync,
+                AnalogOscillatorUpdate::FmDepth(depth) => self.fm_depth = depth,
+            },
+            _ => (),
         }
     }
 
@@ -124,7 +230,50 @@ impl Module for AnalogOscillator {
             0 => self.input.level = value,
             1 => self.input.frequency = value,
             2 => self.input.phase = value,
+            3 => self.input.pulse_width = value,
+            4 => self.input.sync = value,
+            5 => self.input.fm = value,
             _ => unreachable!(),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::module::ModuleMessageUnion;
+
+    /// A sine starting at its peak, so the first sample shows whether the output is held back
+    fn sine(updates: &[AnalogOscillatorUpdate]) -> AnalogOscillator {
+        let mut oscillator = AnalogOscillator::new(0, 48000).level(1.0);
+        for &update in [AnalogOscillatorUpdate::Shape(WaveShape::Sine), AnalogOscillatorUpdate::Phase(0.25)].iter().chain(updates) {
+            oscillator.update(ModuleMessage::ComponentChange(ModuleMessageUnion { analog: update }));
+        }
+        oscillator
+    }
+
+    fn run(oscillator: &mut AnalogOscillator, len: usize) -> Vec<f32> {
+        (0..len).map(|_| {
+            oscillator.process();
+            oscillator.get_output(0)
+        }).collect()
+    }
+
+    #[test]
+    fn output_is_only_held_back_while_synced() {
+        let free = run(&mut sine(&[]), 3);
+        assert!((free[0] - 1.0).abs() < 1e-3);
+
+        let synced = run(&mut sine(&[AnalogOscillatorUpdate::Sync(true)]), 3);
+        assert_eq!(synced[0], 0.0);
+        assert_eq!(synced[1..], free[..2]);
+    }
+
+    #[test]
+    fn silent_at_level_zero() {
+        let mut oscillator = sine(&[AnalogOscillatorUpdate::Level(0.0)]);
+        assert_eq!(run(&mut oscillator, 3), [0.0; 3]);
+
+        oscillator.modulate(0, 0.5);
+        assert!(run(&mut oscillator, 1)[0] > 0.0);
+    }
+}
@@ -49,6 +49,14 @@ impl Butterworth {
         self.frequency = freq.log2() / 14.55;
         self
     }
+
+    fn reset(&mut self) {
+        self.x_minus = 0.0;
+        self.x_minus2 = 0.0;
+        self.y_minus = 0.0;
+        self.y_minus2 = 0.0;
+        self.output.value = 0.0;
+    }
 }
 
 impl Module for Butterworth {
@@ -90,7 +98,9 @@ impl Module for Butterworth {
             ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.butterworth} {
                 ButterworthUpdate::Frequency(frequency) => self.frequency = frequency,
                 ButterworthUpdate::SampleRate(sample_rate) => self.sample_rate = sample_rate,
-            }
+            },
+            ModuleMessage::Reset => self.reset(),
+            _ => (),
         }
     }
 
@@ -82,7 +82,9 @@ impl Module for Chorus {
                         self.ratio = 0.5;
                     }
                 },
-            }
+            },
+            ModuleMessage::Reset => self.buffer.iter_mut().for_each(|sample| *sample = 0.0),
+            _ => (),
         }
     }
 
@@ -0,0 +1,315 @@
+use std::fmt;
+
+use crate::audio::module::{Module, ModuleMessage};
+
+const TICKS_PER_BEAT: f64 = 24.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockSource {
+    Internal,
+    External,
+}
+
+impl ClockSource {
+    pub const ALL: [ClockSource; 2] = [ClockSource::Internal, ClockSource::External];
+}
+
+impl fmt::Display for ClockSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClockSource::Internal => write!(f, "Internal clock"),
+            ClockSource::External => write!(f, "MIDI clock"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Division {
+    Whole,
+    Half,
+    Quarter,
+    Eighth,
+    Sixteenth,
+    ThirtySecond,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feel {
+    Straight,
+    Dotted,
+    Triplet,
+}
+
+/// A note length relative to the tempo
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteDivision {
+    pub division: Division,
+    pub feel: Feel,
+}
+
+impl NoteDivision {
+    pub fn new(division: Division, feel: Feel) -> Self {
+        Self {
+            division,
+            feel,
+        }
+    }
+
+    pub fn all() -> Vec<NoteDivision> {
+        let divisions = [Division::Whole, Division::Half, Division::Quarter, Division::Eighth, Division::Sixteenth, Division::ThirtySecond];
+        let feels = [Feel::Straight, Feel::Dotted, Feel::Triplet];
+
+        divisions.into_iter()
+            .flat_map(|division| feels.into_iter().map(move |feel| NoteDivision::new(division, feel)))
+            .collect()
+    }
+
+    /// Length in quarter notes
+    pub fn beats(&self) -> f32 {
+        let straight = match self.division {
+            Division::Whole => 4.0,
+            Division::Half => 2.0,
+            Division::Quarter => 1.0,
+            Division::Eighth => 0.5,
+            Division::Sixteenth => 0.25,
+            Division::ThirtySecond => 0.125,
+        };
+
+        match self.feel {
+            Feel::Straight => straight,
+            Feel::Dotted => straight * 1.5,
+            Feel::Triplet => straight * 2.0 / 3.0,
+        }
+    }
+
+    /// Length in seconds at the given tempo
+    pub fn seconds(&self, bpm: f32) -> f32 {
+        self.beats() * 60.0 / bpm
+    }
+}
+
+impl fmt::Display for NoteDivision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let division = match self.division {
+            Division::Whole => "1/1",
+            Division::Half => "1/2",
+            Division::Quarter => "1/4",
+            Division::Eighth => "1/8",
+            Division::Sixteenth => "1/16",
+            Division::ThirtySecond => "1/32",
+        };
+
+        match self.feel {
+            Feel::Straight => write!(f, "{division}"),
+            Feel::Dotted => write!(f, "{division} dotted"),
+            Feel::Triplet => write!(f, "{division} triplet"),
+        }
+    }
+}
+
+/// Whether a time based module follows the tempo input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TempoSync {
+    Off,
+    On(NoteDivision),
+}
+
+impl TempoSync {
+    pub fn all() -> Vec<TempoSync> {
+        std::iter::once(TempoSync::Off)
+            .chain(NoteDivision::all().into_iter().map(TempoSync::On))
+            .collect()
+    }
+
+    /// Length in seconds if synced and the tempo is known
+    pub fn seconds(&self, bpm: f32) -> Option<f32> {
+        match self {
+            TempoSync::On(division) if bpm > 0.0 => Some(division.seconds(bpm)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TempoSync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TempoSync::Off => write!(f, "Free"),
+            TempoSync::On(division) => write!(f, "{division}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ClockUpdate {
+    SampleRate(usize),
+    Source(ClockSource),
+    Bpm(f32),
+    BeatsPerBar(u8),
+    /// MIDI clock tick with its timestamp in microseconds
+    Tick(u64),
+    Start,
+    Continue,
+    Stop,
+    SongPosition(u16),
+}
+
+#[derive(Default)]
+struct Outputs {
+    beat_trigger: f32,
+    bar_trigger: f32,
+    beat_phase: f32,
+    bar_phase: f32,
+    bpm: f32,
+    running: f32,
+    position: f32,
+}
+
+/// Transport and tempo source, either free running at its own tempo or following MIDI clock
+pub struct Clock {
+    id: usize,
+    sample_rate: usize,
+    source: ClockSource,
+    bpm: f32,
+    beats_per_bar: u8,
+    output: Outputs,
+    // State
+    running: bool,
+    position: f64,
+    last_beat: i64,
+    ticks: u64,
+    last_stamp: Option<u64>,
+    external_bpm: f32,
+}
+
+impl Clock {
+    pub fn new(id: usize, sample_rate: usize) -> Self {
+        Self {
+            id,
+            sample_rate,
+            source: ClockSource::Internal,
+            bpm: 120.0,
+            beats_per_bar: 4,
+            output: Outputs::default(),
+            running: true,
+            position: 0.0,
+            last_beat: -1,
+            ticks: 0,
+            last_stamp: None,
+            external_bpm: 0.0,
+        }
+    }
+
+    fn tempo(&self) -> f32 {
+        match self.source {
+            ClockSource::Internal => self.bpm,
+            ClockSource::External => self.external_bpm,
+        }
+    }
+
+    fn tick(&mut self, stamp: u64) {
+        if let Some(last_stamp) = self.last_stamp {
+            let delta = stamp.saturating_sub(last_stamp);
+            // Ignore gaps longer than a tick at 10 bpm
+            if delta > 0 && delta < 250_000 {
+                let bpm = 60_000_000.0 / (delta as f32 * TICKS_PER_BEAT as f32);
+                if self.external_bpm == 0.0 {
+                    self.external_bpm = bpm;
+                } else {
+                    self.external_bpm += (bpm - self.external_bpm) * 0.05;
+                }
+            }
+        }
+        self.last_stamp = Some(stamp);
+
+        if self.source == ClockSource::External && self.running {
+            self.ticks += 1;
+            self.position = self.position.max(self.ticks as f64 / TICKS_PER_BEAT);
+        }
+    }
+
+    fn start(&mut self) {
+        self.running = true;
+        self.ticks = 0;
+        self.position = 0.0;
+        self.last_beat = -1;
+    }
+}
+
+impl Module for Clock {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn process(&mut self) {
+        let bpm = self.tempo();
+
+        if self.running {
+            let increment = bpm as f64 / 60.0 / self.sample_rate as f64;
+            self.position = match self.source {
+                ClockSource::Internal => self.position + increment,
+                // Interpolate between ticks without running ahead of the next one
+                ClockSource::External => (self.position + increment).min((self.ticks + 1) as f64 / TICKS_PER_BEAT),
+            };
+        }
+
+        let beat = self.position.floor() as i64;
+        let beats_per_bar = self.beats_per_bar.max(1) as f64;
+
+        self.output.beat_trigger = 0.0;
+        self.output.bar_trigger = 0.0;
+        if self.running && beat != self.last_beat {
+            self.output.beat_trigger = 1.0;
+            if beat % beats_per_bar as i64 == 0 {
+                self.output.bar_trigger = 1.0;
+            }
+            self.last_beat = beat;
+        }
+
+        self.output.beat_phase = self.position.fract() as f32;
+        self.output.bar_phase = (self.position / beats_per_bar).fract() as f32;
+        self.output.bpm = bpm;
+        self.output.running = if self.running {1.0} else {0.0};
+        self.output.position = self.position as f32;
+    }
+
+    fn update(&mut self, msg: ModuleMessage) {
+        match msg {
+            ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.clock} {
+                ClockUpdate::SampleRate(sample_rate) => self.sample_rate = sample_rate,
+                ClockUpdate::Source(source) => {
+                    self.source = source;
+                    self.ticks = (self.position * TICKS_PER_BEAT) as u64;
+                },
+                ClockUpdate::Bpm(bpm) => self.bpm = bpm.max(1.0),
+                ClockUpdate::BeatsPerBar(beats_per_bar) => self.beats_per_bar = beats_per_bar,
+                ClockUpdate::Tick(stamp) => self.tick(stamp),
+                ClockUpdate::Start => self.start(),
+                ClockUpdate::Continue => self.running = true,
+                ClockUpdate::Stop => self.running = false,
+                ClockUpdate::SongPosition(sixteenths) => {
+                    self.ticks = sixteenths as u64 * 6;
+                    self.position = self.ticks as f64 / TICKS_PER_BEAT;
+                    self.last_beat = self.position.floor() as i64 - 1;
+                },
+            },
+            _ => (),
+        }
+    }
+
+    fn get_output(&self, target_output: usize) -> f32 {
+        match target_output {
+            0 => self.output.beat_trigger,
+            1 => self.output.bar_trigger,
+            2 => self.output.beat_phase,
+            3 => self.output.bar_phase,
+            4 => self.output.bpm,
+            5 => self.output.running,
+            6 => self.output.position,
+            _ => unreachable!(),
+        }
+    }
+
+    fn modulate(&mut self, _component: usize, _value: f32) {
+        unreachable!()
+    }
+}
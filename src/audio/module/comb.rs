@@ -67,7 +67,9 @@ impl Module for Comb {
             ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.comb} {
                 CombUpdate::DelayTime(delay_time) => self.delay_time = delay_time,
                 CombUpdate::Gain(gain) => self.gain = gain,
-            }
+            },
+            ModuleMessage::Reset => self.buffer.iter_mut().for_each(|sample| *sample = 0.0),
+            _ => (),
         }
     }
 
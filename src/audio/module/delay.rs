@@ -1,6 +1,7 @@
 use std::collections::VecDeque;
 
 use crate::audio::module::{Module, ModuleMessage};
+use crate::audio::module::clock::TempoSync;
 
 const MAX_DELAY_SAMPLES: usize = 48000 * 6;
 
@@ -8,11 +9,13 @@ const MAX_DELAY_SAMPLES: usize = 48000 * 6;
 pub enum DelayUpdate {
     Time(f32),
     Ratio(f32),
+    Sync(TempoSync),
 }
 
 #[derive(Default)]
 struct Inputs {
     value: f32,
+    tempo: f32,
 }
 
 #[derive(Default)]
@@ -25,6 +28,7 @@ pub struct Delay {
     sample_rate: usize,
     ratio: f32,
     time: f32,
+    sync: TempoSync,
     input: Inputs,
     output: Outputs,
     // State
@@ -38,6 +42,7 @@ impl Delay {
             sample_rate,
             ratio: 0.0,
             time: 0.0,
+            sync: TempoSync::Off,
             input: Inputs::default(),
             output: Outputs::default(),
             buffer: VecDeque::with_capacity(MAX_DELAY_SAMPLES),
@@ -51,7 +56,11 @@ impl Module for Delay {
     }
 
     fn process(&mut self) {
-        let delay_index = (self.time * self.sample_rate as f32) as usize;
+        let time = match self.sync.seconds(self.input.tempo) {
+            Some(time) => time.min(MAX_DELAY_SAMPLES as f32 / self.sample_rate as f32),
+            None => self.time,
+        };
+        let delay_index = (time * self.sample_rate as f32) as usize;
 
         let delay_amp = self.buffer.remove(delay_index);
 
@@ -80,7 +89,16 @@ impl Module for Delay {
                         self.ratio = 0.4;
                     }
                 },
-            }
+                DelayUpdate::Sync(sync) => {
+                    self.sync = sync;
+
+                    if sync != TempoSync::Off && self.ratio == 0.0 {
+                        self.ratio = 0.4;
+                    }
+                },
+            },
+            ModuleMessage::Reset => self.buffer.iter_mut().for_each(|sample| *sample = 0.0),
+            _ => (),
         }
     }
 
@@ -94,6 +112,7 @@ impl Module for Delay {
     fn modulate(&mut self, component: usize, value: f32) {
         match component {
             0 => self.input.value += value,
+            1 => self.input.tempo = value,
             _ => unreachable!(),
         }
     }
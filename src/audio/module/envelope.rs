@@ -53,6 +53,14 @@ impl Envelope {
             output: Outputs::default(),
         }
     }
+
+    /// Drops straight to silence instead of releasing
+    fn reset(&mut self) {
+        self.start = None;
+        self.released = None;
+        self.release_start_value = 0.0;
+        self.output.value = 0.0;
+    }
 }
 
 impl Module for Envelope {
@@ -121,7 +129,9 @@ impl Module for Envelope {
                 EnvelopeUpdate::Decay(decay) => self.decay = decay,
                 EnvelopeUpdate::Release(release) => self.release = release,
                 EnvelopeUpdate::Sustain(sustain) => self.sustain = sustain,
-            }
+            },
+            ModuleMessage::Reset => self.reset(),
+            _ => (),
         }
     }
 }
\ No newline at end of file
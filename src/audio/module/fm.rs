@@ -0,0 +1,255 @@
+use std::f32::consts::{PI, TAU};
+
+use crate::audio::module::{Module, ModuleMessage, ModuleMessageUnion};
+use crate::audio::module::envelope::{Envelope, EnvelopeUpdate};
+
+pub const OPERATOR_COUNT: usize = 4;
+/// Radians a modulator at full level pushes the phase of the operator it feeds
+const MODULATION_INDEX: f32 = 4.0;
+
+/// Which operators feed which, operators only ever modulate lower numbered ones
+pub struct Algorithm {
+    pub name: &'static str,
+    /// For each operator, a bit for every operator that modulates it
+    modulators: [u8; OPERATOR_COUNT],
+    /// A bit for every operator that is heard
+    carriers: u8,
+}
+
+pub const ALGORITHMS: [Algorithm; 8] = [
+    Algorithm { name: "4 > 3 > 2 > 1", modulators: [0b0010, 0b0100, 0b1000, 0], carriers: 0b0001 },
+    Algorithm { name: "3 + 4 > 2 > 1", modulators: [0b0010, 0b1100, 0, 0], carriers: 0b0001 },
+    Algorithm { name: "2 + (4 > 3) > 1", modulators: [0b0110, 0, 0b1000, 0], carriers: 0b0001 },
+    Algorithm { name: "(3 > 2) + 4 > 1", modulators: [0b1010, 0b0100, 0, 0], carriers: 0b0001 },
+    Algorithm { name: "2 > 1, 4 > 3", modulators: [0b0010, 0, 0b1000, 0], carriers: 0b0101 },
+    Algorithm { name: "4 > 1, 2, 3", modulators: [0b1000, 0b1000, 0b1000, 0], carriers: 0b0111 },
+    Algorithm { name: "4 > 3, 2, 1", modulators: [0, 0, 0b1000, 0], carriers: 0b0111 },
+    Algorithm { name: "1, 2, 3, 4", modulators: [0, 0, 0, 0], carriers: 0b1111 },
+];
+
+#[derive(Clone, Copy, Debug)]
+pub enum FmUpdate {
+    Algorithm(usize),
+    /// Multiple of the note's frequency the operator plays at
+    Ratio(usize, f32),
+    /// Plays at the fixed frequency whatever the note
+    Fixed(usize, bool),
+    FixedFrequency(usize, f32),
+    Level(usize, f32),
+    /// How much of its own output the operator feeds back into its phase
+    Feedback(usize, f32),
+    Envelope(usize, EnvelopeUpdate),
+    Gain(f32),
+}
+
+#[derive(Default)]
+struct Inputs {
+    gate: f32,
+    frequency: f32,
+    velocity: f32,
+}
+
+#[derive(Default)]
+struct Outputs {
+    value: f32,
+}
+
+struct Operator {
+    ratio: f32,
+    fixed: bool,
+    fixed_frequency: f32,
+    level: f32,
+    feedback: f32,
+    envelope: Envelope,
+    // State
+    phase: f32,
+    /// The last two outputs, averaged for feedback so it doesn't ring at Nyquist
+    previous: [f32; 2],
+}
+
+impl Operator {
+    fn new(id: usize, level: f32) -> Self {
+        Self {
+            ratio: 1.0,
+            fixed: false,
+            fixed_frequency: 440.0,
+            level,
+            feedback: 0.0,
+            envelope: Envelope::new(id),
+            phase: 0.0,
+            previous: [0.0; 2],
+        }
+    }
+}
+
+/// Sine operators modulating each other's phase, played like an oscillator and envelope pair
+pub struct Fm {
+    id: usize,
+    sample_rate: usize,
+    algorithm: usize,
+    gain: f32,
+    input: Inputs,
+    output: Outputs,
+    // State
+    operators: [Operator; OPERATOR_COUNT],
+}
+
+impl Fm {
+    pub fn new(id: usize, sample_rate: usize) -> Self {
+        Self {
+            id,
+            sample_rate,
+            algorithm: 0,
+            gain: 0.0,
+            input: Inputs::default(),
+            output: Outputs::default(),
+            operators: [
+                Operator::new(id, 1.0),
+                Operator::new(id, 0.5),
+                Operator::new(id, 0.0),
+                Operator::new(id, 0.0),
+            ],
+        }
+    }
+
+    fn reset(&mut self) {
+        for operator in &mut self.operators {
+            operator.envelope.update(ModuleMessage::Reset);
+            operator.phase = 0.0;
+            operator.previous = [0.0; 2];
+        }
+        self.output.value = 0.0;
+    }
+}
+
+impl Module for Fm {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn process(&mut self) {
+        // A released voice stays silent once every operator's envelope has run out
+        let idle = self.input.gate == 0.0 && self.operators.iter().all(|operator| operator.envelope.get_output(0) == 0.0);
+        if self.gain == 0.0 || idle {
+            self.output.value = 0.0;
+            return;
+        }
+
+        let frequency = self.input.frequency.clamp(0.0, 1.0);
+        let frequency = 2.0_f32.powf(127.0 / 12.0 * frequency) * 8.176; // C-1 (midi note 0)
+        let algorithm = &ALGORITHMS[self.algorithm];
+
+        // Modulators are always higher numbered, so working down computes each one before it is used
+        let mut value = 0.0;
+        for index in (0..OPERATOR_COUNT).rev() {
+            let modulation: f32 = (0..OPERATOR_COUNT)
+                .filter(|modulator| algorithm.modulators[index] & (1 << modulator) != 0)
+                .map(|modulator| self.operators[modulator].previous[0])
+                .sum();
+
+            let operator = &mut self.operators[index];
+            let feedback = operator.feedback * PI * (operator.previous[0] + operator.previous[1]) / 2.0;
+            let raw = (TAU * operator.phase + MODULATION_INDEX * modulation + feedback).sin();
+
+            operator.envelope.process();
+            let out = raw * operator.envelope.get_output(0) * operator.level;
+            operator.previous = [out, operator.previous[0]];
+
+            let operator_frequency = if operator.fixed {operator.fixed_frequency} else {frequency * operator.ratio};
+            operator.phase = (operator.phase + operator_frequency / self.sample_rate as f32) % 1.0;
+
+            if algorithm.carriers & (1 << index) != 0 {
+                value += out;
+            }
+        }
+
+        self.output.value = value / algorithm.carriers.count_ones() as f32 * self.gain;
+    }
+
+    fn update(&mut self, msg: ModuleMessage) {
+        match msg {
+            ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.fm} {
+                FmUpdate::Algorithm(algorithm) => self.algorithm = algorithm.min(ALGORITHMS.len() - 1),
+                FmUpdate::Ratio(index, ratio) => if let Some(operator) = self.operators.get_mut(index) {
+                    operator.ratio = ratio;
+                },
+                FmUpdate::Fixed(index, fixed) => if let Some(operator) = self.operators.get_mut(index) {
+                    operator.fixed = fixed;
+                },
+                FmUpdate::FixedFrequency(index, frequency) => if let Some(operator) = self.operators.get_mut(index) {
+                    operator.fixed_frequency = frequency;
+                },
+                FmUpdate::Level(index, level) => if let Some(operator) = self.operators.get_mut(index) {
+                    operator.level = level;
+                },
+                FmUpdate::Feedback(index, feedback) => if let Some(operator) = self.operators.get_mut(index) {
+                    operator.feedback = feedback;
+                },
+                FmUpdate::Envelope(index, update) => if let Some(operator) = self.operators.get_mut(index) {
+                    operator.envelope.update(ModuleMessage::ComponentChange(ModuleMessageUnion {envelope: update}));
+                },
+                FmUpdate::Gain(gain) => self.gain = gain,
+            },
+            ModuleMessage::Reset => self.reset(),
+            _ => (),
+        }
+    }
+
+    fn get_output(&self, target_output: usize) -> f32 {
+        match target_output {
+            0 => self.output.value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn modulate(&mut self, component: usize, value: f32) {
+        match component {
+            // The envelopes see the gate even while processing is skipped, so a skipped note still starts and releases them
+            0 => {
+                self.input.gate = value;
+                self.operators.iter_mut().for_each(|operator| operator.envelope.modulate(0, value));
+            },
+            1 => self.input.frequency = value,
+            2 => {
+                self.input.velocity = value;
+                self.operators.iter_mut().for_each(|operator| operator.envelope.modulate(1, value));
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing(gain: f32) -> Fm {
+        let mut fm = Fm::new(0, 48000);
+        fm.update(ModuleMessage::ComponentChange(ModuleMessageUnion {fm: FmUpdate::Gain(gain)}));
+        fm.modulate(1, 0.5);
+        fm.modulate(2, 1.0);
+        fm
+    }
+
+    #[test]
+    fn skips_work_while_silent() {
+        let mut fm = playing(0.0);
+        fm.modulate(0, 1.0);
+        fm.process();
+        assert_eq!(fm.get_output(0), 0.0);
+        assert!(fm.operators.iter().all(|operator| operator.phase == 0.0));
+
+        let mut fm = playing(1.0);
+        fm.process();
+        assert_eq!(fm.get_output(0), 0.0);
+        assert!(fm.operators.iter().all(|operator| operator.phase == 0.0));
+    }
+
+    #[test]
+    fn plays_while_gated() {
+        let mut fm = playing(1.0);
+        fm.modulate(0, 1.0);
+        fm.process();
+        assert!(fm.operators[0].phase > 0.0);
+    }
+}
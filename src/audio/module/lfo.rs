@@ -1,4 +1,5 @@
 use crate::audio::module::{Module, ModuleMessage};
+use crate::audio::module::clock::TempoSync;
 
 #[derive(Clone, Copy, Debug)]
 pub enum WaveShape {
@@ -14,6 +15,7 @@ pub enum LfoUpdate {
     Frequency(f32),
     Phase(f32),
     Shape(WaveShape),
+    Sync(TempoSync),
 }
 
 #[derive(Default)]
@@ -21,6 +23,7 @@ struct Inputs {
     level: f32,
     frequency: f32,
     phase: f32,
+    tempo: f32,
 }
 
 #[derive(Default)]
@@ -36,6 +39,7 @@ pub struct Lfo {
     level: f32,
     frequency: f32,
     phase: f32,
+    sync: TempoSync,
     current_phase: f32,
     input: Inputs,
     output: Outputs,
@@ -51,6 +55,7 @@ impl Lfo {
             frequency: 0.0,
             shape: WaveShape::Triangle,
             phase: 0.0,
+            sync: TempoSync::Off,
             input: Inputs::default(),
             output: Outputs::default(),
         }
@@ -74,7 +79,10 @@ impl Module for Lfo {
 
         let level = (self.level + level_input).min(1.0).max(0.0);
         let frequency = (self.frequency + frequency_input).min(1.0).max(0.0);
-        let frequency = 2.0_f32.powf(127.0 / 12.0 * frequency) * 0.5; // C-1 (midi note 0)
+        let frequency = match self.sync.seconds(self.input.tempo) {
+            Some(period) => 1.0 / period,
+            None => 2.0_f32.powf(127.0 / 12.0 * frequency) * 0.5, // C-1 (midi note 0)
+        };
 
         let phase = (self.current_phase + phase_input) % 1.0;
         let quarter_phase = (phase + 0.25) % 1.0;
@@ -111,7 +119,9 @@ impl Module for Lfo {
                 LfoUpdate::Frequency(frequency) => self.frequency = frequency,
                 LfoUpdate::Phase(phase) => self.phase = phase,
                 LfoUpdate::Shape(shape) => self.shape = shape,
-            }
+                LfoUpdate::Sync(sync) => self.sync = sync,
+            },
+            _ => (),
         }
     }
 
@@ -128,6 +138,7 @@ impl Module for Lfo {
             0 => self.input.level = value,
             1 => self.input.frequency = value,
             2 => self.input.phase = value,
+            3 => self.input.tempo = value,
             _ => unreachable!(),
         }
     }
@@ -1,6 +1,8 @@
 use std::collections::VecDeque;
+use std::sync::Arc;
 
 use crate::audio::module::{Module, ModuleMessage};
+use crate::audio::tuning::Tuning;
 
 #[derive(Clone, Copy, Debug)]
 pub enum MidiUpdate {
@@ -8,37 +10,315 @@ pub enum MidiUpdate {
     KeyRelease(u8),
     PedalPress,
     PedalRelease,
+    ControlChange(u8, u8),
+    PitchBend(u16),
+    ChannelPressure(u8),
+    PolyPressure(u8, u8),
+    Mpe(MpeUpdate),
+
+    // Voice allocation
+    VoiceCount(usize),
+    StealPolicy(StealPolicy),
+    NotePriority(NotePriority),
+    Legato(bool),
+    Unison(usize),
+    /// Semitones between the outermost unison voices
+    UnisonDetune(f32),
+
+    // Glide
+    /// Seconds, zero turns glide off
+    GlideTime(f32),
+    GlideMode(GlideMode),
+    GlideRate(GlideRate),
+
+    // Response
+    VelocityCurve(VelocityCurve),
+    /// Output velocity at one of the evenly spaced custom curve points
+    VelocityBreakpoint(usize, u8),
+    FixedVelocity(u8),
+    /// Semitones at full bend up
+    BendUp(f32),
+    /// Semitones at full bend down
+    BendDown(f32),
+}
+
+/// Channel voice messages that keep their channel, so MPE member channels can be routed to voices.
+/// Channels are zero based.
+#[derive(Clone, Copy, Debug)]
+pub enum MpeUpdate {
+    Zone(MpeZone),
+    KeyPress { channel: u8, note: u8, velocity: u8 },
+    KeyRelease { channel: u8, note: u8 },
+    PitchBend { channel: u8, value: u16 },
+    Pressure { channel: u8, pressure: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+}
+
+/// Lower zone uses channel 1 as master and 2-16 as members, upper zone uses channel 16 as master and 1-15 as members
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MpeZone {
+    Off,
+    Lower,
+    Upper,
+}
+
+impl MpeZone {
+    pub const ALL: [MpeZone; 3] = [MpeZone::Off, MpeZone::Lower, MpeZone::Upper];
+
+    fn is_member(&self, channel: u8) -> bool {
+        match self {
+            MpeZone::Off => false,
+            MpeZone::Lower => channel != 0,
+            MpeZone::Upper => channel != 15,
+        }
+    }
+}
+
+impl std::fmt::Display for MpeZone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MpeZone::Off => write!(f, "MPE off"),
+            MpeZone::Lower => write!(f, "MPE lower zone"),
+            MpeZone::Upper => write!(f, "MPE upper zone"),
+        }
+    }
+}
+
+/// Which playing voice a new note takes over once every voice is in use.
+/// Voices only held by the sustain pedal are always taken first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StealPolicy {
+    Oldest,
+    Quietest,
+    Lowest,
+    Highest,
+}
+
+impl StealPolicy {
+    pub const ALL: [StealPolicy; 4] = [StealPolicy::Oldest, StealPolicy::Quietest, StealPolicy::Lowest, StealPolicy::Highest];
+}
+
+impl std::fmt::Display for StealPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StealPolicy::Oldest => write!(f, "Steal oldest"),
+            StealPolicy::Quietest => write!(f, "Steal quietest"),
+            StealPolicy::Lowest => write!(f, "Steal lowest"),
+            StealPolicy::Highest => write!(f, "Steal highest"),
+        }
+    }
+}
+
+/// Which held key the mono output plays
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NotePriority {
+    Last,
+    Low,
+    High,
+}
+
+impl NotePriority {
+    pub const ALL: [NotePriority; 3] = [NotePriority::Last, NotePriority::Low, NotePriority::High];
+}
+
+impl std::fmt::Display for NotePriority {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotePriority::Last => write!(f, "Last note priority"),
+            NotePriority::Low => write!(f, "Low note priority"),
+            NotePriority::High => write!(f, "High note priority"),
+        }
+    }
+}
+
+/// When a new note slides from the previous one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GlideMode {
+    Always,
+    /// Only while another key is held
+    Legato,
+}
+
+impl GlideMode {
+    pub const ALL: [GlideMode; 2] = [GlideMode::Always, GlideMode::Legato];
+}
+
+impl std::fmt::Display for GlideMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GlideMode::Always => write!(f, "Always glide"),
+            GlideMode::Legato => write!(f, "Legato glide"),
+        }
+    }
+}
+
+/// Whether the glide time is for the whole slide or for each octave
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GlideRate {
+    ConstantTime,
+    ConstantRate,
 }
 
-const POLY_VOICE_COUNT: usize = 16;
+impl GlideRate {
+    pub const ALL: [GlideRate; 2] = [GlideRate::ConstantTime, GlideRate::ConstantRate];
+}
+
+impl std::fmt::Display for GlideRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GlideRate::ConstantTime => write!(f, "Constant time"),
+            GlideRate::ConstantRate => write!(f, "Constant rate"),
+        }
+    }
+}
+
+/// How key velocity turns into the velocity outputs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VelocityCurve {
+    Linear,
+    /// Louder when played lightly
+    Soft,
+    /// Needs more force for the same level
+    Hard,
+    /// Straight lines between the breakpoints
+    Custom,
+    /// Every note plays at the fixed velocity
+    Fixed,
+}
+
+impl VelocityCurve {
+    pub const ALL: [VelocityCurve; 5] = [
+        VelocityCurve::Linear,
+        VelocityCurve::Soft,
+        VelocityCurve::Hard,
+        VelocityCurve::Custom,
+        VelocityCurve::Fixed,
+    ];
+}
+
+impl std::fmt::Display for VelocityCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VelocityCurve::Linear => write!(f, "Linear velocity"),
+            VelocityCurve::Soft => write!(f, "Soft velocity"),
+            VelocityCurve::Hard => write!(f, "Hard velocity"),
+            VelocityCurve::Custom => write!(f, "Custom velocity"),
+            VelocityCurve::Fixed => write!(f, "Fixed velocity"),
+        }
+    }
+}
+
+/// Custom curve points, spread evenly from velocity 0 to 127
+pub const VELOCITY_BREAKPOINT_COUNT: usize = 5;
+pub const LINEAR_BREAKPOINTS: [u8; VELOCITY_BREAKPOINT_COUNT] = [0, 32, 64, 95, 127];
+
+pub const POLY_VOICE_COUNT: usize = 16;
+
+// Output layout: mono gate/note/velocity, then gate/note/velocity for each poly voice, then controllers,
+// then bend/pressure/slide for each poly voice, then the unison spread of each poly voice, then the soft pedal,
+// then the untuned key number of each poly voice
+const MONO_OUTPUT_COUNT: usize = 3;
+const VOICE_OUTPUT_COUNT: usize = 3;
+const PITCH_BEND_OUTPUT: usize = MONO_OUTPUT_COUNT + POLY_VOICE_COUNT * VOICE_OUTPUT_COUNT;
+const MOD_WHEEL_OUTPUT: usize = PITCH_BEND_OUTPUT + 1;
+const AFTERTOUCH_OUTPUT: usize = PITCH_BEND_OUTPUT + 2;
+const EXPRESSION_OUTPUT_START: usize = AFTERTOUCH_OUTPUT + 1;
+const EXPRESSION_OUTPUT_COUNT: usize = 3;
+const UNISON_OUTPUT_START: usize = EXPRESSION_OUTPUT_START + POLY_VOICE_COUNT * EXPRESSION_OUTPUT_COUNT;
+const SOFT_PEDAL_OUTPUT: usize = UNISON_OUTPUT_START + POLY_VOICE_COUNT;
+const KEY_OUTPUT_START: usize = SOFT_PEDAL_OUTPUT + 1;
+const KEY_OUTPUT_END: usize = KEY_OUTPUT_START + POLY_VOICE_COUNT;
+
+const MOD_WHEEL_CC: u8 = 1;
+const MOD_WHEEL_LSB_CC: u8 = 33;
+pub const SLIDE_CC: u8 = 74;
+const SUSTAIN_CC: u8 = 64;
+const SOSTENUTO_CC: u8 = 66;
+const SOFT_PEDAL_CC: u8 = 67;
+pub const ALL_SOUND_OFF_CC: u8 = 120;
+pub const ALL_NOTES_OFF_CC: u8 = 123;
+
+/// Per note pitch bend range in semitones, the MPE default
+const MPE_BEND_RANGE: f32 = 48.0;
+
+#[derive(Clone, Copy, Debug)]
+struct Expression {
+    bend: f32,
+    pressure: f32,
+    slide: f32,
+}
+
+impl Default for Expression {
+    fn default() -> Self {
+        Self {
+            bend: 0.0,
+            pressure: 0.0,
+            slide: 0.5,
+        }
+    }
+}
+
+/// The pitch a note output is at while sliding to its note
+#[derive(Clone, Copy, Default, Debug)]
+struct Glide {
+    pitch: f32,
+    /// Change per sample, zero jumps straight to the note
+    step: f32,
+}
+
+impl Glide {
+    fn process(&mut self, target: f32) {
+        let distance = target - self.pitch;
+        if self.step == 0.0 || distance.abs() <= self.step {
+            self.pitch = target;
+        } else {
+            self.pitch += self.step.copysign(distance);
+        }
+    }
+}
 
 #[derive(Clone, Copy, Default, Debug)]
 struct Voice {
     pressed: bool,
+    /// Held by the sostenuto pedal, which only catches notes that were down when it was pressed
+    sostenuto: bool,
     gate: f32,
+    key: u8,
     note: f32,
     velocity: f32,
     trigger: bool,
     ready: bool,
     on: bool,
+    channel: Option<u8>,
+    expression: Expression,
+    /// Position in the unison stack, from -1 to 1
+    unison: f32,
+    glide: Glide,
 }
 
 impl Voice {
-    fn new(gate: f32, note: u8, velocity: u8) -> Self {
+    fn new(gate: f32, key: u8, note: f32, velocity: f32) -> Self {
         Self {
             pressed: true,
+            sostenuto: false,
             gate,
-            note: note as f32 / 127.0,
-            velocity: velocity as f32 / 127.0,
+            key,
+            note,
+            velocity,
             trigger: true,
             ready: false,
             on: true,
+            channel: None,
+            expression: Expression::default(),
+            unison: 0.0,
+            glide: Glide::default(),
         }
     }
 }
 
 pub struct Midi {
     id: usize,
+    sample_rate: usize,
     gate: f32,
     trigger: bool,
     ready: bool,
@@ -47,13 +327,46 @@ pub struct Midi {
 
     // Controls
     sustain: bool,
-    pressed: bool,
+    sostenuto: bool,
+    /// The mono note was held when the sostenuto pedal went down
+    mono_sostenuto: bool,
+    soft_pedal: f32,
+    /// Keys held for the mono output in the order they were pressed, with their velocity
+    held: Vec<(u8, u8)>,
+    note_priority: NotePriority,
+    legato: bool,
+    pitch_bend: f32,
+    mod_wheel: (u8, u8),
+    aftertouch: f32,
+    bend_up: f32,
+    bend_down: f32,
+    velocity_curve: VelocityCurve,
+    velocity_breakpoints: [u8; VELOCITY_BREAKPOINT_COUNT],
+    fixed_velocity: u8,
 
     // Poly voices
     voices: [Voice; POLY_VOICE_COUNT],
+    voice_count: usize,
     next: usize,
-    
+    steal_policy: StealPolicy,
+    unison: usize,
+    unison_detune: f32,
+
     replace_queue: VecDeque<usize>,
+
+    // Glide
+    glide: Glide,
+    glide_time: f32,
+    glide_mode: GlideMode,
+    glide_rate: GlideRate,
+    /// Where the next poly note slides from
+    last_note: Option<f32>,
+
+    tuning: Arc<Tuning>,
+
+    // MPE
+    zone: MpeZone,
+    channels: [Expression; 16],
 }
 
 impl Module for Midi {
@@ -69,7 +382,8 @@ impl Module for Midi {
             self.gate = 1.0;
             self.ready = false;
         }
-        
+        self.glide.process(self.note);
+
         for voice in &mut self.voices {
             if voice.trigger {
                 voice.ready = true;
@@ -78,82 +392,48 @@ impl Module for Midi {
                 voice.gate = 1.0;
                 voice.ready = false;
             }
+            voice.glide.process(voice.note);
         }
     }
 
     fn update(&mut self, msg: ModuleMessage) {
         match msg {
             ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.midi} {
-                MidiUpdate::KeyPress(note, velocity) => {
-                    // Mono
-                    self.pressed = true;
-                    self.gate = 0.0;
-                    self.trigger = true;
-                    self.note = note as f32 / 127.0;
-                    self.velocity = velocity as f32 / 127.0;
-
-                    // Poly
-                    let new_voice;
-                    if let Some(voice) = self.voices.iter().cycle().skip(self.next).take(POLY_VOICE_COUNT).position(|voice| !voice.on ) {
-                        new_voice = (voice + self.next) % POLY_VOICE_COUNT;
-                        self.next = (self.next + 1) % POLY_VOICE_COUNT;
-                    } else {
-                        new_voice = self.replace_queue.pop_front().unwrap();
-                    }
-                    self.voices[new_voice] = Voice::new(0.0, note, velocity);
-                    self.replace_queue.push_back(new_voice);
-                },
-                MidiUpdate::KeyRelease(note) => {
-                    let note_signal = note as f32 / 127.0;
-
-                    if self.note == note_signal {
-                        if !self.sustain {
-                            self.gate = 0.0;
-                            self.ready = false;
-                            self.trigger = false;
-                        }
-                        self.pressed = false;
-                    }
-
-                    // Poly
-                    for (i, voice) in self.voices.iter_mut().enumerate() {
-                        if voice.note == note_signal {
-                            if !self.sustain && voice.on {
-                                voice.ready = false;
-                                voice.trigger = false;
-                                voice.gate = 0.0;
-                                voice.on = false;
-                                
-                                let queue_pos = self.replace_queue.iter().position(|voice_num| *voice_num == i).unwrap();
-                                self.replace_queue.remove(queue_pos);
-                            }
-                            voice.pressed = false;
-                        }
+                MidiUpdate::KeyPress(note, velocity) => self.key_press(note, velocity, None),
+                MidiUpdate::KeyRelease(note) => self.key_release(note, None),
+                MidiUpdate::ControlChange(controller, value) => self.control_change(controller, value),
+                MidiUpdate::PitchBend(value) => self.pitch_bend = (value as f32 - 8192.0) / 8192.0,
+                MidiUpdate::ChannelPressure(pressure) => self.aftertouch = pressure as f32 / 127.0,
+                MidiUpdate::PolyPressure(note, pressure) => {
+                    for voice in self.voices.iter_mut().filter(|voice| voice.on && voice.key == note) {
+                        voice.expression.pressure = pressure as f32 / 127.0;
                     }
                 },
                 MidiUpdate::PedalPress => self.sustain = true,
-                MidiUpdate::PedalRelease => {
-                    self.sustain = false; 
-                    if !self.pressed {
-                        self.gate = 0.0;
-                        self.ready = false;
-                        self.trigger = false;
-                    }
-
-                    // Poly
-                    for (i, voice) in self.voices.iter_mut().enumerate() {
-                        if voice.on && !voice.pressed {
-                            voice.ready = false;
-                            voice.trigger = false;
-                            voice.gate = 0.0;
-                            voice.on = false;
-                            
-                            let queue_pos = self.replace_queue.iter().position(|voice_num| *voice_num == i).unwrap();
-                            self.replace_queue.remove(queue_pos);
-                        }
+                MidiUpdate::PedalRelease => self.pedal_release(),
+                MidiUpdate::Mpe(update) => self.mpe(update),
+                MidiUpdate::VoiceCount(count) => self.set_voice_count(count),
+                MidiUpdate::StealPolicy(policy) => self.steal_policy = policy,
+                MidiUpdate::NotePriority(priority) => self.note_priority = priority,
+                MidiUpdate::Legato(legato) => self.legato = legato,
+                MidiUpdate::Unison(unison) => self.unison = unison.clamp(1, POLY_VOICE_COUNT),
+                MidiUpdate::UnisonDetune(detune) => self.unison_detune = detune,
+                MidiUpdate::GlideTime(time) => self.glide_time = time,
+                MidiUpdate::GlideMode(mode) => self.glide_mode = mode,
+                MidiUpdate::GlideRate(rate) => self.glide_rate = rate,
+                MidiUpdate::VelocityCurve(curve) => self.velocity_curve = curve,
+                MidiUpdate::VelocityBreakpoint(index, velocity) => {
+                    if let Some(breakpoint) = self.velocity_breakpoints.get_mut(index) {
+                        *breakpoint = velocity.min(127);
                     }
                 },
-            }
+                MidiUpdate::FixedVelocity(velocity) => self.fixed_velocity = velocity.min(127),
+                MidiUpdate::BendUp(semitones) => self.bend_up = semitones,
+                MidiUpdate::BendDown(semitones) => self.bend_down = semitones,
+            },
+            ModuleMessage::Tuning(tuning) => self.tuning = tuning,
+            ModuleMessage::Reset => self.all_sound_off(),
+            _ => (),
         }
         // println!("{:?}", self.replace_queue);
     }
@@ -161,15 +441,36 @@ impl Module for Midi {
     fn get_output(&self, target_output: usize) -> f32 {
         match target_output {
             0 => self.gate,
-            1 => self.note,
+            1 => self.glide.pitch + self.bend() / 127.0,
             2 => self.velocity,
+            PITCH_BEND_OUTPUT => self.pitch_bend,
+            MOD_WHEEL_OUTPUT => (self.mod_wheel.0 as f32 * 128.0 + self.mod_wheel.1 as f32) / 16383.0,
+            AFTERTOUCH_OUTPUT => self.aftertouch,
+            EXPRESSION_OUTPUT_START..UNISON_OUTPUT_START => {
+                let voice = (target_output - EXPRESSION_OUTPUT_START) / EXPRESSION_OUTPUT_COUNT;
+                let data_type = (target_output - EXPRESSION_OUTPUT_START) % EXPRESSION_OUTPUT_COUNT;
+
+                match data_type {
+                    0 => self.voices[voice].expression.bend,
+                    1 => self.voices[voice].expression.pressure,
+                    2 => self.voices[voice].expression.slide,
+                    _ => unreachable!(),
+                }
+            },
+            UNISON_OUTPUT_START..SOFT_PEDAL_OUTPUT => self.voices[target_output - UNISON_OUTPUT_START].unison,
+            SOFT_PEDAL_OUTPUT => self.soft_pedal,
+            KEY_OUTPUT_START..KEY_OUTPUT_END => self.voices[target_output - KEY_OUTPUT_START].key as f32,
             _ => {
-                let voice = (target_output - 3) / 3;
-                let data_type = target_output % 3;
+                let voice = (target_output - MONO_OUTPUT_COUNT) / VOICE_OUTPUT_COUNT;
+                let data_type = target_output % VOICE_OUTPUT_COUNT;
 
                 match data_type {
                     0 => self.voices[voice].gate,
-                    1 => self.voices[voice].note,
+                    1 => {
+                        let voice = &self.voices[voice];
+                        let semitones = self.bend() + voice.expression.bend * MPE_BEND_RANGE + voice.unison * self.unison_detune / 2.0;
+                        voice.glide.pitch + semitones / 127.0
+                    },
                     2 => self.voices[voice].velocity,
                     _ => unreachable!(),
                 }
@@ -181,20 +482,628 @@ impl Module for Midi {
 }
 
 impl Midi {
-    pub fn new(id: usize) -> Midi {
+    pub fn new(id: usize, sample_rate: usize) -> Midi {
         Self {
             id,
+            sample_rate,
             gate: 0.0,
             trigger: false,
             ready: false,
             note: 0.0,
             velocity: 0.0,
             sustain: false,
-            pressed: false,
+            sostenuto: false,
+            mono_sostenuto: false,
+            soft_pedal: 0.0,
+            held: Vec::with_capacity(128),
+            note_priority: NotePriority::Last,
+            legato: false,
+            pitch_bend: 0.0,
+            mod_wheel: (0, 0),
+            aftertouch: 0.0,
+            bend_up: 2.0,
+            bend_down: 2.0,
+            velocity_curve: VelocityCurve::Linear,
+            velocity_breakpoints: LINEAR_BREAKPOINTS,
+            fixed_velocity: 100,
 
             voices: [Voice::default(); POLY_VOICE_COUNT],
+            voice_count: POLY_VOICE_COUNT,
             replace_queue: VecDeque::with_capacity(POLY_VOICE_COUNT),
             next: 0,
+            steal_policy: StealPolicy::Oldest,
+            unison: 1,
+            unison_detune: 0.1,
+
+            glide: Glide::default(),
+            glide_time: 0.0,
+            glide_mode: GlideMode::Always,
+            glide_rate: GlideRate::ConstantTime,
+            last_note: None,
+
+            tuning: Arc::new(Tuning::equal()),
+
+            zone: MpeZone::Off,
+            channels: [Expression::default(); 16],
+        }
+    }
+
+    /// Starts a note, a channel ties the voice to an MPE member channel
+    fn key_press(&mut self, note: u8, velocity: u8, channel: Option<u8>) {
+        let legato = !self.held.is_empty();
+
+        // Mono
+        let previous = self.mono_note();
+        self.held.retain(|(key, _)| *key != note);
+        self.held.push((note, velocity));
+        self.play_mono(previous);
+
+        // Poly
+        let tuned = self.tuning.note(note);
+        let glide = self.glide(self.last_note, tuned, legato);
+        self.last_note = Some(tuned);
+        let unison = self.unison.min(self.voice_count);
+        // Queued after the loop, so a steal can't take a voice this note has just started
+        let mut allocated = [0; POLY_VOICE_COUNT];
+        for (i, slot) in allocated.iter_mut().enumerate().take(unison) {
+            let new_voice = self.allocate();
+            self.voices[new_voice] = Voice::new(0.0, note, tuned, self.shape_velocity(velocity));
+            self.voices[new_voice].glide = glide;
+            if unison > 1 {
+                self.voices[new_voice].unison = i as f32 / (unison - 1) as f32 * 2.0 - 1.0;
+            }
+            if let Some(channel) = channel {
+                self.voices[new_voice].channel = Some(channel);
+                self.voices[new_voice].expression = self.channels[channel as usize];
+            }
+            *slot = new_voice;
+        }
+        self.replace_queue.extend(&allocated[..unison]);
+    }
+
+    fn key_release(&mut self, note: u8, channel: Option<u8>) {
+        // Mono
+        if self.held.iter().any(|(key, _)| *key == note) {
+            let previous = self.mono_note();
+            self.held.retain(|(key, _)| *key != note);
+            if self.held.is_empty() {
+                if !self.sustain && !self.mono_sostenuto {
+                    self.release_mono();
+                }
+            } else {
+                self.play_mono(previous);
+            }
+        }
+
+        // Poly
+        for i in 0..POLY_VOICE_COUNT {
+            let voice = &mut self.voices[i];
+            if voice.key == note && voice.channel == channel {
+                voice.pressed = false;
+                if !self.sustain && !voice.sostenuto && voice.on {
+                    self.release_voice(i);
+                }
+            }
         }
     }
-}
\ No newline at end of file
+
+    fn pedal_release(&mut self) {
+        self.sustain = false;
+        if self.held.is_empty() && !self.mono_sostenuto {
+            self.release_mono();
+        }
+
+        // Poly
+        for i in 0..POLY_VOICE_COUNT {
+            let voice = &self.voices[i];
+            if voice.on && !voice.pressed && !voice.sostenuto {
+                self.release_voice(i);
+            }
+        }
+    }
+
+    /// Catches the notes that are down, later notes aren't held
+    fn sostenuto_press(&mut self) {
+        if self.sostenuto {
+            return;
+        }
+        self.sostenuto = true;
+        self.mono_sostenuto = !self.held.is_empty();
+
+        for voice in self.voices.iter_mut().filter(|voice| voice.on && voice.pressed) {
+            voice.sostenuto = true;
+        }
+    }
+
+    fn sostenuto_release(&mut self) {
+        self.sostenuto = false;
+        if self.mono_sostenuto && self.held.is_empty() && !self.sustain {
+            self.release_mono();
+        }
+        self.mono_sostenuto = false;
+
+        // Poly
+        for i in 0..POLY_VOICE_COUNT {
+            let voice = &mut self.voices[i];
+            if voice.sostenuto {
+                voice.sostenuto = false;
+                if voice.on && !voice.pressed && !self.sustain {
+                    self.release_voice(i);
+                }
+            }
+        }
+    }
+
+    /// Releases every key, notes held by the pedals keep sounding
+    fn all_notes_off(&mut self) {
+        self.held.clear();
+        if !self.sustain && !self.mono_sostenuto {
+            self.release_mono();
+        }
+
+        // Poly
+        for i in 0..POLY_VOICE_COUNT {
+            self.voices[i].pressed = false;
+            if self.voices[i].on && !self.sustain && !self.voices[i].sostenuto {
+                self.release_voice(i);
+            }
+        }
+    }
+
+    /// Silences every voice straight away and forgets the pedals
+    fn all_sound_off(&mut self) {
+        self.held.clear();
+        self.sustain = false;
+        self.sostenuto = false;
+        self.mono_sostenuto = false;
+        self.release_mono();
+
+        // Poly
+        for i in 0..POLY_VOICE_COUNT {
+            self.release_voice(i);
+            self.voices[i].pressed = false;
+            self.voices[i].sostenuto = false;
+        }
+        self.replace_queue.clear();
+    }
+
+    fn release_mono(&mut self) {
+        self.gate = 0.0;
+        self.ready = false;
+        self.trigger = false;
+    }
+
+    /// The held key the mono output should play
+    fn mono_note(&self) -> Option<(u8, u8)> {
+        match self.note_priority {
+            NotePriority::Last => self.held.last().copied(),
+            NotePriority::Low => self.held.iter().min_by_key(|(key, _)| *key).copied(),
+            NotePriority::High => self.held.iter().max_by_key(|(key, _)| *key).copied(),
+        }
+    }
+
+    /// Moves the mono output to the prioritised key if it changed,
+    /// legato only retriggers when no key was held before
+    fn play_mono(&mut self, previous: Option<(u8, u8)>) {
+        let Some((note, velocity)) = self.mono_note() else {
+            return;
+        };
+        if previous.is_some_and(|(key, _)| key == note) {
+            return;
+        }
+
+        self.note = self.tuning.note(note);
+        let from = self.last_note.map(|_| self.glide.pitch);
+        self.glide = self.glide(from, self.note, previous.is_some());
+        if !(self.legato && previous.is_some()) {
+            self.velocity = self.shape_velocity(velocity);
+            self.gate = 0.0;
+            self.trigger = true;
+        }
+    }
+
+    /// Key velocity through the velocity curve, from 0 to 1
+    fn shape_velocity(&self, velocity: u8) -> f32 {
+        let x = velocity.min(127) as f32 / 127.0;
+        match self.velocity_curve {
+            VelocityCurve::Linear => x,
+            VelocityCurve::Soft => x.sqrt(),
+            VelocityCurve::Hard => x * x,
+            VelocityCurve::Custom => {
+                let position = x * (VELOCITY_BREAKPOINT_COUNT - 1) as f32;
+                let index = (position as usize).min(VELOCITY_BREAKPOINT_COUNT - 2);
+                let t = position - index as f32;
+                let from = self.velocity_breakpoints[index] as f32;
+                let to = self.velocity_breakpoints[index + 1] as f32;
+                (from + (to - from) * t) / 127.0
+            },
+            VelocityCurve::Fixed => self.fixed_velocity as f32 / 127.0,
+        }
+    }
+
+    /// Channel pitch bend in semitones, up and down have their own range
+    fn bend(&self) -> f32 {
+        if self.pitch_bend >= 0.0 {
+            self.pitch_bend * self.bend_up
+        } else {
+            self.pitch_bend * self.bend_down
+        }
+    }
+
+    /// Starts a slide to a note, from nowhere jumps straight there
+    fn glide(&self, from: Option<f32>, to: f32, legato: bool) -> Glide {
+        let from = from.filter(|_| self.glide_time > 0.0 && (legato || self.glide_mode == GlideMode::Always));
+        let Some(from) = from else {
+            return Glide {
+                pitch: to,
+                step: 0.0,
+            };
+        };
+
+        let distance = match self.glide_rate {
+            GlideRate::ConstantTime => (to - from).abs(),
+            GlideRate::ConstantRate => 12.0 / 127.0,
+        };
+        Glide {
+            pitch: from,
+            step: distance / (self.glide_time * self.sample_rate as f32),
+        }
+    }
+
+    /// A free voice in round robin order, or one taken from a playing note by the steal policy
+    fn allocate(&mut self) -> usize {
+        let count = self.voice_count;
+        if let Some(voice) = (0..count).map(|i| (self.next + i) % count).find(|voice| !self.voices[*voice].on) {
+            self.next = (voice + 1) % count;
+            return voice;
+        }
+
+        let sustained_only = self.replace_queue.iter().any(|voice| !self.voices[*voice].pressed);
+        let candidates = self.replace_queue.iter()
+            .enumerate()
+            .filter(|(_, voice)| !sustained_only || !self.voices[**voice].pressed)
+            .map(|(position, voice)| (position, &self.voices[*voice]));
+
+        // Ties go to the oldest voice, which comes first in the queue
+        let position = match self.steal_policy {
+            StealPolicy::Oldest => candidates.map(|(position, _)| position).next(),
+            StealPolicy::Quietest => candidates.min_by(|a, b| a.1.velocity.total_cmp(&b.1.velocity)).map(|(position, _)| position),
+            StealPolicy::Lowest => candidates.min_by_key(|(_, voice)| voice.key).map(|(position, _)| position),
+            StealPolicy::Highest => candidates.min_by_key(|(_, voice)| std::cmp::Reverse(voice.key)).map(|(position, _)| position),
+        };
+        self.replace_queue.remove(position.unwrap()).unwrap()
+    }
+
+    fn release_voice(&mut self, index: usize) {
+        let voice = &mut self.voices[index];
+        voice.ready = false;
+        voice.trigger = false;
+        voice.gate = 0.0;
+        voice.on = false;
+
+        if let Some(queue_pos) = self.replace_queue.iter().position(|voice_num| *voice_num == index) {
+            self.replace_queue.remove(queue_pos);
+        }
+    }
+
+    /// Voices past the new count are released straight away
+    fn set_voice_count(&mut self, count: usize) {
+        self.voice_count = count.clamp(1, POLY_VOICE_COUNT);
+        self.next %= self.voice_count;
+        for i in self.voice_count..POLY_VOICE_COUNT {
+            self.release_voice(i);
+            self.voices[i].pressed = false;
+        }
+    }
+
+    fn control_change(&mut self, controller: u8, value: u8) {
+        match controller {
+            MOD_WHEEL_CC => self.mod_wheel = (value, 0),
+            MOD_WHEEL_LSB_CC => self.mod_wheel.1 = value,
+            SOSTENUTO_CC => {
+                if value < 64 {
+                    self.sostenuto_release();
+                } else {
+                    self.sostenuto_press();
+                }
+            },
+            SOFT_PEDAL_CC => self.soft_pedal = value as f32 / 127.0,
+            ALL_SOUND_OFF_CC => self.all_sound_off(),
+            ALL_NOTES_OFF_CC => self.all_notes_off(),
+            _ => (),
+        }
+    }
+
+    /// Member channel messages only affect the voices playing on that channel,
+    /// everything else is handled like a normal channel voice message
+    fn mpe(&mut self, update: MpeUpdate) {
+        match update {
+            MpeUpdate::Zone(zone) => {
+                self.zone = zone;
+                self.channels = [Expression::default(); 16];
+                // Their note offs would come on a channel the new zone may not route back to them
+                for i in 0..POLY_VOICE_COUNT {
+                    if self.voices[i].channel.take().is_some() {
+                        self.release_voice(i);
+                        self.voices[i].pressed = false;
+                        self.voices[i].expression = Expression::default();
+                    }
+                }
+            },
+            MpeUpdate::KeyPress { channel, note, velocity } => {
+                let channel = Some(channel).filter(|channel| self.zone.is_member(*channel));
+                self.key_press(note, velocity, channel);
+            },
+            MpeUpdate::KeyRelease { channel, note } => {
+                let channel = Some(channel).filter(|channel| self.zone.is_member(*channel));
+                self.key_release(note, channel);
+            },
+            MpeUpdate::PitchBend { channel, value } if self.zone.is_member(channel) => {
+                self.channels[channel as usize].bend = (value as f32 - 8192.0) / 8192.0;
+                self.update_channel(channel);
+            },
+            MpeUpdate::Pressure { channel, pressure } if self.zone.is_member(channel) => {
+                self.channels[channel as usize].pressure = pressure as f32 / 127.0;
+                self.update_channel(channel);
+            },
+            MpeUpdate::ControlChange { channel, controller: SLIDE_CC, value } if self.zone.is_member(channel) => {
+                self.channels[channel as usize].slide = value as f32 / 127.0;
+                self.update_channel(channel);
+            },
+            MpeUpdate::PitchBend { value, .. } => self.pitch_bend = (value as f32 - 8192.0) / 8192.0,
+            MpeUpdate::Pressure { pressure, .. } => self.aftertouch = pressure as f32 / 127.0,
+            MpeUpdate::ControlChange { controller: SUSTAIN_CC, value, .. } => {
+                if value < 64 {
+                    self.pedal_release();
+                } else {
+                    self.sustain = true;
+                }
+            },
+            MpeUpdate::ControlChange { controller, value, .. } => self.control_change(controller, value),
+        }
+    }
+
+    fn update_channel(&mut self, channel: u8) {
+        let expression = self.channels[channel as usize];
+        for voice in self.voices.iter_mut().filter(|voice| voice.channel == Some(channel)) {
+            voice.expression = expression;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::module::ModuleMessageUnion;
+
+    /// Sends each update, then runs long enough for new notes to open their gates
+    fn play(midi: &mut Midi, updates: &[MidiUpdate]) {
+        for &update in updates {
+            midi.update(ModuleMessage::ComponentChange(ModuleMessageUnion { midi: update }));
+        }
+        midi.process();
+        midi.process();
+    }
+
+    /// The keys of every poly voice with its gate open, lowest first
+    fn sounding(midi: &Midi) -> Vec<u8> {
+        let mut keys: Vec<u8> = (0..POLY_VOICE_COUNT)
+            .filter(|voice| midi.get_output(MONO_OUTPUT_COUNT + voice * VOICE_OUTPUT_COUNT) == 1.0)
+            .map(|voice| midi.get_output(KEY_OUTPUT_START + voice) as u8)
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    fn gated_voices(midi: &Midi) -> Vec<usize> {
+        (0..POLY_VOICE_COUNT)
+            .filter(|voice| midi.get_output(MONO_OUTPUT_COUNT + voice * VOICE_OUTPUT_COUNT) == 1.0)
+            .collect()
+    }
+
+    fn mono_note(midi: &Midi) -> f32 {
+        midi.get_output(1)
+    }
+
+    #[test]
+    fn allocates_free_voices_round_robin() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[MidiUpdate::KeyPress(60, 100), MidiUpdate::KeyPress(62, 100), MidiUpdate::KeyPress(64, 100)]);
+        assert_eq!(gated_voices(&midi), [0, 1, 2]);
+
+        play(&mut midi, &[MidiUpdate::KeyRelease(62)]);
+        assert_eq!(sounding(&midi), [60, 64]);
+
+        // The released voice waits its turn rather than being reused straight away
+        play(&mut midi, &[MidiUpdate::KeyPress(65, 100)]);
+        assert_eq!(gated_voices(&midi), [0, 2, 3]);
+        assert_eq!(sounding(&midi), [60, 64, 65]);
+    }
+
+    #[test]
+    fn steals_by_policy() {
+        for (policy, stolen) in [
+            (StealPolicy::Oldest, 60),
+            (StealPolicy::Quietest, 72),
+            (StealPolicy::Lowest, 48),
+            (StealPolicy::Highest, 72),
+        ] {
+            let mut midi = Midi::new(0, 48000);
+            play(&mut midi, &[
+                MidiUpdate::VoiceCount(3),
+                MidiUpdate::StealPolicy(policy),
+                MidiUpdate::KeyPress(60, 100),
+                MidiUpdate::KeyPress(72, 20),
+                MidiUpdate::KeyPress(48, 80),
+                MidiUpdate::KeyPress(67, 90),
+            ]);
+
+            let mut expected: Vec<u8> = [48, 60, 67, 72].into_iter().filter(|key| *key != stolen).collect();
+            expected.sort();
+            assert_eq!(sounding(&midi), expected, "{policy}");
+        }
+    }
+
+    #[test]
+    fn steals_sustained_notes_before_held_ones() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[
+            MidiUpdate::VoiceCount(2),
+            MidiUpdate::StealPolicy(StealPolicy::Highest),
+            MidiUpdate::PedalPress,
+            MidiUpdate::KeyPress(60, 100),
+            MidiUpdate::KeyRelease(60),
+            MidiUpdate::KeyPress(62, 100),
+            MidiUpdate::KeyPress(64, 100),
+        ]);
+        assert_eq!(sounding(&midi), [62, 64]);
+    }
+
+    #[test]
+    fn voice_count_releases_voices_past_it() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[
+            MidiUpdate::KeyPress(60, 100),
+            MidiUpdate::KeyPress(62, 100),
+            MidiUpdate::KeyPress(64, 100),
+            MidiUpdate::KeyPress(65, 100),
+        ]);
+        play(&mut midi, &[MidiUpdate::VoiceCount(2)]);
+        assert_eq!(gated_voices(&midi), [0, 1]);
+
+        play(&mut midi, &[MidiUpdate::KeyPress(67, 100)]);
+        assert_eq!(gated_voices(&midi), [0, 1]);
+        assert_eq!(sounding(&midi), [62, 67]);
+    }
+
+    #[test]
+    fn mono_output_follows_note_priority() {
+        let tuning = Tuning::equal();
+        for (priority, playing, after_release) in [
+            (NotePriority::Last, 67, 60),
+            (NotePriority::Low, 60, 64),
+            (NotePriority::High, 67, 64),
+        ] {
+            let mut midi = Midi::new(0, 48000);
+            play(&mut midi, &[
+                MidiUpdate::NotePriority(priority),
+                MidiUpdate::KeyPress(64, 100),
+                MidiUpdate::KeyPress(60, 100),
+                MidiUpdate::KeyPress(67, 100),
+            ]);
+            assert_eq!(mono_note(&midi), tuning.note(playing), "{priority}");
+            assert_eq!(midi.get_output(0), 1.0);
+
+            play(&mut midi, &[MidiUpdate::KeyRelease(playing)]);
+            assert_eq!(mono_note(&midi), tuning.note(after_release), "{priority}");
+            assert_eq!(midi.get_output(0), 1.0);
+        }
+    }
+
+    #[test]
+    fn unison_stacks_voices_and_steals_whole_stacks() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[MidiUpdate::VoiceCount(4), MidiUpdate::Unison(2), MidiUpdate::KeyPress(60, 100)]);
+        assert_eq!(sounding(&midi), [60, 60]);
+        assert_eq!([0, 1].map(|voice| midi.get_output(UNISON_OUTPUT_START + voice)), [-1.0, 1.0]);
+
+        play(&mut midi, &[MidiUpdate::KeyPress(62, 100), MidiUpdate::KeyPress(64, 100)]);
+        assert_eq!(sounding(&midi), [62, 62, 64, 64]);
+    }
+
+    #[test]
+    fn unison_steals_skip_voices_the_same_note_took() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[MidiUpdate::VoiceCount(2), MidiUpdate::Unison(2), MidiUpdate::KeyPress(60, 100)]);
+        play(&mut midi, &[MidiUpdate::KeyPress(62, 100)]);
+        assert_eq!(sounding(&midi), [62, 62]);
+    }
+
+    #[test]
+    fn sostenuto_holds_only_notes_down_when_pressed() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[
+            MidiUpdate::KeyPress(60, 100),
+            MidiUpdate::ControlChange(SOSTENUTO_CC, 127),
+            MidiUpdate::KeyPress(62, 100),
+            MidiUpdate::KeyRelease(60),
+            MidiUpdate::KeyRelease(62),
+        ]);
+        assert_eq!(sounding(&midi), [60]);
+        assert_eq!(midi.get_output(0), 1.0);
+
+        play(&mut midi, &[MidiUpdate::ControlChange(SOSTENUTO_CC, 0)]);
+        assert_eq!(sounding(&midi), []);
+        assert_eq!(midi.get_output(0), 0.0);
+    }
+
+    #[test]
+    fn notes_held_by_both_pedals_wait_for_the_last_one() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[
+            MidiUpdate::KeyPress(60, 100),
+            MidiUpdate::ControlChange(SOSTENUTO_CC, 127),
+            MidiUpdate::PedalPress,
+            MidiUpdate::KeyRelease(60),
+            MidiUpdate::ControlChange(SOSTENUTO_CC, 0),
+        ]);
+        assert_eq!(sounding(&midi), [60]);
+        play(&mut midi, &[MidiUpdate::PedalRelease]);
+        assert_eq!(sounding(&midi), []);
+        assert_eq!(midi.get_output(0), 0.0);
+
+        play(&mut midi, &[
+            MidiUpdate::KeyPress(62, 100),
+            MidiUpdate::ControlChange(SOSTENUTO_CC, 127),
+            MidiUpdate::PedalPress,
+            MidiUpdate::KeyRelease(62),
+            MidiUpdate::PedalRelease,
+        ]);
+        assert_eq!(sounding(&midi), [62]);
+        play(&mut midi, &[MidiUpdate::ControlChange(SOSTENUTO_CC, 0)]);
+        assert_eq!(sounding(&midi), []);
+    }
+
+    #[test]
+    fn all_notes_off_leaves_sustained_notes() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[MidiUpdate::KeyPress(60, 100), MidiUpdate::KeyPress(62, 100)]);
+        play(&mut midi, &[MidiUpdate::ControlChange(ALL_NOTES_OFF_CC, 0)]);
+        assert_eq!(sounding(&midi), []);
+        assert_eq!(midi.get_output(0), 0.0);
+
+        play(&mut midi, &[MidiUpdate::PedalPress, MidiUpdate::KeyPress(64, 100)]);
+        play(&mut midi, &[MidiUpdate::ControlChange(ALL_NOTES_OFF_CC, 0)]);
+        assert_eq!(sounding(&midi), [64]);
+        assert_eq!(midi.get_output(0), 1.0);
+
+        play(&mut midi, &[MidiUpdate::PedalRelease]);
+        assert_eq!(sounding(&midi), []);
+        assert_eq!(midi.get_output(0), 0.0);
+    }
+
+    #[test]
+    fn all_sound_off_forgets_the_pedals() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[
+            MidiUpdate::PedalPress,
+            MidiUpdate::KeyPress(60, 100),
+            MidiUpdate::ControlChange(SOSTENUTO_CC, 127),
+        ]);
+        play(&mut midi, &[MidiUpdate::ControlChange(ALL_SOUND_OFF_CC, 0)]);
+        assert_eq!(sounding(&midi), []);
+        assert_eq!(midi.get_output(0), 0.0);
+
+        play(&mut midi, &[MidiUpdate::KeyPress(62, 100), MidiUpdate::KeyRelease(62)]);
+        assert_eq!(sounding(&midi), []);
+    }
+
+    #[test]
+    fn soft_pedal_output() {
+        let mut midi = Midi::new(0, 48000);
+        play(&mut midi, &[MidiUpdate::ControlChange(SOFT_PEDAL_CC, 127)]);
+        assert_eq!(midi.get_output(SOFT_PEDAL_OUTPUT), 1.0);
+        play(&mut midi, &[MidiUpdate::ControlChange(SOFT_PEDAL_CC, 0)]);
+        assert_eq!(midi.get_output(SOFT_PEDAL_OUTPUT), 0.0);
+    }
+}
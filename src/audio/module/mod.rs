@@ -1,13 +1,27 @@
+pub mod additive;
 pub mod allpass;
 pub mod analog;
 pub mod butterworth;
+pub mod clock;
 pub mod comb;
 pub mod chorus;
 pub mod delay;
 pub mod envelope;
+pub mod fm;
 pub mod lfo;
 pub mod midi;
+pub mod noise;
 pub mod reverb;
+pub mod sampler;
+pub mod sequencer;
+pub mod supersaw;
+pub mod wavetable;
+
+use std::sync::Arc;
+
+use crate::audio::sfz::Instrument;
+use crate::audio::tuning::Tuning;
+use crate::audio::wavetable::Wavetable;
 
 pub trait Module {
     fn id(&self) -> usize;
@@ -21,23 +35,39 @@ pub trait Module {
     fn modulate(&mut self, component: usize, value: f32);
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum ModuleMessage {
     ComponentChange(ModuleMessageUnion),
+    /// Sent to every pitched module
+    Tuning(Arc<Tuning>),
+    /// Replaces the frames a wavetable oscillator plays
+    Wavetable(Arc<Wavetable>),
+    /// Replaces the regions a sampler plays
+    Instrument(Arc<Instrument>),
+    /// Silences the module, clearing held notes and any buffered tail
+    Reset,
 }
 
 #[derive(Copy, Clone)]
 pub union ModuleMessageUnion {
+    pub additive: additive::AdditiveUpdate,
     pub allpass: allpass::AllpassUpdate,
     pub analog: analog::AnalogOscillatorUpdate,
     pub butterworth: butterworth::ButterworthUpdate,
+    pub clock: clock::ClockUpdate,
     pub comb: comb::CombUpdate,
     pub chorus: chorus::ChorusUpdate,
     pub delay: delay::DelayUpdate,
     pub envelope: envelope::EnvelopeUpdate,
+    pub fm: fm::FmUpdate,
     pub midi: midi::MidiUpdate,
+    pub noise: noise::NoiseUpdate,
     pub lfo: lfo::LfoUpdate,
     pub reverb: reverb::ReverbUpdate,
+    pub sampler: sampler::SamplerUpdate,
+    pub sequencer: sequencer::SequencerUpdate,
+    pub supersaw: supersaw::SupersawUpdate,
+    pub wavetable: wavetable::WavetableUpdate,
 }
 
 impl std::fmt::Debug for ModuleMessageUnion {
@@ -0,0 +1,198 @@
+use crate::audio::module::{Module, ModuleMessage};
+use crate::audio::rng::Rng;
+
+const DEFAULT_SEED: u32 = 0x9E37_79B9;
+
+#[derive(Clone, Copy, Debug)]
+pub enum NoiseUpdate {
+    /// Restarts the sequence, the same seed always gives the same noise
+    Seed(u32),
+    /// Seconds the smoothed random output takes to glide most of the way to a new value
+    Smoothing(f32),
+    /// Level of the white, pink and brown outputs
+    Gain(f32),
+    /// Level of the stepped and smoothed random outputs
+    RandomDepth(f32),
+}
+
+#[derive(Default)]
+struct Inputs {
+    level: f32,
+    gate: f32,
+}
+
+#[derive(Default)]
+struct Outputs {
+    white: f32,
+    pink: f32,
+    brown: f32,
+    stepped: f32,
+    smoothed: f32,
+}
+
+/// White, pink and brown noise, plus a random value picked on every rising edge of the gate input
+pub struct Noise {
+    id: usize,
+    sample_rate: usize,
+    seed: u32,
+    smoothing: f32,
+    gain: f32,
+    random_depth: f32,
+    input: Inputs,
+    output: Outputs,
+    // State
+    rng: Rng,
+    /// Paul Kellet's pink filter
+    pink: [f32; 7],
+    brown: f32,
+    random: f32,
+    smoothed: f32,
+    last_gate: f32,
+}
+
+impl Noise {
+    pub fn new(id: usize, sample_rate: usize) -> Self {
+        Self {
+            id,
+            sample_rate,
+            seed: DEFAULT_SEED,
+            smoothing: 0.0,
+            gain: 0.0,
+            random_depth: 0.0,
+            input: Inputs::default(),
+            output: Outputs::default(),
+            rng: Rng::new(DEFAULT_SEED),
+            pink: [0.0; 7],
+            brown: 0.0,
+            random: 0.0,
+            smoothed: 0.0,
+            last_gate: 0.0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.rng = Rng::new(self.seed);
+        self.pink = [0.0; 7];
+        self.brown = 0.0;
+        self.random = 0.0;
+        self.smoothed = 0.0;
+        self.last_gate = 0.0;
+        self.output = Outputs::default();
+    }
+}
+
+impl Module for Noise {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn process(&mut self) {
+        let white = self.rng.next_f32() * 2.0 - 1.0;
+
+        let pink = &mut self.pink;
+        pink[0] = 0.99886 * pink[0] + white * 0.0555179;
+        pink[1] = 0.99332 * pink[1] + white * 0.0750759;
+        pink[2] = 0.96900 * pink[2] + white * 0.153852;
+        pink[3] = 0.86650 * pink[3] + white * 0.3104856;
+        pink[4] = 0.55000 * pink[4] + white * 0.5329522;
+        pink[5] = -0.7616 * pink[5] - white * 0.0168980;
+        let pink_value = (pink.iter().sum::<f32>() + white * 0.5362) * 0.11;
+        pink[6] = white * 0.115926;
+
+        // Leaky integration keeps the walk from drifting away
+        self.brown = (self.brown + white * 0.02) / 1.02;
+
+        let level = self.input.level.clamp(0.0, 1.0) * self.gain;
+        self.output.white = white * level;
+        self.output.pink = pink_value * level;
+        self.output.brown = self.brown * 3.5 * level;
+
+        if self.last_gate <= 0.0 && self.input.gate > 0.0 {
+            self.random = self.rng.next_f32() * 2.0 - 1.0;
+        }
+        self.last_gate = self.input.gate;
+
+        let coefficient = if self.smoothing > 0.0 {
+            1.0 - (-1.0 / (self.smoothing * self.sample_rate as f32)).exp()
+        } else {
+            1.0
+        };
+        self.smoothed += (self.random - self.smoothed) * coefficient;
+
+        self.output.stepped = self.random * self.random_depth;
+        self.output.smoothed = self.smoothed * self.random_depth;
+    }
+
+    fn update(&mut self, msg: ModuleMessage) {
+        match msg {
+            ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.noise} {
+                NoiseUpdate::Seed(seed) => {
+                    self.seed = seed;
+                    self.reset();
+                },
+                NoiseUpdate::Smoothing(smoothing) => self.smoothing = smoothing,
+                NoiseUpdate::Gain(gain) => self.gain = gain,
+                NoiseUpdate::RandomDepth(depth) => self.random_depth = depth,
+            },
+            ModuleMessage::Reset => self.reset(),
+            _ => (),
+        }
+    }
+
+    fn get_output(&self, target_output: usize) -> f32 {
+        match target_output {
+            0 => self.output.white,
+            1 => self.output.pink,
+            2 => self.output.brown,
+            3 => self.output.stepped,
+            4 => self.output.smoothed,
+            _ => unreachable!(),
+        }
+    }
+
+    fn modulate(&mut self, component: usize, value: f32) {
+        match component {
+            0 => self.input.level = value,
+            1 => self.input.gate = value,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::module::ModuleMessageUnion;
+
+    fn run(noise: &mut Noise, len: usize) -> Vec<[f32; 5]> {
+        (0..len).map(|i| {
+            noise.modulate(1, (i % 64 < 32) as u8 as f32);
+            noise.process();
+            [0, 1, 2, 3, 4].map(|output| noise.get_output(output))
+        }).collect()
+    }
+
+    fn seeded(seed: u32) -> Noise {
+        let mut noise = Noise::new(0, 48000);
+        noise.modulate(0, 1.0);
+        for update in [NoiseUpdate::Gain(1.0), NoiseUpdate::RandomDepth(1.0), NoiseUpdate::Smoothing(0.001), NoiseUpdate::Seed(seed)] {
+            noise.update(ModuleMessage::ComponentChange(ModuleMessageUnion { noise: update }));
+        }
+        noise
+    }
+
+    #[test]
+    fn same_seed_gives_same_sequence() {
+        let first = run(&mut seeded(1234), 1000);
+        assert_eq!(first, run(&mut seeded(1234), 1000));
+        assert_ne!(first, run(&mut seeded(4321), 1000));
+    }
+
+    #[test]
+    fn reset_restarts_sequence() {
+        let mut noise = seeded(1234);
+        let first = run(&mut noise, 1000);
+        noise.update(ModuleMessage::Reset);
+        assert_eq!(first, run(&mut noise, 1000));
+    }
+}
@@ -10,6 +10,9 @@ pub enum ReverbUpdate {
 #[derive(Default)]
 struct Inputs {
     value: f32,
+    /// Stereo sources keep their sides dry, the tank hears them mixed down
+    left: f32,
+    right: f32,
 }
 
 #[derive(Default)]
@@ -52,6 +55,17 @@ impl Reverb {
             ],
         }
     }
+
+    fn reset(&mut self) {
+        for ap in &mut self.allpass {
+            ap.update(ModuleMessage::Reset);
+        }
+        self.lp.update(ModuleMessage::Reset);
+        for comb in &mut self.combs {
+            comb.update(ModuleMessage::Reset);
+        }
+        self.output = Outputs::default();
+    }
 }
 
 impl Module for Reverb {
@@ -60,13 +74,14 @@ impl Module for Reverb {
     }
 
     fn process(&mut self) {
-        self.output.left = self.input.value * (1.0 - self.wet);
-        self.output.right = self.input.value * (1.0 - self.wet);
+        self.output.left = (self.input.value + self.input.left) * (1.0 - self.wet);
+        self.output.right = (self.input.value + self.input.right) * (1.0 - self.wet);
+        let mono = self.input.value + (self.input.left + self.input.right) / 2.0;
 
         for ap in &mut self.allpass {
             ap.process();
         }
-        self.allpass[0].modulate(0, self.input.value);
+        self.allpass[0].modulate(0, mono);
         self.allpass[1].modulate(0, self.allpass[0].get_output(0));
         self.allpass[2].modulate(0, self.allpass[1].get_output(0));
 
@@ -104,7 +119,9 @@ impl Module for Reverb {
         match msg {
             ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.reverb} {
                 ReverbUpdate::Wet(wet) => self.wet = wet,
-            }
+            },
+            ModuleMessage::Reset => self.reset(),
+            _ => (),
         }
     }
 
@@ -119,6 +136,8 @@ impl Module for Reverb {
     fn modulate(&mut self, component: usize, value: f32) {
         match component {
             0 => self.input.value = value,
+            1 => self.input.left = value,
+            2 => self.input.right = value,
             _ => unreachable!(),
         }
     }
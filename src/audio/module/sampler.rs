@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use crate::audio::module::{Module, ModuleMessage, ModuleMessageUnion};
+use crate::audio::module::envelope::{Envelope, EnvelopeUpdate};
+use crate::audio::sfz::{Instrument, LoopMode};
+
+/// Regions sounding at once, the oldest is cut when another starts
+const MAX_LAYERS: usize = 8;
+
+#[derive(Clone, Copy, Debug)]
+pub enum SamplerUpdate {
+    Gain(f32),
+}
+
+#[derive(Default)]
+struct Inputs {
+    gate: f32,
+    frequency: f32,
+    velocity: f32,
+    /// MIDI key number, regions are picked by key while the tuned frequency sets the pitch
+    key: f32,
+}
+
+#[derive(Default)]
+struct Outputs {
+    value: f32,
+}
+
+/// A region playing, with its own amp envelope
+struct Layer {
+    region: usize,
+    position: f64,
+    envelope: Envelope,
+    velocity: f32,
+    held: bool,
+}
+
+/// Plays the regions of an SFZ instrument that match each note, the instrument arrives as `ModuleMessage::Instrument`
+pub struct Sampler {
+    id: usize,
+    sample_rate: usize,
+    gain: f32,
+    instrument: Option<Arc<Instrument>>,
+    input: Inputs,
+    output: Outputs,
+    // State
+    layers: Vec<Layer>,
+    last_gate: f32,
+}
+
+impl Sampler {
+    pub fn new(id: usize, sample_rate: usize) -> Self {
+        Self {
+            id,
+            sample_rate,
+            gain: 1.0,
+            instrument: None,
+            input: Inputs::default(),
+            output: Outputs::default(),
+            layers: Vec::with_capacity(MAX_LAYERS),
+            last_gate: 0.0,
+        }
+    }
+
+    /// Whatever is still sounding releases, and every region matching the note and velocity starts
+    fn trigger(&mut self) {
+        let Some(instrument) = &self.instrument else {
+            return;
+        };
+        let key = self.input.key.round().clamp(0.0, 127.0) as u8;
+        let velocity = (self.input.velocity * 127.0).round().clamp(0.0, 127.0) as u8;
+
+        for layer in &mut self.layers {
+            layer.held = false;
+        }
+
+        for (index, region) in instrument.regions.iter().enumerate().filter(|(_, region)| region.contains(key, velocity)) {
+            if self.layers.len() == MAX_LAYERS {
+                self.layers.remove(0);
+            }
+
+            let mut envelope = Envelope::new(self.id);
+            for update in [
+                EnvelopeUpdate::Attack(region.attack),
+                EnvelopeUpdate::Decay(region.decay),
+                EnvelopeUpdate::Sustain(region.sustain),
+                EnvelopeUpdate::Release(region.release),
+            ] {
+                envelope.update(ModuleMessage::ComponentChange(ModuleMessageUnion {envelope: update}));
+            }
+
+            self.layers.push(Layer {
+                region: index,
+                position: 0.0,
+                envelope,
+                velocity: self.input.velocity,
+                held: true,
+            });
+        }
+    }
+}
+
+impl Module for Sampler {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn process(&mut self) {
+        if self.last_gate <= 0.0 && self.input.gate > 0.0 {
+            self.trigger();
+        } else if self.last_gate > 0.0 && self.input.gate <= 0.0 {
+            for layer in &mut self.layers {
+                layer.held = false;
+            }
+        }
+        self.last_gate = self.input.gate;
+
+        let Some(instrument) = &self.instrument else {
+            self.output.value = 0.0;
+            return;
+        };
+
+        let pitch = self.input.frequency as f64 * 127.0;
+        let sample_rate = self.sample_rate as f64;
+        let mut value = 0.0;
+
+        self.layers.retain_mut(|layer| {
+            let region = &instrument.regions[layer.region];
+            let data = &region.sample.data;
+
+            let index = layer.position as usize;
+            if index + 1 >= data.len() {
+                return false;
+            }
+
+            // A one shot ignores the note ending and plays through
+            let gate = if layer.held || region.loop_mode == LoopMode::OneShot {1.0} else {0.0};
+            layer.envelope.modulate(0, gate);
+            layer.envelope.modulate(1, layer.velocity);
+            layer.envelope.process();
+            let level = layer.envelope.get_output(0);
+            if gate == 0.0 && level <= 0.0 {
+                return false;
+            }
+
+            let t = (layer.position - index as f64) as f32;
+            let raw = data[index] + (data[index + 1] - data[index]) * t;
+            value += raw * level * region.gain;
+
+            let ratio = 2.0_f64.powf((pitch - region.root as f64) / 12.0) * region.sample.sample_rate as f64 / sample_rate;
+            layer.position += ratio;
+
+            let looping = match region.loop_mode {
+                LoopMode::Continuous => true,
+                LoopMode::Sustain => layer.held,
+                LoopMode::NoLoop | LoopMode::OneShot => false,
+            };
+            if looping && region.loop_end > region.loop_start && layer.position > region.loop_end as f64 {
+                layer.position -= (region.loop_end - region.loop_start) as f64;
+            }
+            true
+        });
+
+        self.output.value = value * self.gain;
+    }
+
+    fn update(&mut self, msg: ModuleMessage) {
+        match msg {
+            ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.sampler} {
+                SamplerUpdate::Gain(gain) => self.gain = gain,
+            },
+            // Layers point into the old instrument's regions
+            ModuleMessage::Instrument(instrument) => {
+                self.instrument = Some(instrument);
+                self.layers.clear();
+            },
+            ModuleMessage::Reset => self.layers.clear(),
+            _ => (),
+        }
+    }
+
+    fn get_output(&self, target_output: usize) -> f32 {
+        match target_output {
+            0 => self.output.value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn modulate(&mut self, component: usize, value: f32) {
+        match component {
+            0 => self.input.gate = value,
+            1 => self.input.frequency = value,
+            2 => self.input.velocity = value,
+            3 => self.input.key = value,
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use crate::audio::module::{Module, ModuleMessage};
+use crate::audio::module::clock::{Division, Feel, NoteDivision};
+use crate::audio::rng::Rng;
+use crate::audio::tuning::Tuning;
+
+pub const MAX_STEPS: usize = 64;
+pub const PATTERN_COUNT: usize = 8;
+
+/// Part of a step the gate stays open for, unless tied into the next step
+const GATE_LENGTH: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub note: u8,
+    pub gate: bool,
+    pub velocity: u8,
+    /// Holds the gate into the next step without retriggering
+    pub tie: bool,
+    /// Chance the step plays, from 0 to 1
+    pub probability: f32,
+}
+
+impl Default for Step {
+    fn default() -> Self {
+        Self {
+            note: 60,
+            gate: false,
+            velocity: 100,
+            tie: false,
+            probability: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Pattern {
+    pub steps: [Step; MAX_STEPS],
+    pub length: usize,
+}
+
+impl Default for Pattern {
+    fn default() -> Self {
+        Self {
+            steps: [Step::default(); MAX_STEPS],
+            length: 16,
+        }
+    }
+}
+
+/// Step and length changes apply to the pattern that is playing
+#[derive(Clone, Copy, Debug)]
+pub enum SequencerUpdate {
+    Pattern(usize),
+    Length(usize),
+    Step(usize, Step),
+    Division(NoteDivision),
+    /// How far every second step is pushed back, from 0 to 0.5 of a step
+    Swing(f32),
+}
+
+#[derive(Default)]
+struct Inputs {
+    position: f32,
+    running: f32,
+}
+
+#[derive(Default)]
+struct Outputs {
+    gate: f32,
+    note: f32,
+    velocity: f32,
+    /// The step's MIDI note, before tuning
+    key: f32,
+}
+
+/// Plays patterns of steps against the clock, with the same gate/note/velocity outputs as a `Midi` voice
+/// followed by the untuned MIDI note
+pub struct Sequencer {
+    id: usize,
+    patterns: [Pattern; PATTERN_COUNT],
+    pattern: usize,
+    division: NoteDivision,
+    swing: f32,
+    tuning: Arc<Tuning>,
+    input: Inputs,
+    output: Outputs,
+    // State
+    last_step: Option<i64>,
+    retrigger: bool,
+    tied: bool,
+    rng: Rng,
+}
+
+impl Sequencer {
+    pub fn new(id: usize) -> Self {
+        Self {
+            id,
+            patterns: [Pattern::default(); PATTERN_COUNT],
+            pattern: 0,
+            division: NoteDivision::new(Division::Sixteenth, Feel::Straight),
+            swing: 0.0,
+            tuning: Arc::new(Tuning::equal()),
+            input: Inputs::default(),
+            output: Outputs::default(),
+            last_step: None,
+            retrigger: false,
+            tied: false,
+            rng: Rng::new(0x6C07_8965),
+        }
+    }
+
+    /// Closes the gate, the next step starts fresh
+    fn stop(&mut self) {
+        self.output.gate = 0.0;
+        self.last_step = None;
+        self.retrigger = false;
+        self.tied = false;
+    }
+
+    fn start_step(&mut self, step: i64) {
+        let pattern = &self.patterns[self.pattern];
+        let current = pattern.steps[step.rem_euclid(pattern.length as i64) as usize];
+
+        if current.gate && self.rng.next_f32() < current.probability {
+            self.output.note = self.tuning.note(current.note);
+            self.output.key = current.note as f32;
+            self.output.velocity = current.velocity as f32 / 127.0;
+            if !(self.tied && self.output.gate > 0.0) {
+                self.output.gate = 0.0;
+                self.retrigger = true;
+            }
+            self.tied = current.tie;
+        } else {
+            self.output.gate = 0.0;
+            self.retrigger = false;
+            self.tied = false;
+        }
+    }
+}
+
+impl Module for Sequencer {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn process(&mut self) {
+        if self.input.running < 0.5 {
+            self.stop();
+            return;
+        }
+
+        if self.retrigger {
+            self.output.gate = 1.0;
+            self.retrigger = false;
+        }
+
+        // Steps come in pairs, the first one is lengthened by the swing and the second one shortened
+        let length = self.division.beats();
+        let pair = (self.input.position / (length * 2.0)).floor();
+        let offset = self.input.position - pair * length * 2.0;
+        let swung = length * (1.0 + self.swing);
+
+        let (step, phase) = if offset < swung {
+            (pair as i64 * 2, offset / swung)
+        } else {
+            (pair as i64 * 2 + 1, (offset - swung) / (length * (1.0 - self.swing)))
+        };
+
+        if self.last_step != Some(step) {
+            self.last_step = Some(step);
+            self.start_step(step);
+        } else if phase >= GATE_LENGTH && !self.tied {
+            self.output.gate = 0.0;
+        }
+    }
+
+    fn update(&mut self, msg: ModuleMessage) {
+        match msg {
+            ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.sequencer} {
+                SequencerUpdate::Pattern(pattern) => self.pattern = pattern.min(PATTERN_COUNT - 1),
+                SequencerUpdate::Length(length) => self.patterns[self.pattern].length = length.clamp(1, MAX_STEPS),
+                SequencerUpdate::Step(index, step) => {
+                    if let Some(current) = self.patterns[self.pattern].steps.get_mut(index) {
+                        *current = step;
+                    }
+                },
+                SequencerUpdate::Division(division) => self.division = division,
+                SequencerUpdate::Swing(swing) => self.swing = swing.clamp(0.0, 0.5),
+            },
+            ModuleMessage::Tuning(tuning) => self.tuning = tuning,
+            ModuleMessage::Reset => self.stop(),
+            _ => (),
+        }
+    }
+
+    fn get_output(&self, target_output: usize) -> f32 {
+        match target_output {
+            0 => self.output.gate,
+            1 => self.output.note,
+            2 => self.output.velocity,
+            3 => self.output.key,
+            _ => unreachable!(),
+        }
+    }
+
+    fn modulate(&mut self, component: usize, value: f32) {
+        match component {
+            0 => self.input.position = value,
+            1 => self.input.running = value,
+            _ => unreachable!(),
+        }
+    }
+}
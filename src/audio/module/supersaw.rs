@@ -0,0 +1,233 @@
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+
+use crate::audio::module::{Module, ModuleMessage};
+use crate::audio::module::analog::WaveShape;
+use crate::audio::rng::Rng;
+
+pub const MAX_SUPERSAW_VOICES: usize = 9;
+/// Semitones the outermost copies sit from the centre at full detune
+const MAX_DETUNE: f32 = 1.0;
+
+/// How the detune amount maps onto the spread of the copies
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetuneCurve {
+    Linear,
+    /// Finer control of small amounts, the classic supersaw response
+    Exponential,
+}
+
+impl DetuneCurve {
+    pub const ALL: [DetuneCurve; 2] = [DetuneCurve::Linear, DetuneCurve::Exponential];
+}
+
+impl std::fmt::Display for DetuneCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DetuneCurve::Linear => write!(f, "Linear detune"),
+            DetuneCurve::Exponential => write!(f, "Exponential detune"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum SupersawUpdate {
+    Shape(WaveShape),
+    /// Copies playing at once, the first one sits in the centre and the rest spread evenly either side
+    Voices(usize),
+    /// From 0, every copy in tune, to 1
+    Detune(f32),
+    DetuneCurve(DetuneCurve),
+    /// From only the centre copy at 0 to only the side copies at 1
+    Mix(f32),
+    /// How far the side copies are panned out, the most detuned ones furthest
+    Spread(f32),
+    /// Start each copy at a random point of its cycle when a note begins
+    RandomPhase(bool),
+    Gain(f32),
+}
+
+#[derive(Default)]
+struct Inputs {
+    level: f32,
+    frequency: f32,
+}
+
+#[derive(Default)]
+struct Outputs {
+    left: f32,
+    right: f32,
+}
+
+/// Several detuned copies of one wave shape, spread across the stereo field
+pub struct Supersaw {
+    id: usize,
+    sample_rate: usize,
+    shape: WaveShape,
+    voice_count: usize,
+    detune: f32,
+    curve: DetuneCurve,
+    mix: f32,
+    spread: f32,
+    random_phase: bool,
+    gain: f32,
+    input: Inputs,
+    output: Outputs,
+    // State
+    phases: [f32; MAX_SUPERSAW_VOICES],
+    /// Frequency ratio and left/right gain of each copy, worked out when the settings change
+    ratios: [f32; MAX_SUPERSAW_VOICES],
+    pans: [(f32, f32); MAX_SUPERSAW_VOICES],
+    last_level: f32,
+    rng: Rng,
+}
+
+impl Supersaw {
+    pub fn new(id: usize, sample_rate: usize) -> Self {
+        let mut supersaw = Self {
+            id,
+            sample_rate,
+            shape: WaveShape::Saw,
+            voice_count: 7,
+            detune: 0.3,
+            curve: DetuneCurve::Linear,
+            mix: 0.5,
+            spread: 0.5,
+            random_phase: true,
+            gain: 0.0,
+            input: Inputs::default(),
+            output: Outputs::default(),
+            phases: [0.0; MAX_SUPERSAW_VOICES],
+            ratios: [1.0; MAX_SUPERSAW_VOICES],
+            pans: [(0.0, 0.0); MAX_SUPERSAW_VOICES],
+            last_level: 0.0,
+            rng: Rng::new(0x7A3D_1F05),
+        };
+        supersaw.layout();
+        supersaw
+    }
+
+    /// Where a copy sits from -1 to 1, the side copies are spaced evenly across the whole range.
+    /// With an odd number of them the middle one lands on the centre, so a lone side copy plays in tune
+    fn position(voice: usize, voice_count: usize) -> f32 {
+        let sides = voice_count - 1;
+        if voice == 0 || sides < 2 {
+            return 0.0;
+        }
+        -1.0 + 2.0 * (voice - 1) as f32 / (sides - 1) as f32
+    }
+
+    fn layout(&mut self) {
+        let detune = match self.curve {
+            DetuneCurve::Linear => self.detune,
+            DetuneCurve::Exponential => self.detune * self.detune,
+        } * MAX_DETUNE;
+
+        // Side copies share their half of the mix, so adding copies doesn't make it louder
+        let sides = self.voice_count - 1;
+        let side_gain = if sides > 0 {self.mix / (sides as f32).sqrt()} else {0.0};
+        let centre_gain = if sides > 0 {1.0 - self.mix} else {1.0};
+
+        for voice in 0..self.voice_count {
+            let position = Supersaw::position(voice, self.voice_count);
+            self.ratios[voice] = 2.0_f32.powf(position * detune / 12.0);
+
+            // Equal power, scaled so a copy in the middle plays at full level on both sides
+            let gain = (if voice == 0 {centre_gain} else {side_gain}) * SQRT_2;
+            let angle = (position * self.spread + 1.0) * FRAC_PI_4;
+            self.pans[voice] = (angle.cos() * gain, angle.sin() * gain);
+        }
+    }
+
+    fn restart(&mut self) {
+        for phase in &mut self.phases {
+            *phase = if self.random_phase {self.rng.next_f32()} else {0.0};
+        }
+    }
+}
+
+impl Module for Supersaw {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn process(&mut self) {
+        let level = self.input.level.clamp(0.0, 1.0);
+        if self.last_level <= 0.0 && level > 0.0 {
+            self.restart();
+        }
+        self.last_level = level;
+
+        let frequency = self.input.frequency.clamp(0.0, 1.0);
+        let frequency = 2.0_f32.powf(127.0 / 12.0 * frequency) * 8.176; // C-1 (midi note 0)
+        let phase_increment = frequency / self.sample_rate as f32;
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+        for voice in 0..self.voice_count {
+            let increment = phase_increment * self.ratios[voice];
+            let raw = self.shape.band_limited(self.phases[voice], increment, 0.5);
+            self.phases[voice] = (self.phases[voice] + increment) % 1.0;
+
+            let (left_gain, right_gain) = self.pans[voice];
+            left += raw * left_gain;
+            right += raw * right_gain;
+        }
+
+        self.output.left = left * level * self.gain;
+        self.output.right = right * level * self.gain;
+    }
+
+    fn update(&mut self, msg: ModuleMessage) {
+        match msg {
+            ModuleMessage::ComponentChange(msg_union) => {
+                match unsafe {msg_union.supersaw} {
+                    SupersawUpdate::Shape(shape) => self.shape = shape,
+                    SupersawUpdate::Voices(count) => self.voice_count = count.clamp(1, MAX_SUPERSAW_VOICES),
+                    SupersawUpdate::Detune(detune) => self.detune = detune,
+                    SupersawUpdate::DetuneCurve(curve) => self.curve = curve,
+                    SupersawUpdate::Mix(mix) => self.mix = mix,
+                    SupersawUpdate::Spread(spread) => self.spread = spread,
+                    SupersawUpdate::RandomPhase(random) => self.random_phase = random,
+                    SupersawUpdate::Gain(gain) => self.gain = gain,
+                }
+                self.layout();
+            },
+            _ => (),
+        }
+    }
+
+    fn get_output(&self, target_output: usize) -> f32 {
+        match target_output {
+            0 => self.output.left,
+            1 => self.output.right,
+            _ => unreachable!(),
+        }
+    }
+
+    fn modulate(&mut self, component: usize, value: f32) {
+        match component {
+            0 => self.input.level = value,
+            1 => self.input.frequency = value,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn side_copies_are_symmetric() {
+        for voice_count in 1..=MAX_SUPERSAW_VOICES {
+            let positions: Vec<f32> = (0..voice_count).map(|voice| Supersaw::position(voice, voice_count)).collect();
+            assert_eq!(positions[0], 0.0);
+            assert!(positions.iter().sum::<f32>().abs() < 1e-6, "{voice_count} voices: {positions:?}");
+            if voice_count > 2 {
+                assert_eq!(positions.iter().copied().fold(f32::MIN, f32::max), 1.0);
+                assert_eq!(positions.iter().copied().fold(f32::MAX, f32::min), -1.0);
+            }
+        }
+        assert_eq!((0..4).map(|voice| Supersaw::position(voice, 4)).collect::<Vec<_>>(), [0.0, -1.0, 0.0, 1.0]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+
+use crate::audio::module::{Module, ModuleMessage};
+use crate::audio::wavetable::Wavetable;
+
+#[derive(Clone, Copy, Debug)]
+pub enum WavetableUpdate {
+    /// Where in the table to play, from the first frame at 0 to the last at 1
+    Position(f32),
+    Gain(f32),
+}
+
+#[derive(Default)]
+struct Inputs {
+    level: f32,
+    frequency: f32,
+    position: f32,
+}
+
+#[derive(Default)]
+struct Outputs {
+    value: f32,
+}
+
+/// Plays single cycle frames from a wavetable, the table itself arrives as `ModuleMessage::Wavetable`
+pub struct WavetableOscillator {
+    id: usize,
+    sample_rate: usize,
+    table: Arc<Wavetable>,
+    position: f32,
+    gain: f32,
+    current_phase: f32,
+    input: Inputs,
+    output: Outputs,
+}
+
+impl WavetableOscillator {
+    pub fn new(id: usize, sample_rate: usize) -> Self {
+        Self {
+            id,
+            sample_rate,
+            table: Arc::new(Wavetable::saw()),
+            position: 0.0,
+            gain: 0.0,
+            current_phase: 0.0,
+            input: Inputs::default(),
+            output: Outputs::default(),
+        }
+    }
+}
+
+impl Module for WavetableOscillator {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn process(&mut self) {
+        let frequency = self.input.frequency.clamp(0.0, 1.0);
+        let frequency = 2.0_f32.powf(127.0 / 12.0 * frequency) * 8.176; // C-1 (midi note 0)
+        let position = (self.position + self.input.position).clamp(0.0, 1.0);
+        let level = self.input.level.clamp(0.0, 1.0);
+
+        let phase_increment = frequency / self.sample_rate as f32;
+        let raw = self.table.sample(position, self.current_phase, phase_increment);
+        self.current_phase = (self.current_phase + phase_increment) % 1.0;
+
+        self.output.value = raw * level * self.gain;
+
+        self.input.position = 0.0;
+    }
+
+    fn update(&mut self, msg: ModuleMessage) {
+        match msg {
+            ModuleMessage::ComponentChange(msg_union) => match unsafe {msg_union.wavetable} {
+                WavetableUpdate::Position(position) => self.position = position,
+                WavetableUpdate::Gain(gain) => self.gain = gain,
+            },
+            ModuleMessage::Wavetable(table) => self.table = table,
+            _ => (),
+        }
+    }
+
+    fn get_output(&self, target_output: usize) -> f32 {
+        match target_output {
+            0 => self.output.value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn modulate(&mut self, component: usize, value: f32) {
+        match component {
+            0 => self.input.level = value,
+            1 => self.input.frequency = value,
+            2 => self.input.position += value,
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use crate::audio::module::midi::MidiUpdate;
+
+#[derive(Clone, Copy, Debug)]
+pub struct SequenceEvent {
+    /// Seconds from the start at normal speed
+    pub time: f64,
+    pub update: MidiUpdate,
+}
+
+/// A loaded MIDI file, sorted by time
+#[derive(Debug)]
+pub struct Sequence {
+    pub events: Vec<SequenceEvent>,
+    pub length: f64,
+}
+
+#[derive(Clone, Debug)]
+pub enum PlayerCommand {
+    Load(Arc<Sequence>),
+    Play,
+    Stop,
+    Loop(bool),
+    /// Seconds from the start
+    Seek(f64),
+    TempoScale(f32),
+}
+
+/// Plays a sequence against the audio clock, one sample at a time
+pub struct Player {
+    sequence: Option<Arc<Sequence>>,
+    playing: bool,
+    looping: bool,
+    tempo_scale: f32,
+    position: f64,
+    next_event: usize,
+    held: [bool; 128],
+    sustain: bool,
+}
+
+impl Player {
+    pub fn new() -> Self {
+        Self {
+            sequence: None,
+            playing: false,
+            looping: false,
+            tempo_scale: 1.0,
+            position: 0.0,
+            next_event: 0,
+            held: [false; 128],
+            sustain: false,
+        }
+    }
+
+    pub fn position(&self) -> f64 {
+        self.position
+    }
+
+    pub fn update(&mut self, command: PlayerCommand, emit: impl FnMut(MidiUpdate)) {
+        match command {
+            PlayerCommand::Load(sequence) => {
+                self.release_all(emit);
+                self.sequence = Some(sequence);
+                self.playing = false;
+                self.position = 0.0;
+                self.next_event = 0;
+            },
+            PlayerCommand::Play => self.playing = self.sequence.is_some(),
+            PlayerCommand::Stop => {
+                self.playing = false;
+                self.release_all(emit);
+            },
+            PlayerCommand::Loop(looping) => self.looping = looping,
+            PlayerCommand::Seek(position) => {
+                self.release_all(emit);
+                self.seek(position);
+            },
+            PlayerCommand::TempoScale(tempo_scale) => self.tempo_scale = tempo_scale.max(0.01),
+        }
+    }
+
+    /// Advances by one sample, sending every event that became due
+    pub fn process(&mut self, sample_rate: usize, mut emit: impl FnMut(MidiUpdate)) {
+        if !self.playing {
+            return;
+        }
+        let Some(sequence) = self.sequence.clone() else {
+            return;
+        };
+
+        self.position += self.tempo_scale as f64 / sample_rate as f64;
+
+        while let Some(event) = sequence.events.get(self.next_event) {
+            if event.time > self.position {
+                break;
+            }
+            self.track(event.update);
+            emit(event.update);
+            self.next_event += 1;
+        }
+
+        if self.position >= sequence.length {
+            self.release_all(&mut emit);
+            if self.looping {
+                self.seek(0.0);
+            } else {
+                self.playing = false;
+            }
+        }
+    }
+
+    fn seek(&mut self, position: f64) {
+        self.position = position.max(0.0);
+        self.next_event = match &self.sequence {
+            Some(sequence) => sequence.events.partition_point(|event| event.time < self.position),
+            None => 0,
+        };
+    }
+
+    fn track(&mut self, update: MidiUpdate) {
+        match update {
+            MidiUpdate::KeyPress(note, _) => self.held[note as usize] = true,
+            MidiUpdate::KeyRelease(note) => self.held[note as usize] = false,
+            MidiUpdate::PedalPress => self.sustain = true,
+            MidiUpdate::PedalRelease => self.sustain = false,
+            _ => (),
+        }
+    }
+
+    /// Releases notes and the pedal left on by the sequence
+    fn release_all(&mut self, mut emit: impl FnMut(MidiUpdate)) {
+        for note in 0..128 {
+            if self.held[note] {
+                emit(MidiUpdate::KeyRelease(note as u8));
+                self.held[note] = false;
+            }
+        }
+        if self.sustain {
+            emit(MidiUpdate::PedalRelease);
+            self.sustain = false;
+        }
+    }
+}
@@ -0,0 +1,31 @@
+/// Small xorshift generator, cheap enough to call every sample
+#[derive(Clone, Copy, Debug)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    /// The seed must not be zero
+    pub fn new(seed: u32) -> Self {
+        Self {
+            state: seed.max(1),
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+
+    /// Uniform in 0..1
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+
+    /// Uniform in 0..len
+    pub fn below(&mut self, len: usize) -> usize {
+        ((self.next_u32() as u64 * len as u64) >> 32) as usize
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use rodio::{Decoder, Source};
+
+const NOTE_NAMES: [&str; 7] = ["c", "d", "e", "f", "g", "a", "b"];
+const NOTE_SEMITONES: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+/// A WAV file mixed down to mono
+pub struct Sample {
+    pub data: Vec<f32>,
+    pub sample_rate: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoopMode {
+    /// Plays to the end of the sample or until released
+    NoLoop,
+    /// Plays to the end of the sample whatever the gate does
+    OneShot,
+    Continuous,
+    /// Loops while the gate is held, then plays on through the end
+    Sustain,
+}
+
+/// One `<region>` with its `<global>`, `<master>` and `<group>` opcodes applied
+pub struct Region {
+    pub sample: Arc<Sample>,
+    pub lokey: u8,
+    pub hikey: u8,
+    pub lovel: u8,
+    pub hivel: u8,
+    pub root: u8,
+    pub loop_mode: LoopMode,
+    pub loop_start: usize,
+    pub loop_end: usize,
+    /// Linear gain from the `volume` opcode
+    pub gain: f32,
+    /// `ampeg_attack`, `ampeg_decay` and `ampeg_release` in seconds
+    pub attack: f32,
+    pub decay: f32,
+    pub release: f32,
+    /// `ampeg_sustain` from 0 to 1
+    pub sustain: f32,
+}
+
+impl Region {
+    pub fn contains(&self, key: u8, velocity: u8) -> bool {
+        (self.lokey..=self.hikey).contains(&key) && (self.lovel..=self.hivel).contains(&velocity)
+    }
+}
+
+/// The regions of an SFZ file, with their samples loaded
+pub struct Instrument {
+    pub regions: Vec<Region>,
+}
+
+impl std::fmt::Debug for Instrument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Instrument({} regions)", self.regions.len())
+    }
+}
+
+impl Instrument {
+    /// Sample paths are relative to the SFZ file, after any `default_path` in `<control>`
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+
+        // Headers can share a line with opcodes, and values like sample names can contain spaces
+        let text: String = text.lines()
+            .map(|line| line.split("//").next().unwrap_or(""))
+            .collect::<Vec<_>>()
+            .join("\n")
+            .replace('<', "\n<")
+            .replace('>', ">\n");
+
+        let mut default_path = String::new();
+        let mut global = HashMap::new();
+        let mut master = HashMap::new();
+        let mut group = HashMap::new();
+        let mut region: Option<HashMap<String, String>> = None;
+        let mut header = String::new();
+        let mut opcode: Option<String> = None;
+
+        let mut samples = HashMap::new();
+        let mut regions = Vec::new();
+        let mut finish = |region: Option<HashMap<String, String>>, global: &HashMap<String, String>, master: &HashMap<String, String>, group: &HashMap<String, String>, default_path: &str| {
+            if let Some(region) = region {
+                let mut opcodes = global.clone();
+                opcodes.extend(master.clone());
+                opcodes.extend(group.clone());
+                opcodes.extend(region);
+                if let Some(region) = build_region(&opcodes, directory, default_path, &mut samples)? {
+                    regions.push(region);
+                }
+            }
+            Ok::<(), String>(())
+        };
+
+        for token in text.split_whitespace() {
+            if token.starts_with('<') && token.ends_with('>') {
+                finish(region.take(), &global, &master, &group, &default_path)?;
+                header = token.trim_matches(|c| c == '<' || c == '>').to_string();
+                match header.as_str() {
+                    "global" => {
+                        global.clear();
+                        master.clear();
+                        group.clear();
+                    },
+                    "master" => {
+                        master.clear();
+                        group.clear();
+                    },
+                    "group" => group.clear(),
+                    "region" => region = Some(HashMap::new()),
+                    _ => (),
+                }
+                opcode = None;
+                continue;
+            }
+
+            let scope = match header.as_str() {
+                "control" => None,
+                "global" => Some(&mut global),
+                "master" => Some(&mut master),
+                "group" => Some(&mut group),
+                "region" => region.as_mut(),
+                _ => continue,
+            };
+
+            match token.split_once('=') {
+                Some((name, value)) => {
+                    match scope {
+                        Some(scope) => {
+                            scope.insert(name.to_string(), value.to_string());
+                        },
+                        None if name == "default_path" => default_path = value.to_string(),
+                        None => (),
+                    }
+                    opcode = Some(name.to_string());
+                },
+                // A value with spaces in it
+                None => if let (Some(scope), Some(name)) = (scope, &opcode) {
+                    if let Some(value) = scope.get_mut(name) {
+                        value.push(' ');
+                        value.push_str(token);
+                    }
+                },
+            }
+        }
+        finish(region.take(), &global, &master, &group, &default_path)?;
+
+        if regions.is_empty() {
+            return Err(String::from("No regions in file"));
+        }
+        Ok(Self {
+            regions,
+        })
+    }
+}
+
+fn build_region(opcodes: &HashMap<String, String>, directory: &Path, default_path: &str, samples: &mut HashMap<String, Arc<Sample>>) -> Result<Option<Region>, String> {
+    let Some(name) = opcodes.get("sample") else {
+        return Ok(None);
+    };
+    let path = directory.join(format!("{default_path}{name}").replace('\\', "/"));
+    let path = path.to_string_lossy().to_string();
+
+    let sample = match samples.get(&path) {
+        Some(sample) => sample.clone(),
+        None => {
+            let sample = Arc::new(load_sample(&path).map_err(|err| format!("{path}: {err}"))?);
+            samples.insert(path, sample.clone());
+            sample
+        },
+    };
+
+    let key = |name: &str| opcodes.get(name).and_then(|value| parse_key(value));
+    let number = |name: &str| opcodes.get(name).and_then(|value| value.parse::<f32>().ok());
+
+    let (lokey, hikey, root) = match key("key") {
+        Some(key) => (key, key, key),
+        None => (key("lokey").unwrap_or(0), key("hikey").unwrap_or(127), key("pitch_keycenter").unwrap_or(60)),
+    };
+    let loop_mode = match opcodes.get("loop_mode").map(String::as_str) {
+        Some("one_shot") => LoopMode::OneShot,
+        Some("loop_continuous") => LoopMode::Continuous,
+        Some("loop_sustain") => LoopMode::Sustain,
+        _ => LoopMode::NoLoop,
+    };
+    let last = sample.data.len().saturating_sub(1);
+    let loop_end = number("loop_end").map_or(last, |end| (end as usize).min(last));
+    let loop_start = number("loop_start").map_or(0, |start| (start as usize).min(loop_end));
+
+    Ok(Some(Region {
+        sample,
+        lokey,
+        hikey,
+        lovel: number("lovel").map_or(0, |velocity| velocity as u8),
+        hivel: number("hivel").map_or(127, |velocity| velocity as u8),
+        root: key("pitch_keycenter").unwrap_or(root),
+        loop_mode,
+        loop_start,
+        loop_end,
+        gain: 10.0_f32.powf(number("volume").unwrap_or(0.0) / 20.0),
+        attack: number("ampeg_attack").unwrap_or(0.0),
+        decay: number("ampeg_decay").unwrap_or(0.0),
+        release: number("ampeg_release").unwrap_or(0.001),
+        sustain: number("ampeg_sustain").unwrap_or(100.0) / 100.0,
+    }))
+}
+
+fn load_sample(path: &str) -> Result<Sample, String> {
+    let file = File::open(path).map_err(|err| err.to_string())?;
+    let decoder = Decoder::new_wav(BufReader::new(file)).map_err(|err| err.to_string())?;
+    let channels = decoder.channels().max(1) as usize;
+    let sample_rate = decoder.sample_rate();
+
+    let interleaved: Vec<f32> = decoder.map(|sample| sample as f32 / i16::MAX as f32).collect();
+    let data = interleaved.chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    Ok(Sample {
+        data,
+        sample_rate,
+    })
+}
+
+/// A MIDI note number, or a name like `c#4` where `c4` is 60
+fn parse_key(value: &str) -> Option<u8> {
+    if let Ok(key) = value.parse::<i32>() {
+        return u8::try_from(key).ok().filter(|key| *key <= 127);
+    }
+
+    let value = value.to_lowercase();
+    let letter = NOTE_NAMES.iter().position(|name| value.starts_with(name))?;
+    let rest = &value[1..];
+    let (accidental, octave) = match rest.chars().next() {
+        Some('#') => (1, &rest[1..]),
+        Some('b') => (-1, &rest[1..]),
+        _ => (0, rest),
+    };
+    let key = (octave.parse::<i32>().ok()? + 1) * 12 + NOTE_SEMITONES[letter] + accidental;
+    u8::try_from(key).ok().filter(|key| *key <= 127)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A directory for one test's files, removed when dropped
+    struct TestDir(PathBuf);
+
+    impl TestDir {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("cav-synth-sfz-{}-{name}", std::process::id()));
+            std::fs::create_dir_all(path.join("samples")).unwrap();
+            Self(path)
+        }
+
+        fn write(&self, name: &str, contents: &[u8]) -> String {
+            let path = self.0.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path.to_string_lossy().to_string()
+        }
+    }
+
+    impl Drop for TestDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// A mono 16-bit WAV file
+    fn wav(len: usize) -> Vec<u8> {
+        let data_len = len as u32 * 2;
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&44100u32.to_le_bytes());
+        bytes.extend_from_slice(&88200u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        for i in 0..len {
+            bytes.extend_from_slice(&((i as i16) * 100).to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn regions_inherit_header_opcodes() {
+        let dir = TestDir::new("inherit");
+        dir.write("samples/a.wav", &wav(100));
+        dir.write("samples/b sample.wav", &wav(50));
+        let path = dir.write("test.sfz", b"
+            <control> default_path=samples/
+            <global> ampeg_release=0.5 volume=-6
+            <group> lokey=40 hikey=50 pitch_keycenter=45 loop_mode=one_shot
+            <region> sample=a.wav
+            <region> sample=a.wav lokey=c4 hikey=c#4 volume=0 // overrides the global volume
+            <group> key=70
+            <region> sample=b sample.wav
+            <master> ampeg_attack=0.1
+            <region> sample=a.wav
+            <global>
+            <region> sample=a.wav
+        ");
+
+        let instrument = Instrument::load(&path).unwrap();
+        let regions = &instrument.regions;
+        assert_eq!(regions.len(), 5);
+
+        let first = &regions[0];
+        assert_eq!((first.lokey, first.hikey, first.root), (40, 50, 45));
+        assert_eq!(first.loop_mode, LoopMode::OneShot);
+        assert_eq!(first.release, 0.5);
+        assert!((first.gain - 0.501).abs() < 1e-3);
+        assert_eq!(first.loop_end, 99);
+
+        let second = &regions[1];
+        assert_eq!((second.lokey, second.hikey, second.root), (60, 61, 45));
+        assert_eq!(second.gain, 1.0);
+        assert!(Arc::ptr_eq(&first.sample, &second.sample));
+
+        // A new group drops the previous group's opcodes
+        let third = &regions[2];
+        assert_eq!((third.lokey, third.hikey, third.root), (70, 70, 70));
+        assert_eq!(third.loop_mode, LoopMode::NoLoop);
+        assert_eq!(third.sample.data.len(), 50);
+
+        // A master drops the group but keeps the global
+        let fourth = &regions[3];
+        assert_eq!((fourth.lokey, fourth.hikey), (0, 127));
+        assert_eq!(fourth.attack, 0.1);
+        assert_eq!(fourth.release, 0.5);
+
+        // A global drops everything
+        let fifth = &regions[4];
+        assert_eq!(fifth.attack, 0.0);
+        assert_eq!(fifth.release, 0.001);
+        assert_eq!(fifth.gain, 1.0);
+    }
+
+    #[test]
+    fn rejects_files_without_regions_or_samples() {
+        let dir = TestDir::new("invalid");
+        let empty = dir.write("empty.sfz", b"<group> lokey=40\n<region> key=60\n");
+        let missing = dir.write("missing.sfz", b"<region> sample=missing.wav\n");
+
+        assert!(Instrument::load(&empty).is_err());
+        assert!(Instrument::load(&missing).is_err());
+    }
+
+    #[test]
+    fn parses_key_names() {
+        assert_eq!(parse_key("60"), Some(60));
+        assert_eq!(parse_key("c4"), Some(60));
+        assert_eq!(parse_key("C#4"), Some(61));
+        assert_eq!(parse_key("eb-1"), Some(3));
+        assert_eq!(parse_key("128"), None);
+        assert_eq!(parse_key("h4"), None);
+    }
+}
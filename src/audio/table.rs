@@ -1,13 +1,79 @@
+use crate::audio::arpeggiator::{Arpeggiator, ArpeggiatorUpdate};
 use crate::audio::module::Module;
-use crate::audio::module::midi::Midi;
+use crate::audio::module::midi::{Midi, MidiUpdate, ALL_NOTES_OFF_CC, ALL_SOUND_OFF_CC};
 
+use super::module::additive::Additive;
 use super::module::analog::AnalogOscillator;
 use super::module::butterworth::Butterworth;
 use super::module::chorus::Chorus;
+use super::module::clock::Clock;
 use super::module::delay::Delay;
 use super::module::envelope::Envelope;
+use super::module::fm::Fm;
+use super::module::lfo::Lfo;
+use super::module::noise::Noise;
 use super::module::reverb::Reverb;
-use super::module::ModuleMessage;
+use super::module::sampler::Sampler;
+use super::module::sequencer::Sequencer;
+use super::module::supersaw::Supersaw;
+use super::module::wavetable::WavetableOscillator;
+use super::module::{ModuleMessage, ModuleMessageUnion};
+
+use crate::synth::midi::MidiMessage;
+
+const MIDI_MODULE: usize = 0;
+const CLOCK_MODULE: usize = 52;
+const SEQUENCER_MODULE: usize = 53;
+
+/// MIDI clock ticks per quarter note
+const CLOCK_RESOLUTION: f32 = 24.0;
+
+/// What sits at each index of the table, so a message can be checked against its module before it's sent
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ModuleKind {
+    Additive,
+    AnalogOscillator,
+    Butterworth,
+    Chorus,
+    Clock,
+    Delay,
+    Envelope,
+    Fm,
+    Lfo,
+    Midi,
+    Noise,
+    Reverb,
+    Sampler,
+    Sequencer,
+    Supersaw,
+    WavetableOscillator,
+}
+
+impl ModuleKind {
+    /// Follows the module list in `ModTable::new`, `None` past the end
+    pub fn of(id: usize) -> Option<ModuleKind> {
+        let kind = match id {
+            0 => ModuleKind::Midi,
+            1..=16 | 54 | 111..=126 => ModuleKind::AnalogOscillator,
+            17..=32 | 55 | 58 => ModuleKind::Envelope,
+            33..=48 | 56 | 59 => ModuleKind::Butterworth,
+            49 => ModuleKind::Chorus,
+            50 => ModuleKind::Delay,
+            51 => ModuleKind::Reverb,
+            52 => ModuleKind::Clock,
+            53 => ModuleKind::Sequencer,
+            57 => ModuleKind::WavetableOscillator,
+            60 => ModuleKind::Lfo,
+            61 => ModuleKind::Supersaw,
+            62 => ModuleKind::Noise,
+            63..=78 => ModuleKind::Fm,
+            79..=94 => ModuleKind::Sampler,
+            95..=110 => ModuleKind::Additive,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
 
 struct Cable {
     source_module: usize,
@@ -35,13 +101,20 @@ impl Cable {
 pub struct ModTable {
     modules: Vec<Box<dyn Module>>,
     cables: Vec<Cable>,
+    /// Sits in front of the `Midi` module, so it isn't part of the cable graph
+    arpeggiator: Arpeggiator,
+    /// Generated notes and the clock, waiting to be sent to the MIDI output
+    midi_output: Vec<MidiMessage>,
+    sequencer_note: Option<u8>,
+    clock_running: bool,
+    clock_tick: i64,
 }
 
 impl ModTable {
     pub fn new() -> Self {        
         Self {
             modules: vec![
-                Box::new(Midi::new(0)),
+                Box::new(Midi::new(0, 48000)),
                 Box::new(AnalogOscillator::new(1, 48000)),
                 Box::new(AnalogOscillator::new(2, 48000)),
                 Box::new(AnalogOscillator::new(3, 48000)),
@@ -93,6 +166,81 @@ impl ModTable {
                 Box::new(Chorus::new(49, 48000)),
                 Box::new(Delay::new(50, 48000)),
                 Box::new(Reverb::new(51, 48000)),
+                Box::new(Clock::new(52, 48000)),
+                Box::new(Sequencer::new(53)),
+                Box::new(AnalogOscillator::new(54, 48000)),
+                Box::new(Envelope::new(55)),
+                Box::new(Butterworth::new(56, 48000)),
+                Box::new(WavetableOscillator::new(57, 48000)),
+                Box::new(Envelope::new(58)),
+                Box::new(Butterworth::new(59, 48000)),
+                Box::new(Lfo::new(60, 48000).frequency(0.1)),
+                Box::new(Supersaw::new(61, 48000)),
+                Box::new(Noise::new(62, 48000)),
+                Box::new(Fm::new(63, 48000)),
+                Box::new(Fm::new(64, 48000)),
+                Box::new(Fm::new(65, 48000)),
+                Box::new(Fm::new(66, 48000)),
+                Box::new(Fm::new(67, 48000)),
+                Box::new(Fm::new(68, 48000)),
+                Box::new(Fm::new(69, 48000)),
+                Box::new(Fm::new(70, 48000)),
+                Box::new(Fm::new(71, 48000)),
+                Box::new(Fm::new(72, 48000)),
+                Box::new(Fm::new(73, 48000)),
+                Box::new(Fm::new(74, 48000)),
+                Box::new(Fm::new(75, 48000)),
+                Box::new(Fm::new(76, 48000)),
+                Box::new(Fm::new(77, 48000)),
+                Box::new(Fm::new(78, 48000)),
+                Box::new(Sampler::new(79, 48000)),
+                Box::new(Sampler::new(80, 48000)),
+                Box::new(Sampler::new(81, 48000)),
+                Box::new(Sampler::new(82, 48000)),
+                Box::new(Sampler::new(83, 48000)),
+                Box::new(Sampler::new(84, 48000)),
+                Box::new(Sampler::new(85, 48000)),
+                Box::new(Sampler::new(86, 48000)),
+                Box::new(Sampler::new(87, 48000)),
+                Box::new(Sampler::new(88, 48000)),
+                Box::new(Sampler::new(89, 48000)),
+                Box::new(Sampler::new(90, 48000)),
+                Box::new(Sampler::new(91, 48000)),
+                Box::new(Sampler::new(92, 48000)),
+                Box::new(Sampler::new(93, 48000)),
+                Box::new(Sampler::new(94, 48000)),
+                Box::new(Additive::new(95, 48000)),
+                Box::new(Additive::new(96, 48000)),
+                Box::new(Additive::new(97, 48000)),
+                Box::new(Additive::new(98, 48000)),
+                Box::new(Additive::new(99, 48000)),
+                Box::new(Additive::new(100, 48000)),
+                Box::new(Additive::new(101, 48000)),
+                Box::new(Additive::new(102, 48000)),
+                Box::new(Additive::new(103, 48000)),
+                Box::new(Additive::new(104, 48000)),
+                Box::new(Additive::new(105, 48000)),
+                Box::new(Additive::new(106, 48000)),
+                Box::new(Additive::new(107, 48000)),
+                Box::new(Additive::new(108, 48000)),
+                Box::new(Additive::new(109, 48000)),
+                Box::new(Additive::new(110, 48000)),
+                Box::new(AnalogOscillator::new(111, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(112, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(113, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(114, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(115, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(116, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(117, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(118, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(119, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(120, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(121, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(122, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(123, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(124, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(125, 48000).level(1.0)),
+                Box::new(AnalogOscillator::new(126, 48000).level(1.0)),
             ],
             cables: vec![
                 // Osc1
@@ -244,12 +392,379 @@ impl ModTable {
                 Cable::new(49, 0, 50, 0),
                 // Delay to reverb
                 Cable::new(50, 0, 51, 0),
+
+                // Tempo to delay
+                Cable::new(52, 4, 50, 1),
+                // Tempo to PWM
+                Cable::new(52, 4, 60, 3),
+
+                // Sequencer voice
+                Cable::new(52, 6, 53, 0),
+                Cable::new(52, 5, 53, 1),
+                Cable::new(53, 0, 55, 0),
+                Cable::new(53, 2, 55, 1),
+                Cable::new(55, 0, 54, 0),
+                Cable::new(53, 1, 54, 1),
+                Cable::new(54, 0, 56, 0),
+                Cable::new(53, 1, 56, 1),
+                Cable::new(55, 0, 56, 1),
+                Cable::new(56, 0, 49, 0),
+
+                // Wavetable voice, played by the mono output with the mod wheel moving through the table
+                Cable::new(0, 0, 58, 0),
+                Cable::new(0, 2, 58, 1),
+                Cable::new(58, 0, 57, 0),
+                Cable::new(0, 1, 57, 1),
+                Cable::new(0, 52, 57, 2),
+                Cable::new(57, 0, 59, 0),
+                Cable::new(0, 1, 59, 1),
+                Cable::new(58, 0, 59, 1),
+                Cable::new(59, 0, 49, 0),
+
+                // Supersaw, sharing the wavetable voice's envelope and going straight to the reverb to keep its stereo spread
+                Cable::new(58, 0, 61, 0),
+                Cable::new(0, 1, 61, 1),
+                Cable::new(61, 0, 51, 1),
+                Cable::new(61, 1, 51, 2),
+
+                // Noise through the wavetable voice's envelope and filter, the random value steps every beat and moves the wavetable position
+                Cable::new(58, 0, 62, 0),
+                Cable::new(62, 0, 59, 0),
+                Cable::new(52, 0, 62, 1),
+                Cable::new(62, 4, 57, 2),
+
+                // FM voices, one per poly voice into its filter
+                // Fm1
+                Cable::new(0, 3, 63, 0),
+                Cable::new(0, 4, 63, 1),
+                Cable::new(0, 5, 63, 2),
+                Cable::new(63, 0, 33, 0),
+                // Fm2
+                Cable::new(0, 6, 64, 0),
+                Cable::new(0, 7, 64, 1),
+                Cable::new(0, 8, 64, 2),
+                Cable::new(64, 0, 34, 0),
+                // Fm3
+                Cable::new(0, 9, 65, 0),
+                Cable::new(0, 10, 65, 1),
+                Cable::new(0, 11, 65, 2),
+                Cable::new(65, 0, 35, 0),
+                // Fm4
+                Cable::new(0, 12, 66, 0),
+                Cable::new(0, 13, 66, 1),
+                Cable::new(0, 14, 66, 2),
+                Cable::new(66, 0, 36, 0),
+                // Fm5
+                Cable::new(0, 15, 67, 0),
+                Cable::new(0, 16, 67, 1),
+                Cable::new(0, 17, 67, 2),
+                Cable::new(67, 0, 37, 0),
+                // Fm6
+                Cable::new(0, 18, 68, 0),
+                Cable::new(0, 19, 68, 1),
+                Cable::new(0, 20, 68, 2),
+                Cable::new(68, 0, 38, 0),
+                // Fm7
+                Cable::new(0, 21, 69, 0),
+                Cable::new(0, 22, 69, 1),
+                Cable::new(0, 23, 69, 2),
+                Cable::new(69, 0, 39, 0),
+                // Fm8
+                Cable::new(0, 24, 70, 0),
+                Cable::new(0, 25, 70, 1),
+                Cable::new(0, 26, 70, 2),
+                Cable::new(70, 0, 40, 0),
+                // Fm9
+                Cable::new(0, 27, 71, 0),
+                Cable::new(0, 28, 71, 1),
+                Cable::new(0, 29, 71, 2),
+                Cable::new(71, 0, 41, 0),
+                // Fm10
+                Cable::new(0, 30, 72, 0),
+                Cable::new(0, 31, 72, 1),
+                Cable::new(0, 32, 72, 2),
+                Cable::new(72, 0, 42, 0),
+                // Fm11
+                Cable::new(0, 33, 73, 0),
+                Cable::new(0, 34, 73, 1),
+                Cable::new(0, 35, 73, 2),
+                Cable::new(73, 0, 43, 0),
+                // Fm12
+                Cable::new(0, 36, 74, 0),
+                Cable::new(0, 37, 74, 1),
+                Cable::new(0, 38, 74, 2),
+                Cable::new(74, 0, 44, 0),
+                // Fm13
+                Cable::new(0, 39, 75, 0),
+                Cable::new(0, 40, 75, 1),
+                Cable::new(0, 41, 75, 2),
+                Cable::new(75, 0, 45, 0),
+                // Fm14
+                Cable::new(0, 42, 76, 0),
+                Cable::new(0, 43, 76, 1),
+                Cable::new(0, 44, 76, 2),
+                Cable::new(76, 0, 46, 0),
+                // Fm15
+                Cable::new(0, 45, 77, 0),
+                Cable::new(0, 46, 77, 1),
+                Cable::new(0, 47, 77, 2),
+                Cable::new(77, 0, 47, 0),
+                // Fm16
+                Cable::new(0, 48, 78, 0),
+                Cable::new(0, 49, 78, 1),
+                Cable::new(0, 50, 78, 2),
+                Cable::new(78, 0, 48, 0),
+
+                // Sampler voices, one per poly voice into its filter
+                // Sampler1
+                Cable::new(0, 3, 79, 0),
+                Cable::new(0, 4, 79, 1),
+                Cable::new(0, 5, 79, 2),
+                Cable::new(0, 119, 79, 3),
+                Cable::new(79, 0, 33, 0),
+                // Sampler2
+                Cable::new(0, 6, 80, 0),
+                Cable::new(0, 7, 80, 1),
+                Cable::new(0, 8, 80, 2),
+                Cable::new(0, 120, 80, 3),
+                Cable::new(80, 0, 34, 0),
+                // Sampler3
+                Cable::new(0, 9, 81, 0),
+                Cable::new(0, 10, 81, 1),
+                Cable::new(0, 11, 81, 2),
+                Cable::new(0, 121, 81, 3),
+                Cable::new(81, 0, 35, 0),
+                // Sampler4
+                Cable::new(0, 12, 82, 0),
+                Cable::new(0, 13, 82, 1),
+                Cable::new(0, 14, 82, 2),
+                Cable::new(0, 122, 82, 3),
+                Cable::new(82, 0, 36, 0),
+                // Sampler5
+                Cable::new(0, 15, 83, 0),
+                Cable::new(0, 16, 83, 1),
+                Cable::new(0, 17, 83, 2),
+                Cable::new(0, 123, 83, 3),
+                Cable::new(83, 0, 37, 0),
+                // Sampler6
+                Cable::new(0, 18, 84, 0),
+                Cable::new(0, 19, 84, 1),
+                Cable::new(0, 20, 84, 2),
+                Cable::new(0, 124, 84, 3),
+                Cable::new(84, 0, 38, 0),
+                // Sampler7
+                Cable::new(0, 21, 85, 0),
+                Cable::new(0, 22, 85, 1),
+                Cable::new(0, 23, 85, 2),
+                Cable::new(0, 125, 85, 3),
+                Cable::new(85, 0, 39, 0),
+                // Sampler8
+                Cable::new(0, 24, 86, 0),
+                Cable::new(0, 25, 86, 1),
+                Cable::new(0, 26, 86, 2),
+                Cable::new(0, 126, 86, 3),
+                Cable::new(86, 0, 40, 0),
+                // Sampler9
+                Cable::new(0, 27, 87, 0),
+                Cable::new(0, 28, 87, 1),
+                Cable::new(0, 29, 87, 2),
+                Cable::new(0, 127, 87, 3),
+                Cable::new(87, 0, 41, 0),
+                // Sampler10
+                Cable::new(0, 30, 88, 0),
+                Cable::new(0, 31, 88, 1),
+                Cable::new(0, 32, 88, 2),
+                Cable::new(0, 128, 88, 3),
+                Cable::new(88, 0, 42, 0),
+                // Sampler11
+                Cable::new(0, 33, 89, 0),
+                Cable::new(0, 34, 89, 1),
+                Cable::new(0, 35, 89, 2),
+                Cable::new(0, 129, 89, 3),
+                Cable::new(89, 0, 43, 0),
+                // Sampler12
+                Cable::new(0, 36, 90, 0),
+                Cable::new(0, 37, 90, 1),
+                Cable::new(0, 38, 90, 2),
+                Cable::new(0, 130, 90, 3),
+                Cable::new(90, 0, 44, 0),
+                // Sampler13
+                Cable::new(0, 39, 91, 0),
+                Cable::new(0, 40, 91, 1),
+                Cable::new(0, 41, 91, 2),
+                Cable::new(0, 131, 91, 3),
+                Cable::new(91, 0, 45, 0),
+                // Sampler14
+                Cable::new(0, 42, 92, 0),
+                Cable::new(0, 43, 92, 1),
+                Cable::new(0, 44, 92, 2),
+                Cable::new(0, 132, 92, 3),
+                Cable::new(92, 0, 46, 0),
+                // Sampler15
+                Cable::new(0, 45, 93, 0),
+                Cable::new(0, 46, 93, 1),
+                Cable::new(0, 47, 93, 2),
+                Cable::new(0, 133, 93, 3),
+                Cable::new(93, 0, 47, 0),
+                // Sampler16
+                Cable::new(0, 48, 94, 0),
+                Cable::new(0, 49, 94, 1),
+                Cable::new(0, 50, 94, 2),
+                Cable::new(0, 134, 94, 3),
+                Cable::new(94, 0, 48, 0),
+
+                // Additive voices, one per poly voice with its envelope and into its filter
+                // Additive1
+                Cable::new(17, 0, 95, 0),
+                Cable::new(0, 4, 95, 1),
+                Cable::new(95, 0, 33, 0),
+                // Additive2
+                Cable::new(18, 0, 96, 0),
+                Cable::new(0, 7, 96, 1),
+                Cable::new(96, 0, 34, 0),
+                // Additive3
+                Cable::new(19, 0, 97, 0),
+                Cable::new(0, 10, 97, 1),
+                Cable::new(97, 0, 35, 0),
+                // Additive4
+                Cable::new(20, 0, 98, 0),
+                Cable::new(0, 13, 98, 1),
+                Cable::new(98, 0, 36, 0),
+                // Additive5
+                Cable::new(21, 0, 99, 0),
+                Cable::new(0, 16, 99, 1),
+                Cable::new(99, 0, 37, 0),
+                // Additive6
+                Cable::new(22, 0, 100, 0),
+                Cable::new(0, 19, 100, 1),
+                Cable::new(100, 0, 38, 0),
+                // Additive7
+                Cable::new(23, 0, 101, 0),
+                Cable::new(0, 22, 101, 1),
+                Cable::new(101, 0, 39, 0),
+                // Additive8
+                Cable::new(24, 0, 102, 0),
+                Cable::new(0, 25, 102, 1),
+                Cable::new(102, 0, 40, 0),
+                // Additive9
+                Cable::new(25, 0, 103, 0),
+                Cable::new(0, 28, 103, 1),
+                Cable::new(103, 0, 41, 0),
+                // Additive10
+                Cable::new(26, 0, 104, 0),
+                Cable::new(0, 31, 104, 1),
+                Cable::new(104, 0, 42, 0),
+                // Additive11
+                Cable::new(27, 0, 105, 0),
+                Cable::new(0, 34, 105, 1),
+                Cable::new(105, 0, 43, 0),
+                // Additive12
+                Cable::new(28, 0, 106, 0),
+                Cable::new(0, 37, 106, 1),
+                Cable::new(106, 0, 44, 0),
+                // Additive13
+                Cable::new(29, 0, 107, 0),
+                Cable::new(0, 40, 107, 1),
+                Cable::new(107, 0, 45, 0),
+                // Additive14
+                Cable::new(30, 0, 108, 0),
+                Cable::new(0, 43, 108, 1),
+                Cable::new(108, 0, 46, 0),
+                // Additive15
+                Cable::new(31, 0, 109, 0),
+                Cable::new(0, 46, 109, 1),
+                Cable::new(109, 0, 47, 0),
+                // Additive16
+                Cable::new(32, 0, 110, 0),
+                Cable::new(0, 49, 110, 1),
+                Cable::new(110, 0, 48, 0),
+
+                // PWM
+                Cable::new(60, 0, 1, 3),
+                Cable::new(60, 0, 2, 3),
+                Cable::new(60, 0, 3, 3),
+                Cable::new(60, 0, 4, 3),
+                Cable::new(60, 0, 5, 3),
+                Cable::new(60, 0, 6, 3),
+                Cable::new(60, 0, 7, 3),
+                Cable::new(60, 0, 8, 3),
+                Cable::new(60, 0, 9, 3),
+                Cable::new(60, 0, 10, 3),
+                Cable::new(60, 0, 11, 3),
+                Cable::new(60, 0, 12, 3),
+                Cable::new(60, 0, 13, 3),
+                Cable::new(60, 0, 14, 3),
+                Cable::new(60, 0, 15, 3),
+                Cable::new(60, 0, 16, 3),
+
+                // Sync and FM, each voice's oscillator follows a modulator playing the same note
+                Cable::new(0, 4, 111, 1),
+                Cable::new(111, 0, 1, 4),
+                Cable::new(111, 0, 1, 5),
+                Cable::new(0, 7, 112, 1),
+                Cable::new(112, 0, 2, 4),
+                Cable::new(112, 0, 2, 5),
+                Cable::new(0, 10, 113, 1),
+                Cable::new(113, 0, 3, 4),
+                Cable::new(113, 0, 3, 5),
+                Cable::new(0, 13, 114, 1),
+                Cable::new(114, 0, 4, 4),
+                Cable::new(114, 0, 4, 5),
+                Cable::new(0, 16, 115, 1),
+                Cable::new(115, 0, 5, 4),
+                Cable::new(115, 0, 5, 5),
+                Cable::new(0, 19, 116, 1),
+                Cable::new(116, 0, 6, 4),
+                Cable::new(116, 0, 6, 5),
+                Cable::new(0, 22, 117, 1),
+                Cable::new(117, 0, 7, 4),
+                Cable::new(117, 0, 7, 5),
+                Cable::new(0, 25, 118, 1),
+                Cable::new(118, 0, 8, 4),
+                Cable::new(118, 0, 8, 5),
+                Cable::new(0, 28, 119, 1),
+                Cable::new(119, 0, 9, 4),
+                Cable::new(119, 0, 9, 5),
+                Cable::new(0, 31, 120, 1),
+                Cable::new(120, 0, 10, 4),
+                Cable::new(120, 0, 10, 5),
+                Cable::new(0, 34, 121, 1),
+                Cable::new(121, 0, 11, 4),
+                Cable::new(121, 0, 11, 5),
+                Cable::new(0, 37, 122, 1),
+                Cable::new(122, 0, 12, 4),
+                Cable::new(122, 0, 12, 5),
+                Cable::new(0, 40, 123, 1),
+                Cable::new(123, 0, 13, 4),
+                Cable::new(123, 0, 13, 5),
+                Cable::new(0, 43, 124, 1),
+                Cable::new(124, 0, 14, 4),
+                Cable::new(124, 0, 14, 5),
+                Cable::new(0, 46, 125, 1),
+                Cable::new(125, 0, 15, 4),
+                Cable::new(125, 0, 15, 5),
+                Cable::new(0, 49, 126, 1),
+                Cable::new(126, 0, 16, 4),
+                Cable::new(126, 0, 16, 5),
             ],
+            arpeggiator: Arpeggiator::new(),
+            midi_output: Vec::with_capacity(256),
+            sequencer_note: None,
+            clock_running: false,
+            clock_tick: 0,
         }
     }
 
     pub fn process(&mut self) -> (f32, f32) {
+        let position = self.modules[CLOCK_MODULE].get_output(6);
+        let running = self.modules[CLOCK_MODULE].get_output(5) > 0.5;
+        let midi = &mut self.modules[MIDI_MODULE];
+        let output = &mut self.midi_output;
+        self.arpeggiator.process(position, running, |update| play(midi, output, update));
+        self.clock_output(position, running);
+
         self.modules.iter_mut().for_each(|module| module.process());
+        self.sequencer_output();
 
         self.cables.iter().for_each(|cable| {
             let output_module_index = cable.source_module;
@@ -262,7 +777,120 @@ impl ModTable {
         (self.modules[51].get_output(0), self.modules[51].get_output(1))
     }
 
+    /// Messages for the `Midi` module go through the arpeggiator, all sound off goes to `panic`
     pub fn update(&mut self, id: usize, msg: ModuleMessage) {
-        self.modules[id].update(msg);
+        match (id, &msg) {
+            (MIDI_MODULE, ModuleMessage::ComponentChange(msg_union)) => {
+                // All sound off also clears the envelopes and effect tails
+                if let MidiUpdate::ControlChange(ALL_SOUND_OFF_CC, _) = unsafe {msg_union.midi} {
+                    self.panic();
+                    return;
+                }
+
+                // While the arpeggiator is on every note it lets through is one it played
+                let enabled = self.arpeggiator.enabled();
+                let midi = &mut self.modules[MIDI_MODULE];
+                let output = &mut self.midi_output;
+                self.arpeggiator.input(unsafe {msg_union.midi}, |update| {
+                    if enabled {
+                        play(midi, output, update);
+                    } else {
+                        midi.update(midi_message(update));
+                    }
+                });
+            },
+            _ => if let Some(module) = self.modules.get_mut(id) {
+                module.update(msg);
+            },
+        }
+    }
+
+    pub fn update_arpeggiator(&mut self, update: ArpeggiatorUpdate) {
+        let midi = &mut self.modules[MIDI_MODULE];
+        let output = &mut self.midi_output;
+        self.arpeggiator.update(update, |update| play(midi, output, update));
+    }
+
+    /// Silences everything, including held notes and effect tails
+    pub fn panic(&mut self) {
+        let midi = &mut self.modules[MIDI_MODULE];
+        let output = &mut self.midi_output;
+        self.arpeggiator.reset(|update| play(midi, output, update));
+        self.modules.iter_mut().for_each(|module| module.update(ModuleMessage::Reset));
+
+        if let Some(note) = self.sequencer_note.take() {
+            self.midi_output.push(MidiMessage::NoteOff { channel: 0, note, velocity: 0 });
+        }
+        self.midi_output.push(MidiMessage::ControlChange { channel: 0, controller: ALL_NOTES_OFF_CC, value: 0 });
+    }
+
+    /// Messages for the MIDI output since the last call, on channel 1
+    pub fn midi_output(&mut self) -> std::vec::Drain<'_, MidiMessage> {
+        self.midi_output.drain(..)
     }
-}
\ No newline at end of file
+
+    /// Start/stop and 24 ticks per quarter note from the clock position
+    fn clock_output(&mut self, position: f32, running: bool) {
+        let tick = (position * CLOCK_RESOLUTION).floor() as i64;
+
+        if running && !self.clock_running {
+            self.midi_output.push(if position <= 0.0 { MidiMessage::Start } else { MidiMessage::Continue });
+            self.midi_output.push(MidiMessage::Clock);
+        } else if !running && self.clock_running {
+            self.midi_output.push(MidiMessage::Stop);
+        } else if running && tick != self.clock_tick {
+            self.midi_output.push(MidiMessage::Clock);
+        }
+        self.clock_running = running;
+        self.clock_tick = tick;
+    }
+
+    /// Turns the sequencer gate into notes, a tie into a different note starts it before ending the last one
+    fn sequencer_output(&mut self) {
+        let sequencer = &self.modules[SEQUENCER_MODULE];
+        let gate = sequencer.get_output(0) > 0.0;
+        let note = sequencer.get_output(3) as u8;
+        let velocity = (sequencer.get_output(2) * 127.0).round() as u8;
+
+        let previous = self.sequencer_note;
+        if gate && previous != Some(note) {
+            self.midi_output.push(MidiMessage::NoteOn { channel: 0, note, velocity });
+            self.sequencer_note = Some(note);
+        } else if !gate {
+            self.sequencer_note = None;
+        }
+
+        if let Some(previous) = previous.filter(|previous| self.sequencer_note != Some(*previous)) {
+            self.midi_output.push(MidiMessage::NoteOff { channel: 0, note: previous, velocity: 0 });
+        }
+    }
+}
+
+fn midi_message(update: MidiUpdate) -> ModuleMessage {
+    ModuleMessage::ComponentChange(ModuleMessageUnion {midi: update})
+}
+
+/// Plays a note from the arpeggiator, which also goes to the MIDI output
+fn play(midi: &mut Box<dyn Module>, output: &mut Vec<MidiMessage>, update: MidiUpdate) {
+    match update {
+        MidiUpdate::KeyPress(note, velocity) => output.push(MidiMessage::NoteOn { channel: 0, note, velocity }),
+        MidiUpdate::KeyRelease(note) => output.push(MidiMessage::NoteOff { channel: 0, note, velocity: 0 }),
+        _ => (),
+    }
+    midi.update(midi_message(update));
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_kinds_cover_the_table() {
+        let table = ModTable::new();
+
+        for (id, module) in table.modules.iter().enumerate() {
+            assert_eq!(module.id(), id);
+            assert!(ModuleKind::of(id).is_some(), "no kind for module {id}");
+        }
+        assert_eq!(ModuleKind::of(table.modules.len()), None);
+    }
+}
@@ -0,0 +1,33 @@
+/// Frequency of MIDI note 0 in 12-TET at A4 = 440 Hz, where note signals are measured from
+const NOTE_ZERO_FREQUENCY: f32 = 8.176;
+
+/// The note signal each key plays.
+///
+/// Pitched modules read notes as `note / 127` and turn them into `2^(127/12 * signal) * 8.176` Hz,
+/// so a tuning stores every key as the (fractional) 12-TET note with the same frequency.
+#[derive(Debug)]
+pub struct Tuning {
+    notes: [f32; 128],
+}
+
+impl Tuning {
+    pub fn equal() -> Self {
+        Self {
+            notes: std::array::from_fn(|key| key as f32 / 127.0),
+        }
+    }
+
+    /// Keys without a frequency keep their 12-TET pitch
+    pub fn from_frequencies(frequencies: impl Fn(u8) -> Option<f32>) -> Self {
+        Self {
+            notes: std::array::from_fn(|key| match frequencies(key as u8) {
+                Some(frequency) if frequency > 0.0 => 12.0 * (frequency / NOTE_ZERO_FREQUENCY).log2() / 127.0,
+                _ => key as f32 / 127.0,
+            }),
+        }
+    }
+
+    pub fn note(&self, key: u8) -> f32 {
+        self.notes[key as usize & 0x7F]
+    }
+}
@@ -0,0 +1,180 @@
+use std::f32::consts::PI;
+use std::fs::File;
+use std::io::BufReader;
+
+use rodio::{Decoder, Source};
+
+/// Samples in one single cycle frame, the Serum layout
+pub const FRAME_SIZE: usize = 2048;
+const MAX_FRAMES: usize = 256;
+/// Highest harmonic a frame can hold, the one at Nyquist is dropped
+const MAX_HARMONIC: usize = FRAME_SIZE / 2 - 1;
+/// Each level keeps half the harmonics of the one before, down to just the fundamental
+const LEVEL_COUNT: usize = 10;
+/// Levels shrink with their harmonics but stay big enough to interpolate smoothly
+const MIN_LEVEL_SIZE: usize = 64;
+
+struct Frame {
+    levels: Vec<Vec<f32>>,
+}
+
+/// Single cycle frames, each stored as band-limited mipmaps so high notes don't alias
+pub struct Wavetable {
+    frames: Vec<Frame>,
+}
+
+impl std::fmt::Debug for Wavetable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Wavetable({} frames)", self.frames.len())
+    }
+}
+
+impl Wavetable {
+    /// A single saw frame, so the oscillator plays before a table is loaded
+    pub fn saw() -> Self {
+        let samples: Vec<f32> = (0..FRAME_SIZE).map(|i| 2.0 * i as f32 / FRAME_SIZE as f32 - 1.0).collect();
+        Self::from_samples(&samples)
+    }
+
+    /// Reads a mono or mixed down WAV file, split into frames of `FRAME_SIZE` samples
+    pub fn load(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|err| err.to_string())?;
+        let decoder = Decoder::new_wav(BufReader::new(file)).map_err(|err| err.to_string())?;
+        let channels = decoder.channels().max(1) as usize;
+
+        let interleaved: Vec<f32> = decoder.map(|sample| sample as f32 / i16::MAX as f32).collect();
+        let samples: Vec<f32> = interleaved.chunks(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+
+        if samples.is_empty() {
+            return Err(String::from("No samples in file"));
+        }
+        Ok(Self::from_samples(&samples))
+    }
+
+    /// Anything shorter than a frame is stretched into one, anything past the last whole frame is ignored
+    pub fn from_samples(samples: &[f32]) -> Self {
+        let peak = samples.iter().fold(0.0_f32, |peak, sample| peak.max(sample.abs()));
+        let gain = if peak > 0.0 { 1.0 / peak } else { 1.0 };
+
+        let frames = if samples.len() < FRAME_SIZE {
+            let stretched: Vec<f32> = (0..FRAME_SIZE)
+                .map(|i| read(samples, i as f32 / FRAME_SIZE as f32))
+                .collect();
+            vec![Frame::new(&stretched, gain)]
+        } else {
+            samples.chunks_exact(FRAME_SIZE)
+                .take(MAX_FRAMES)
+                .map(|frame| Frame::new(frame, gain))
+                .collect()
+        };
+
+        Self {
+            frames,
+        }
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// `position` from 0 to 1 crossfades through the frames, `phase_increment` picks the mipmap level
+    pub fn sample(&self, position: f32, phase: f32, phase_increment: f32) -> f32 {
+        // The smallest level whose highest harmonic stays under Nyquist
+        let level = (2.0 * MAX_HARMONIC as f32 * phase_increment).log2().ceil().clamp(0.0, (LEVEL_COUNT - 1) as f32) as usize;
+
+        let frame = position.clamp(0.0, 1.0) * (self.frames.len() - 1) as f32;
+        let index = frame.floor() as usize;
+        let current = read(&self.frames[index].levels[level], phase);
+
+        match self.frames.get(index + 1) {
+            Some(next) => {
+                let t = frame - index as f32;
+                current + (read(&next.levels[level], phase) - current) * t
+            },
+            None => current,
+        }
+    }
+}
+
+impl Frame {
+    fn new(samples: &[f32], gain: f32) -> Self {
+        let mut re: Vec<f32> = samples.iter().map(|sample| sample * gain).collect();
+        let mut im = vec![0.0; FRAME_SIZE];
+        fft(&mut re, &mut im, false);
+
+        let levels = (0..LEVEL_COUNT).map(|level| {
+            let harmonics = MAX_HARMONIC >> level;
+            let size = (FRAME_SIZE >> level).max(MIN_LEVEL_SIZE);
+
+            // Only the kept harmonics and their mirror images, the DC offset is dropped too
+            let mut level_re = vec![0.0; FRAME_SIZE];
+            let mut level_im = vec![0.0; FRAME_SIZE];
+            for harmonic in 1..=harmonics {
+                level_re[harmonic] = re[harmonic];
+                level_im[harmonic] = im[harmonic];
+                level_re[FRAME_SIZE - harmonic] = re[FRAME_SIZE - harmonic];
+                level_im[FRAME_SIZE - harmonic] = im[FRAME_SIZE - harmonic];
+            }
+            fft(&mut level_re, &mut level_im, true);
+
+            level_re.iter()
+                .step_by(FRAME_SIZE / size)
+                .map(|sample| sample / FRAME_SIZE as f32)
+                .collect()
+        }).collect();
+
+        Self {
+            levels,
+        }
+    }
+}
+
+/// Linear interpolation around one cycle, `phase` from 0 to 1
+fn read(table: &[f32], phase: f32) -> f32 {
+    let position = phase.rem_euclid(1.0) * table.len() as f32;
+    let index = position as usize % table.len();
+    let next = (index + 1) % table.len();
+    let t = position - position.floor();
+    table[index] + (table[next] - table[index]) * t
+}
+
+/// In place radix-2 FFT, the inverse is left unscaled
+fn fft(re: &mut [f32], im: &mut [f32], inverse: bool) {
+    let n = re.len();
+
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let sign = if inverse { 1.0 } else { -1.0 };
+    let mut length = 2;
+    while length <= n {
+        let angle = sign * 2.0 * PI / length as f32;
+        for start in (0..n).step_by(length) {
+            for k in 0..length / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + length / 2;
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        length <<= 1;
+    }
+}
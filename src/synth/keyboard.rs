@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Message;
+
+use iced::keyboard::Key;
+use iced::widget::{container, horizontal_space, mouse_area, row, stack, Row};
+use iced::{Border, Color, Element};
+
+/// Keys from left to right on the home row and the row above it, starting at C
+const NOTE_KEYS: [&str; 18] = ["a", "w", "s", "e", "d", "f", "t", "g", "y", "h", "u", "j", "k", "o", "l", "p", ";", "'"];
+const OCTAVE_DOWN_KEY: &str = "z";
+const OCTAVE_UP_KEY: &str = "x";
+const VELOCITY_DOWN_KEY: &str = "c";
+const VELOCITY_UP_KEY: &str = "v";
+
+const VELOCITY_STEP: u8 = 10;
+/// How far a varying velocity may stray from the set velocity
+const VELOCITY_SPREAD: i32 = 20;
+
+const PIANO_OCTAVES: u8 = 2;
+const WHITE_KEY_WIDTH: f32 = 28.0;
+const WHITE_KEY_HEIGHT: f32 = 100.0;
+const BLACK_KEY_WIDTH: f32 = 18.0;
+const BLACK_KEY_HEIGHT: f32 = 60.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VelocityMode {
+    Fixed,
+    Varying,
+}
+
+impl VelocityMode {
+    pub const ALL: [VelocityMode; 2] = [VelocityMode::Fixed, VelocityMode::Varying];
+}
+
+impl fmt::Display for VelocityMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VelocityMode::Fixed => write!(f, "Fixed velocity"),
+            VelocityMode::Varying => write!(f, "Varying velocity"),
+        }
+    }
+}
+
+/// Plays notes from the computer keyboard and the on screen piano
+pub struct Keyboard {
+    /// MIDI note of the lowest key
+    base_note: u8,
+    velocity: u8,
+    velocity_mode: VelocityMode,
+    /// Note each held key started, so changing octave doesn't leave notes hanging
+    held_keys: HashMap<String, u8>,
+    held_piano_note: Option<u8>,
+    seed: u32,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self {
+            base_note: 60,
+            velocity: 100,
+            velocity_mode: VelocityMode::Fixed,
+            held_keys: HashMap::new(),
+            held_piano_note: None,
+            seed: 0x9E37_79B9,
+        }
+    }
+
+    pub fn octave(&self) -> i8 {
+        (self.base_note / 12) as i8 - 1
+    }
+
+    pub fn velocity(&self) -> u8 {
+        self.velocity
+    }
+
+    pub fn velocity_mode(&self) -> VelocityMode {
+        self.velocity_mode
+    }
+
+    pub fn set_velocity_mode(&mut self, velocity_mode: VelocityMode) {
+        self.velocity_mode = velocity_mode;
+    }
+
+    pub fn key_press(&mut self, key: Key) -> Option<Message> {
+        let Key::Character(key) = key else {
+            return None;
+        };
+        let key = key.to_lowercase();
+
+        match key.as_str() {
+            OCTAVE_DOWN_KEY => self.base_note = self.base_note.saturating_sub(12).max(12),
+            OCTAVE_UP_KEY => self.base_note = (self.base_note + 12).min(108),
+            VELOCITY_DOWN_KEY => self.velocity = self.velocity.saturating_sub(VELOCITY_STEP).max(1),
+            VELOCITY_UP_KEY => self.velocity = (self.velocity + VELOCITY_STEP).min(127),
+            _ => {
+                let offset = NOTE_KEYS.iter().position(|note_key| *note_key == key)?;
+                // Held keys repeat, only the first press plays
+                if self.held_keys.contains_key(&key) {
+                    return None;
+                }
+                let note = (self.base_note + offset as u8).min(127);
+                self.held_keys.insert(key, note);
+                return Some(Message::KeyPress(note, self.next_velocity()));
+            },
+        }
+        None
+    }
+
+    pub fn key_release(&mut self, key: Key) -> Option<Message> {
+        let Key::Character(key) = key else {
+            return None;
+        };
+        let note = self.held_keys.remove(&key.to_lowercase())?;
+        Some(Message::KeyRelease(note))
+    }
+
+    pub fn piano_press(&mut self, note: u8) -> Option<Message> {
+        self.held_piano_note = Some(note);
+        Some(Message::KeyPress(note, self.next_velocity()))
+    }
+
+    /// Only releases if the piano is holding the note, so leaving a key without pressing it does nothing
+    pub fn piano_release(&mut self, note: u8) -> Option<Message> {
+        if self.held_piano_note != Some(note) {
+            return None;
+        }
+        self.held_piano_note = None;
+        Some(Message::KeyRelease(note))
+    }
+
+    fn next_velocity(&mut self) -> u8 {
+        match self.velocity_mode {
+            VelocityMode::Fixed => self.velocity,
+            VelocityMode::Varying => {
+                // xorshift32
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 17;
+                self.seed ^= self.seed << 5;
+
+                let offset = (self.seed % (VELOCITY_SPREAD as u32 * 2 + 1)) as i32 - VELOCITY_SPREAD;
+                (self.velocity as i32 + offset).clamp(1, 127) as u8
+            },
+        }
+    }
+
+    fn is_held(&self, note: u8) -> bool {
+        self.held_piano_note == Some(note) || self.held_keys.values().any(|held| *held == note)
+    }
+
+    /// Clickable keys starting at the computer keyboard's octave
+    pub fn piano(&self) -> Element<'_, Message> {
+        let first = self.base_note;
+        let last = (first + PIANO_OCTAVES * 12).min(127);
+
+        let mut white_keys = Row::new();
+        let mut black_keys = Row::new();
+        let mut white_count = 0;
+        let mut black_cursor = 0.0;
+
+        for note in first..=last {
+            if is_black(note) {
+                let x = white_count as f32 * WHITE_KEY_WIDTH - BLACK_KEY_WIDTH / 2.0;
+                black_keys = black_keys
+                    .push(horizontal_space().width(x - black_cursor))
+                    .push(self.piano_key(note, BLACK_KEY_WIDTH, BLACK_KEY_HEIGHT));
+                black_cursor = x + BLACK_KEY_WIDTH;
+            } else {
+                white_keys = white_keys.push(self.piano_key(note, WHITE_KEY_WIDTH, WHITE_KEY_HEIGHT));
+                white_count += 1;
+            }
+        }
+
+        row![stack![white_keys, black_keys]].into()
+    }
+
+    fn piano_key(&self, note: u8, width: f32, height: f32) -> Element<'_, Message> {
+        let color = match (self.is_held(note), is_black(note)) {
+            (true, _) => Color::from_rgb(0.55, 0.7, 0.95),
+            (false, true) => Color::BLACK,
+            (false, false) => Color::WHITE,
+        };
+
+        mouse_area(
+            container(horizontal_space())
+                .width(width)
+                .height(height)
+                .style(move |_| container::Style {
+                    background: Some(color.into()),
+                    border: Border {
+                        color: Color::BLACK,
+                        width: 1.0,
+                        radius: 0.0.into(),
+                    },
+                    ..Default::default()
+                })
+        )
+            .on_press(Message::PianoPress(note))
+            .on_release(Message::PianoRelease(note))
+            .on_exit(Message::PianoRelease(note))
+            .into()
+    }
+}
+
+fn is_black(note: u8) -> bool {
+    matches!(note % 12, 1 | 3 | 6 | 8 | 10)
+}
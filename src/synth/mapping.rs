@@ -0,0 +1,160 @@
+use std::fmt;
+
+use super::parameter::Target;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+    Linear,
+    Exponential,
+    Logarithmic,
+}
+
+impl Curve {
+    pub const ALL: [Curve; 3] = [Curve::Linear, Curve::Exponential, Curve::Logarithmic];
+
+    /// Shapes a 0 to 1 controller position
+    pub fn apply(&self, x: f32) -> f32 {
+        match self {
+            Curve::Linear => x,
+            Curve::Exponential => x * x,
+            Curve::Logarithmic => x.sqrt(),
+        }
+    }
+
+    /// The controller position that gives a shaped value
+    pub fn invert(&self, y: f32) -> f32 {
+        match self {
+            Curve::Linear => y,
+            Curve::Exponential => y.sqrt(),
+            Curve::Logarithmic => y * y,
+        }
+    }
+
+    pub fn key(&self) -> &'static str {
+        match self {
+            Curve::Linear => "linear",
+            Curve::Exponential => "exponential",
+            Curve::Logarithmic => "logarithmic",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Curve> {
+        Curve::ALL.into_iter().find(|curve| curve.key() == key)
+    }
+}
+
+impl fmt::Display for Curve {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Curve::Linear => "Linear",
+            Curve::Exponential => "Exponential",
+            Curve::Logarithmic => "Logarithmic",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Binds a MIDI CC to a module parameter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mapping {
+    pub controller: u8,
+    pub target: Target,
+    pub min: f32,
+    pub max: f32,
+    pub curve: Curve,
+}
+
+impl Mapping {
+    pub fn new(controller: u8, target: Target) -> Self {
+        let range = target.parameter.range();
+        Self {
+            controller,
+            target,
+            min: *range.start(),
+            max: *range.end(),
+            curve: Curve::Linear,
+        }
+    }
+
+    pub fn value(&self, cc_value: u8) -> f32 {
+        let x = self.curve.apply(cc_value as f32 / 127.0);
+        self.min + (self.max - self.min) * x
+    }
+
+    /// The CC value that sets the target to `value`, clamped to the mapped range
+    pub fn cc_value(&self, value: f32) -> u8 {
+        let y = if self.max == self.min {
+            0.0
+        } else {
+            ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        };
+        (self.curve.invert(y) * 127.0).round() as u8
+    }
+}
+
+#[derive(Default)]
+pub struct MappingTable {
+    mappings: Vec<Mapping>,
+    learning: Option<Target>,
+}
+
+impl MappingTable {
+    pub fn new(mappings: Vec<Mapping>) -> Self {
+        Self {
+            mappings,
+            learning: None,
+        }
+    }
+
+    pub fn mappings(&self) -> &[Mapping] {
+        &self.mappings
+    }
+
+    pub fn learning(&self) -> Option<Target> {
+        self.learning
+    }
+
+    /// The next CC received gets bound to the target
+    pub fn learn(&mut self, target: Target) {
+        self.learning = Some(target);
+    }
+
+    pub fn cancel_learn(&mut self) {
+        self.learning = None;
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.mappings.len() {
+            self.mappings.remove(index);
+        }
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut Mapping> {
+        self.mappings.get_mut(index)
+    }
+
+    /// Finishes a pending learn with this controller. Returns true if a new mapping was made.
+    pub fn bind(&mut self, controller: u8) -> bool {
+        let Some(target) = self.learning.take() else {
+            return false;
+        };
+
+        self.mappings.retain(|mapping| mapping.target != target);
+        self.mappings.push(Mapping::new(controller, target));
+        true
+    }
+
+    /// Every target the controller is mapped to, with the value it should be set to
+    pub fn apply(&self, controller: u8, value: u8) -> impl Iterator<Item = (Target, f32)> + '_ {
+        self.mappings.iter()
+            .filter(move |mapping| mapping.controller == controller)
+            .map(move |mapping| (mapping.target, mapping.value(value)))
+    }
+
+    /// Every controller mapped to the target, with the value that matches the parameter
+    pub fn feedback(&self, target: Target, value: f32) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.mappings.iter()
+            .filter(move |mapping| mapping.target == target)
+            .map(move |mapping| (mapping.controller, mapping.cc_value(value)))
+    }
+}
@@ -0,0 +1,175 @@
+/// A MIDI channel voice message, or one of the system messages used for syncing.
+/// Channels are zero based (0 is MIDI channel 1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MidiMessage {
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    PolyPressure { channel: u8, note: u8, pressure: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelPressure { channel: u8, pressure: u8 },
+    /// 14 bit value, 8192 is centered
+    PitchBend { channel: u8, value: u16 },
+    /// Sent 24 times per quarter note
+    Clock,
+    Start,
+    Continue,
+    Stop,
+    /// Position in sixteenth notes since the start of the song
+    SongPosition(u16),
+}
+
+impl MidiMessage {
+    /// None for system messages
+    pub fn channel(&self) -> Option<u8> {
+        match *self {
+            MidiMessage::NoteOff { channel, .. }
+            | MidiMessage::NoteOn { channel, .. }
+            | MidiMessage::PolyPressure { channel, .. }
+            | MidiMessage::ControlChange { channel, .. }
+            | MidiMessage::ProgramChange { channel, .. }
+            | MidiMessage::ChannelPressure { channel, .. }
+            | MidiMessage::PitchBend { channel, .. } => Some(channel),
+            MidiMessage::Clock
+            | MidiMessage::Start
+            | MidiMessage::Continue
+            | MidiMessage::Stop
+            | MidiMessage::SongPosition(_) => None,
+        }
+    }
+
+    /// Moves a channel voice message to another channel, system messages are left as they are
+    pub fn with_channel(self, channel: u8) -> Self {
+        match self {
+            MidiMessage::NoteOff { note, velocity, .. } => MidiMessage::NoteOff { channel, note, velocity },
+            MidiMessage::NoteOn { note, velocity, .. } => MidiMessage::NoteOn { channel, note, velocity },
+            MidiMessage::PolyPressure { note, pressure, .. } => MidiMessage::PolyPressure { channel, note, pressure },
+            MidiMessage::ControlChange { controller, value, .. } => MidiMessage::ControlChange { channel, controller, value },
+            MidiMessage::ProgramChange { program, .. } => MidiMessage::ProgramChange { channel, program },
+            MidiMessage::ChannelPressure { pressure, .. } => MidiMessage::ChannelPressure { channel, pressure },
+            MidiMessage::PitchBend { value, .. } => MidiMessage::PitchBend { channel, value },
+            message => message,
+        }
+    }
+
+    /// The raw bytes, always with a status byte
+    pub fn to_bytes(self) -> Vec<u8> {
+        match self {
+            MidiMessage::NoteOff { channel, note, velocity } => vec![0x80 | channel, note, velocity],
+            MidiMessage::NoteOn { channel, note, velocity } => vec![0x90 | channel, note, velocity],
+            MidiMessage::PolyPressure { channel, note, pressure } => vec![0xA0 | channel, note, pressure],
+            MidiMessage::ControlChange { channel, controller, value } => vec![0xB0 | channel, controller, value],
+            MidiMessage::ProgramChange { channel, program } => vec![0xC0 | channel, program],
+            MidiMessage::ChannelPressure { channel, pressure } => vec![0xD0 | channel, pressure],
+            MidiMessage::PitchBend { channel, value } => vec![0xE0 | channel, (value & 0x7F) as u8, (value >> 7 & 0x7F) as u8],
+            MidiMessage::Clock => vec![0xF8],
+            MidiMessage::Start => vec![0xFA],
+            MidiMessage::Continue => vec![0xFB],
+            MidiMessage::Stop => vec![0xFC],
+            MidiMessage::SongPosition(position) => vec![0xF2, (position & 0x7F) as u8, (position >> 7 & 0x7F) as u8],
+        }
+    }
+}
+
+/// Turns raw MIDI bytes into messages, keeping track of running status between calls.
+#[derive(Default)]
+pub struct Parser {
+    running_status: Option<u8>,
+}
+
+impl Parser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parses a single message. System messages other than clock and transport are skipped.
+    pub fn parse(&mut self, bytes: &[u8]) -> Option<MidiMessage> {
+        let (&first, rest) = bytes.split_first()?;
+
+        let (status, data) = if first & 0x80 != 0 {
+            if first >= 0xF0 {
+                // System common messages cancel running status, real time messages don't
+                if first < 0xF8 {
+                    self.running_status = None;
+                }
+                return Self::parse_system(first, rest);
+            }
+            self.running_status = Some(first);
+            (first, rest)
+        } else {
+            (self.running_status?, bytes)
+        };
+
+        let channel = status & 0x0F;
+        let data1 = *data.first()? & 0x7F;
+
+        let message = match status & 0xF0 {
+            0xC0 => MidiMessage::ProgramChange { channel, program: data1 },
+            0xD0 => MidiMessage::ChannelPressure { channel, pressure: data1 },
+            kind => {
+                let data2 = *data.get(1)? & 0x7F;
+                match kind {
+                    0x80 => MidiMessage::NoteOff { channel, note: data1, velocity: data2 },
+                    // A note on with zero velocity is a note off
+                    0x90 if data2 == 0 => MidiMessage::NoteOff { channel, note: data1, velocity: 0 },
+                    0x90 => MidiMessage::NoteOn { channel, note: data1, velocity: data2 },
+                    0xA0 => MidiMessage::PolyPressure { channel, note: data1, pressure: data2 },
+                    0xB0 => MidiMessage::ControlChange { channel, controller: data1, value: data2 },
+                    0xE0 => MidiMessage::PitchBend { channel, value: (data2 as u16) << 7 | data1 as u16 },
+                    _ => unreachable!(),
+                }
+            },
+        };
+
+        Some(message)
+    }
+
+    fn parse_system(status: u8, data: &[u8]) -> Option<MidiMessage> {
+        match status {
+            0xF2 => {
+                let lsb = *data.first()? as u16 & 0x7F;
+                let msb = *data.get(1)? as u16 & 0x7F;
+                Some(MidiMessage::SongPosition(msb << 7 | lsb))
+            },
+            0xF8 => Some(MidiMessage::Clock),
+            0xFA => Some(MidiMessage::Start),
+            0xFB => Some(MidiMessage::Continue),
+            0xFC => Some(MidiMessage::Stop),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_status() {
+        let mut parser = Parser::new();
+        assert_eq!(parser.parse(&[0x91, 60, 100]), Some(MidiMessage::NoteOn { channel: 1, note: 60, velocity: 100 }));
+        assert_eq!(parser.parse(&[64, 90]), Some(MidiMessage::NoteOn { channel: 1, note: 64, velocity: 90 }));
+        assert_eq!(parser.parse(&[60, 0]), Some(MidiMessage::NoteOff { channel: 1, note: 60, velocity: 0 }));
+    }
+
+    #[test]
+    fn real_time_keeps_running_status() {
+        let mut parser = Parser::new();
+        parser.parse(&[0xB0, 1, 10]);
+        assert_eq!(parser.parse(&[0xF8]), Some(MidiMessage::Clock));
+        assert_eq!(parser.parse(&[1, 20]), Some(MidiMessage::ControlChange { channel: 0, controller: 1, value: 20 }));
+    }
+
+    #[test]
+    fn system_common_cancels_running_status() {
+        let mut parser = Parser::new();
+        parser.parse(&[0x90, 60, 100]);
+        assert_eq!(parser.parse(&[0xF2, 0x10, 0x01]), Some(MidiMessage::SongPosition(0x90)));
+        assert_eq!(parser.parse(&[64, 90]), None);
+    }
+
+    #[test]
+    fn data_without_status() {
+        assert_eq!(Parser::new().parse(&[60, 100]), None);
+    }
+}
@@ -0,0 +1,389 @@
+mod message;
+pub mod smf;
+
+use super::Message;
+use crate::audio::module::clock::ClockUpdate;
+use crate::audio::module::midi::{MpeUpdate, SLIDE_CC};
+use message::Parser;
+pub use message::MidiMessage;
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use iced::stream;
+use iced::futures::channel::mpsc::{self as iced_mpsc, Sender};
+use iced::futures::{SinkExt, Stream, StreamExt};
+
+use midir::{self, MidiInput, MidiInputConnection, MidiOutput, MidiOutputConnection, Ignore};
+
+const CLIENT_NAME: &str = "cav-synth";
+const VIRTUAL_PORT_NAME: &str = "cav-synth";
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+pub enum Input {
+    Close,
+    TogglePort(String),
+    SetChannel(ReceiveChannel),
+    SetMpe(bool),
+    SetVirtualPort(bool),
+    SetOutputPort(OutputPort),
+    /// Zero based
+    SetOutputChannel(u8),
+    /// Goes out on the output channel
+    Send(MidiMessage),
+}
+
+/// Which MIDI channel messages are accepted from. Channels are zero based.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReceiveChannel {
+    Omni,
+    Channel(u8),
+}
+
+impl ReceiveChannel {
+    pub const ALL: [ReceiveChannel; 17] = [
+        ReceiveChannel::Omni,
+        ReceiveChannel::Channel(0), ReceiveChannel::Channel(1), ReceiveChannel::Channel(2), ReceiveChannel::Channel(3),
+        ReceiveChannel::Channel(4), ReceiveChannel::Channel(5), ReceiveChannel::Channel(6), ReceiveChannel::Channel(7),
+        ReceiveChannel::Channel(8), ReceiveChannel::Channel(9), ReceiveChannel::Channel(10), ReceiveChannel::Channel(11),
+        ReceiveChannel::Channel(12), ReceiveChannel::Channel(13), ReceiveChannel::Channel(14), ReceiveChannel::Channel(15),
+    ];
+
+    fn accepts(&self, channel: u8) -> bool {
+        match *self {
+            ReceiveChannel::Omni => true,
+            ReceiveChannel::Channel(receive) => receive == channel,
+        }
+    }
+}
+
+impl fmt::Display for ReceiveChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReceiveChannel::Omni => write!(f, "Omni"),
+            ReceiveChannel::Channel(channel) => write!(f, "Channel {}", channel + 1),
+        }
+    }
+}
+
+/// Where generated notes, the clock and parameter feedback are sent
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputPort {
+    None,
+    Port(String),
+}
+
+impl fmt::Display for OutputPort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputPort::None => write!(f, "No MIDI output"),
+            OutputPort::Port(name) => write!(f, "{name}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Port {
+    pub name: String,
+    pub enabled: bool,
+}
+
+/// Shared with the input callbacks
+#[derive(Clone, Copy)]
+struct Settings {
+    channel: ReceiveChannel,
+    /// Keep the channel of every message so MPE member channels can be told apart
+    mpe: bool,
+}
+
+/// Keeps one connection per enabled input port, following ports as they come and go.
+/// Ports are enabled by default, so devices plugged in later are picked up automatically.
+struct PortManager {
+    ports: Vec<Port>,
+    disabled: HashSet<String>,
+    connections: HashMap<String, MidiInputConnection<()>>,
+    virtual_port: Option<MidiInputConnection<()>>,
+    settings: Arc<Mutex<Settings>>,
+    output_ports: Vec<String>,
+    /// Reconnected whenever the port shows up again
+    output_port: OutputPort,
+    output: Option<MidiOutputConnection>,
+    output_channel: u8,
+}
+
+impl PortManager {
+    fn new() -> Self {
+        Self {
+            ports: Vec::new(),
+            disabled: HashSet::new(),
+            connections: HashMap::new(),
+            virtual_port: None,
+            settings: Arc::new(Mutex::new(Settings {
+                channel: ReceiveChannel::Omni,
+                mpe: false,
+            })),
+            output_ports: Vec::new(),
+            output_port: OutputPort::None,
+            output: None,
+            output_channel: 0,
+        }
+    }
+
+    fn set_channel(&mut self, channel: ReceiveChannel) {
+        self.settings.lock().unwrap().channel = channel;
+    }
+
+    fn set_mpe(&mut self, mpe: bool) {
+        self.settings.lock().unwrap().mpe = mpe;
+    }
+
+    fn toggle(&mut self, name: String) {
+        if !self.disabled.remove(&name) {
+            self.connections.remove(&name);
+            self.disabled.insert(name);
+        }
+    }
+
+    /// Rescans the available ports, dropping connections to unplugged devices and connecting new ones.
+    /// Returns true if the port list shown to the user changed.
+    fn refresh(&mut self, output: &Sender<Message>) -> bool {
+        let available = match MidiInput::new(CLIENT_NAME) {
+            Ok(midi_in) => midi_in.ports().iter()
+                .filter_map(|port| midi_in.port_name(port).ok())
+                .collect(),
+            Err(err) => {
+                eprintln!("Midi unavailable: {err}");
+                Vec::new()
+            },
+        };
+
+        self.connections.retain(|name, _| available.contains(name));
+
+        for name in &available {
+            if self.disabled.contains(name) || self.connections.contains_key(name) {
+                continue;
+            }
+            match Self::connect(name, output.clone(), self.settings.clone()) {
+                Some(connection) => {
+                    println!("Connected midi port: {name}");
+                    self.connections.insert(name.clone(), connection);
+                },
+                None => eprintln!("Failed to connect midi port: {name}"),
+            }
+        }
+
+        let ports: Vec<Port> = available.into_iter()
+            .map(|name| Port {
+                enabled: !self.disabled.contains(&name),
+                name,
+            })
+            .collect();
+
+        let changed = ports != self.ports;
+        self.ports = ports;
+        changed
+    }
+
+    /// Rescans the output ports, following the selected one as it comes and goes.
+    /// Returns true if the port list changed.
+    fn refresh_outputs(&mut self) -> bool {
+        let available: Vec<String> = match MidiOutput::new(CLIENT_NAME) {
+            Ok(midi_out) => midi_out.ports().iter()
+                .filter_map(|port| midi_out.port_name(port).ok())
+                .filter(|name| !is_own_port(name))
+                .collect(),
+            Err(err) => {
+                eprintln!("Midi output unavailable: {err}");
+                Vec::new()
+            },
+        };
+
+        if let OutputPort::Port(name) = &self.output_port {
+            if !available.contains(name) {
+                self.output = None;
+            } else if self.output.is_none() {
+                self.output = Self::connect_output(name);
+            }
+        }
+
+        let changed = available != self.output_ports;
+        self.output_ports = available;
+        changed
+    }
+
+    fn set_output_port(&mut self, port: OutputPort) {
+        self.output = match &port {
+            // Sending to our own ports would feed everything back into the input
+            OutputPort::Port(name) if !is_own_port(name) => Self::connect_output(name),
+            OutputPort::None | OutputPort::Port(_) => None,
+        };
+        self.output_port = port;
+    }
+
+    fn connect_output(name: &str) -> Option<MidiOutputConnection> {
+        let midi_out = MidiOutput::new(CLIENT_NAME).ok()?;
+        let out_ports = midi_out.ports();
+        let out_port = out_ports.iter().find(|port| midi_out.port_name(port).is_ok_and(|port_name| port_name == name))?;
+
+        match midi_out.connect(out_port, "synth-midi-out") {
+            Ok(connection) => {
+                println!("Connected midi output: {name}");
+                Some(connection)
+            },
+            Err(err) => {
+                eprintln!("Failed to connect midi output {name}: {err}");
+                None
+            },
+        }
+    }
+
+    fn send(&mut self, message: MidiMessage) {
+        if let Some(output) = &mut self.output {
+            if let Err(err) = output.send(&message.with_channel(self.output_channel).to_bytes()) {
+                eprintln!("Failed to send midi: {err}");
+            }
+        }
+    }
+
+    fn connect(name: &str, output: Sender<Message>, settings: Arc<Mutex<Settings>>) -> Option<MidiInputConnection<()>> {
+        let mut midi_in = MidiInput::new(CLIENT_NAME).ok()?;
+        midi_in.ignore(Ignore::ActiveSense);
+
+        let in_ports = midi_in.ports();
+        let in_port = in_ports.iter().find(|port| midi_in.port_name(port).is_ok_and(|port_name| port_name == name))?;
+
+        midi_in.connect(in_port, "synth-midi", Self::callback(output, settings), ()).ok()
+    }
+
+    /// Publishes an input port other applications can send to
+    #[cfg(unix)]
+    fn set_virtual_port(&mut self, enabled: bool, output: &Sender<Message>) {
+        use midir::os::unix::VirtualInput;
+
+        if !enabled {
+            self.virtual_port = None;
+            return;
+        }
+        if self.virtual_port.is_some() {
+            return;
+        }
+
+        let Ok(mut midi_in) = MidiInput::new(CLIENT_NAME) else {
+            eprintln!("Midi unavailable, no virtual port created");
+            return;
+        };
+        midi_in.ignore(Ignore::ActiveSense);
+
+        match midi_in.create_virtual(VIRTUAL_PORT_NAME, Self::callback(output.clone(), self.settings.clone()), ()) {
+            Ok(connection) => {
+                println!("Created virtual midi port: {VIRTUAL_PORT_NAME}");
+                self.virtual_port = Some(connection);
+            },
+            Err(err) => eprintln!("Failed to create virtual midi port: {err}"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn set_virtual_port(&mut self, enabled: bool, _output: &Sender<Message>) {
+        if enabled {
+            eprintln!("Virtual midi ports are not supported on this platform");
+        }
+    }
+
+    fn callback(mut output: Sender<Message>, settings: Arc<Mutex<Settings>>) -> impl FnMut(u64, &[u8], &mut ()) + Send + 'static {
+        let mut parser = Parser::new();
+        move |stamp, message, _| {
+            if let Some(message) = parser.parse(message) {
+                let settings = *settings.lock().unwrap();
+                if settings.mpe {
+                    handle_mpe_message(&mut output, message, stamp);
+                } else if message.channel().is_none_or(|channel| settings.channel.accepts(channel)) {
+                    handle_message(&mut output, message, stamp);
+                }
+            }
+        }
+    }
+}
+
+/// Ports this program opened, including the virtual input, which some backends prefix with the client name
+fn is_own_port(name: &str) -> bool {
+    name == VIRTUAL_PORT_NAME || name.starts_with(&format!("{CLIENT_NAME}:"))
+}
+
+fn handle_message(output: &mut Sender<Message>, message: MidiMessage, stamp: u64) {
+    let msg = match message {
+        MidiMessage::NoteOn { note, velocity, .. } => Message::KeyPress(note, velocity),
+        MidiMessage::NoteOff { note, .. } => Message::KeyRelease(note),
+        MidiMessage::ControlChange { controller: 64, value, .. } => {
+            if value < 64 {
+                Message::PedalRelease
+            } else {
+                Message::PedalPress
+            }
+        },
+        MidiMessage::ControlChange { controller, value, .. } => Message::ControlChange(controller, value),
+        MidiMessage::PitchBend { value, .. } => Message::PitchBend(value),
+        MidiMessage::ChannelPressure { pressure, .. } => Message::ChannelPressure(pressure),
+        MidiMessage::PolyPressure { note, pressure, .. } => Message::PolyPressure(note, pressure),
+        MidiMessage::ProgramChange { program, .. } => Message::ProgramChange(program),
+        MidiMessage::Clock => Message::Clock(ClockUpdate::Tick(stamp)),
+        MidiMessage::Start => Message::Clock(ClockUpdate::Start),
+        MidiMessage::Continue => Message::Clock(ClockUpdate::Continue),
+        MidiMessage::Stop => Message::Clock(ClockUpdate::Stop),
+        MidiMessage::SongPosition(position) => Message::Clock(ClockUpdate::SongPosition(position)),
+    };
+
+    let _ = output.try_send(msg);
+}
+
+fn handle_mpe_message(output: &mut Sender<Message>, message: MidiMessage, stamp: u64) {
+    let update = match message {
+        MidiMessage::NoteOn { channel, note, velocity } => MpeUpdate::KeyPress { channel, note, velocity },
+        MidiMessage::NoteOff { channel, note, .. } => MpeUpdate::KeyRelease { channel, note },
+        MidiMessage::PitchBend { channel, value } => MpeUpdate::PitchBend { channel, value },
+        MidiMessage::ChannelPressure { channel, pressure } => MpeUpdate::Pressure { channel, pressure },
+        // Other controllers go through MIDI learn and the mappings like on a normal channel
+        MidiMessage::ControlChange { channel, controller: SLIDE_CC, value } => MpeUpdate::ControlChange { channel, controller: SLIDE_CC, value },
+        _ => return handle_message(output, message, stamp),
+    };
+
+    let _ = output.try_send(Message::Mpe(update));
+}
+
+pub fn listen() -> impl Stream<Item = Message> {
+    stream::channel(100, |mut output| async move {
+        let (sender, mut receiver) = iced_mpsc::channel(100);
+        let _ = output.send(Message::MidiThreadReady(sender)).await;
+
+        let mut ports = PortManager::new();
+        let mut next_refresh = Instant::now();
+
+        loop {
+            // Outgoing messages wake the loop often, rescanning is kept to the poll interval
+            if Instant::now() >= next_refresh {
+                if ports.refresh(&output) {
+                    println!("Midi port count: {}", ports.ports.len());
+                    let _ = output.send(Message::MidiPorts(ports.ports.clone())).await;
+                }
+                if ports.refresh_outputs() {
+                    let _ = output.send(Message::MidiOutputPorts(ports.output_ports.clone())).await;
+                }
+                next_refresh = Instant::now() + POLL_INTERVAL;
+            }
+
+            match tokio::time::timeout(next_refresh.saturating_duration_since(Instant::now()), receiver.next()).await {
+                Ok(Some(Input::Close)) | Ok(None) => break,
+                Ok(Some(Input::TogglePort(name))) => ports.toggle(name),
+                Ok(Some(Input::SetChannel(channel))) => ports.set_channel(channel),
+                Ok(Some(Input::SetMpe(mpe))) => ports.set_mpe(mpe),
+                Ok(Some(Input::SetVirtualPort(enabled))) => ports.set_virtual_port(enabled, &output),
+                Ok(Some(Input::SetOutputPort(port))) => ports.set_output_port(port),
+                Ok(Some(Input::SetOutputChannel(channel))) => ports.output_channel = channel,
+                Ok(Some(Input::Send(message))) => ports.send(message),
+                Err(_) => (),
+            }
+        }
+    })
+}
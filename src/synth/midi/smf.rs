@@ -0,0 +1,280 @@
+use super::message::{MidiMessage, Parser};
+
+use crate::audio::module::midi::MidiUpdate;
+use crate::audio::player::{Sequence, SequenceEvent};
+
+/// Microseconds per quarter note until the file sets a tempo (120 bpm)
+const DEFAULT_TEMPO: u32 = 500_000;
+
+enum TrackEvent {
+    Tempo(u32),
+    Update(MidiUpdate),
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            pos: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(len).filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| String::from("Unexpected end of file"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Variable length quantity, at most 4 bytes
+    fn vlq(&mut self) -> Result<u32, String> {
+        let mut value = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = value << 7 | (byte & 0x7F) as u32;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(String::from("Invalid variable length value"))
+    }
+}
+
+/// Reads a Standard MIDI File (format 0 or 1), merging all tracks and channels into one sequence
+pub fn parse(bytes: &[u8]) -> Result<Sequence, String> {
+    let mut reader = Reader::new(bytes);
+
+    if reader.take(4)? != b"MThd" {
+        return Err(String::from("Not a MIDI file"));
+    }
+    let header_len = reader.u32()? as usize;
+    let mut header = Reader::new(reader.take(header_len)?);
+    let format = header.u16()?;
+    let _track_count = header.u16()?;
+    let division = header.u16()?;
+
+    if format > 1 {
+        return Err(format!("Unsupported MIDI file format {format}"));
+    }
+
+    let mut events = Vec::new();
+    while !reader.is_empty() {
+        let id = reader.take(4)?;
+        let len = reader.u32()? as usize;
+        let chunk = reader.take(len)?;
+        if id == b"MTrk" {
+            parse_track(chunk, &mut events)?;
+        }
+    }
+
+    // Stable, so events on the same tick keep their file order
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let smpte_seconds_per_tick = if division & 0x8000 != 0 {
+        let frames_per_second = -((division >> 8) as i8) as f64;
+        let ticks_per_frame = (division & 0xFF) as f64;
+        Some(1.0 / (frames_per_second * ticks_per_frame))
+    } else {
+        None
+    };
+    let ticks_per_quarter = (division & 0x7FFF).max(1) as f64;
+
+    let mut sequence = Sequence {
+        events: Vec::with_capacity(events.len()),
+        length: 0.0,
+    };
+    let mut tempo = DEFAULT_TEMPO;
+    let mut last_tick = 0;
+    let mut time = 0.0;
+
+    for (tick, event) in events {
+        let seconds_per_tick = smpte_seconds_per_tick.unwrap_or(tempo as f64 / 1_000_000.0 / ticks_per_quarter);
+        time += (tick - last_tick) as f64 * seconds_per_tick;
+        last_tick = tick;
+
+        match event {
+            TrackEvent::Tempo(new_tempo) => tempo = new_tempo,
+            TrackEvent::Update(update) => sequence.events.push(SequenceEvent { time, update }),
+        }
+    }
+    sequence.length = time;
+
+    Ok(sequence)
+}
+
+fn parse_track(chunk: &[u8], events: &mut Vec<(u64, TrackEvent)>) -> Result<(), String> {
+    let mut reader = Reader::new(chunk);
+    let mut parser = Parser::new();
+    let mut running_status = None;
+    let mut tick = 0;
+
+    while !reader.is_empty() {
+        tick += reader.vlq()? as u64;
+
+        let mut status = reader.u8()?;
+        match status {
+            0xFF => {
+                let kind = reader.u8()?;
+                let len = reader.vlq()? as usize;
+                let data = reader.take(len)?;
+                match (kind, data) {
+                    (0x51, [a, b, c]) => events.push((tick, TrackEvent::Tempo(u32::from_be_bytes([0, *a, *b, *c])))),
+                    (0x2F, _) => break,
+                    _ => (),
+                }
+                continue;
+            },
+            0xF0 | 0xF7 => {
+                let len = reader.vlq()? as usize;
+                reader.take(len)?;
+                running_status = None;
+                continue;
+            },
+            0x80.. => running_status = Some(status),
+            _ => {
+                // Running status, this byte was the first data byte
+                reader.pos -= 1;
+                status = running_status.ok_or_else(|| String::from("Data without a status byte"))?;
+            },
+        }
+
+        let data_len = match status & 0xF0 {
+            0xC0 | 0xD0 => 1,
+            _ => 2,
+        };
+        let mut message = vec![status];
+        message.extend_from_slice(reader.take(data_len)?);
+
+        if let Some(update) = parser.parse(&message).and_then(midi_update) {
+            events.push((tick, TrackEvent::Update(update)));
+        }
+    }
+
+    Ok(())
+}
+
+/// The update the live input sends for the same message
+fn midi_update(message: MidiMessage) -> Option<MidiUpdate> {
+    let update = match message {
+        MidiMessage::NoteOn { note, velocity, .. } => MidiUpdate::KeyPress(note, velocity),
+        MidiMessage::NoteOff { note, .. } => MidiUpdate::KeyRelease(note),
+        MidiMessage::ControlChange { controller: 64, value, .. } => {
+            if value < 64 {
+                MidiUpdate::PedalRelease
+            } else {
+                MidiUpdate::PedalPress
+            }
+        },
+        MidiMessage::ControlChange { controller, value, .. } => MidiUpdate::ControlChange(controller, value),
+        MidiMessage::PitchBend { value, .. } => MidiUpdate::PitchBend(value),
+        MidiMessage::ChannelPressure { pressure, .. } => MidiUpdate::ChannelPressure(pressure),
+        MidiMessage::PolyPressure { note, pressure, .. } => MidiUpdate::PolyPressure(note, pressure),
+        _ => return None,
+    };
+    Some(update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(format: u16, division: u16, tracks: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"MThd".to_vec();
+        bytes.extend_from_slice(&6u32.to_be_bytes());
+        bytes.extend_from_slice(&format.to_be_bytes());
+        bytes.extend_from_slice(&(tracks.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(&division.to_be_bytes());
+        for track in tracks {
+            bytes.extend_from_slice(b"MTrk");
+            bytes.extend_from_slice(&(track.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(track);
+        }
+        bytes
+    }
+
+    fn assert_times(sequence: &Sequence, expected: &[f64]) {
+        assert_eq!(sequence.events.len(), expected.len());
+        for (event, time) in sequence.events.iter().zip(expected) {
+            assert!((event.time - time).abs() < 1e-9, "{} != {time}", event.time);
+        }
+    }
+
+    #[test]
+    fn delta_times_and_running_status() {
+        // 480 ticks per quarter note at the default 120 bpm, 480 is 0x83 0x60 as a variable length value
+        let track = [
+            0x00, 0x90, 60, 100,
+            0x83, 0x60, 60, 0,
+            0x83, 0x60, 0x80, 62, 0,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let sequence = parse(&file(0, 480, &[&track])).unwrap();
+
+        assert_times(&sequence, &[0.0, 0.5, 1.0]);
+        assert!((sequence.length - 1.0).abs() < 1e-9);
+        assert!(matches!(sequence.events[0].update, MidiUpdate::KeyPress(60, 100)));
+        assert!(matches!(sequence.events[1].update, MidiUpdate::KeyRelease(60)));
+        assert!(matches!(sequence.events[2].update, MidiUpdate::KeyRelease(62)));
+    }
+
+    #[test]
+    fn tempo_changes_apply_to_later_ticks() {
+        let tempo_track = [
+            0x83, 0x60, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let note_track = [
+            0x00, 0x90, 60, 100,
+            0x87, 0x40, 0x80, 60, 0,
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        let sequence = parse(&file(1, 480, &[&tempo_track, &note_track])).unwrap();
+
+        // 480 ticks at 500000 us, then 480 more at 250000 us per quarter note
+        assert_times(&sequence, &[0.0, 0.75]);
+    }
+
+    #[test]
+    fn smpte_division_ignores_tempo() {
+        // 25 frames per second, 40 ticks per frame
+        let track = [
+            0x00, 0xFF, 0x51, 0x03, 0x03, 0xD0, 0x90,
+            0x00, 0x90, 60, 100,
+            0x83, 0x60, 0x80, 60, 0,
+        ];
+        let sequence = parse(&file(0, 0xE728, &[&track])).unwrap();
+
+        assert_times(&sequence, &[0.0, 0.48]);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        assert!(parse(b"RIFF").is_err());
+        assert!(parse(&file(2, 480, &[])).is_err());
+        assert!(parse(&file(0, 480, &[&[0x00, 60, 100]])).is_err());
+    }
+}
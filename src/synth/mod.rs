@@ -1,48 +1,384 @@
-mod midi;
+mod keyboard;
+mod mapping;
+pub mod midi;
+mod parameter;
+mod preset;
+mod scala;
+mod sequencer;
+mod session;
+
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
 
 use crate::audio;
+use crate::audio::arpeggiator::{ArpOrder, ArpeggiatorUpdate};
 use crate::audio::module::{ModuleMessage, ModuleMessageUnion};
-use crate::audio::module::midi::MidiUpdate;
+use crate::audio::module::additive::{AdditiveUpdate, Spectrum, MAX_PARTIALS};
+use crate::audio::module::analog::{AnalogOscillatorUpdate, WaveShape};
+use crate::audio::module::clock::{ClockSource, ClockUpdate, Division, Feel, NoteDivision, TempoSync};
+use crate::audio::module::delay::DelayUpdate;
+use crate::audio::module::lfo::LfoUpdate;
+use crate::audio::module::fm::{ALGORITHMS, OPERATOR_COUNT};
+use crate::audio::module::midi::{
+    GlideMode, GlideRate, MidiUpdate, MpeUpdate, MpeZone, NotePriority, StealPolicy, VelocityCurve, LINEAR_BREAKPOINTS,
+    POLY_VOICE_COUNT, VELOCITY_BREAKPOINT_COUNT,
+};
+use crate::audio::module::noise::NoiseUpdate;
+use crate::audio::module::sequencer::SequencerUpdate;
+use crate::audio::module::supersaw::{DetuneCurve, SupersawUpdate, MAX_SUPERSAW_VOICES};
+use crate::audio::player::{PlayerCommand, Sequence};
+use crate::audio::sfz::Instrument;
+use crate::audio::tuning::Tuning;
+use crate::audio::wavetable::Wavetable;
+use keyboard::{Keyboard, VelocityMode};
+use mapping::{Curve, MappingTable};
+use parameter::{Parameter, Target, VoiceGroup};
+use preset::{Bank, Preset};
+use scala::{KeyboardMap, Scale};
+use sequencer::SequencerEditor;
+use session::Session;
 
-use iced::{window, Element, Length, Subscription, Task};
+use iced::{keyboard as iced_keyboard, window, Element, Length, Subscription, Task};
 use iced::futures::channel::mpsc::Sender;
-use iced::widget::{button, column, row, slider};
+use iced::widget::{button, checkbox, column, mouse_area, pick_list, row, slider, text, text_input, Column, Row};
+
+const MIDI_MODULE: usize = 0;
+/// One oscillator per poly voice, all set alike
+const OSCILLATOR_MODULES: RangeInclusive<usize> = 1..=16;
+const DELAY_MODULE: usize = 50;
+const CLOCK_MODULE: usize = 52;
+const SEQUENCER_MODULE: usize = 53;
+const WAVETABLE_MODULE: usize = 57;
+const PWM_LFO_MODULE: usize = 60;
+const SUPERSAW_MODULE: usize = 61;
+const NOISE_MODULE: usize = 62;
+/// One FM module per poly voice, all set alike
+const FM_MODULES: RangeInclusive<usize> = 63..=78;
+/// One sampler per poly voice, all playing the same instrument
+const SAMPLER_MODULES: RangeInclusive<usize> = 79..=94;
+/// One additive oscillator per poly voice, all set alike
+const ADDITIVE_MODULES: RangeInclusive<usize> = 95..=110;
+/// One sync and FM source per poly voice, following the voice's note
+const MODULATOR_MODULES: RangeInclusive<usize> = 111..=126;
+/// Partials of the custom additive spectrum that get a slider
+const EDITABLE_PARTIALS: usize = 16;
 
+const BANK_SELECT_MSB_CC: u8 = 0;
+const BANK_SELECT_LSB_CC: u8 = 32;
 
 #[derive(Clone, Debug)]
 pub enum Message {
     AudioThreadReady(Sender<audio::Input>),
     Close(window::Id),
     ComponentChange(usize, ModuleMessage),
+    OscillatorShape(WaveShape),
+    OscillatorSync(bool),
     MidiThreadReady(Sender<midi::Input>),
+    MidiPorts(Vec<midi::Port>),
+    MidiPortToggled(String),
+    VirtualPortToggled(bool),
+    MidiOutputPorts(Vec<String>),
+    MidiOutputPort(midi::OutputPort),
+    MidiOutputChannel(u8),
 
     // MIDI
     KeyPress(u8, u8),
     KeyRelease(u8),
     PedalPress,
     PedalRelease,
+    ControlChange(u8, u8),
+    PitchBend(u16),
+    ChannelPressure(u8),
+    PolyPressure(u8, u8),
+    Mpe(MpeUpdate),
+    /// Silences every voice and effect tail
+    Panic,
+    ReceiveChannel(midi::ReceiveChannel),
+    MpeZone(MpeZone),
+    Clock(ClockUpdate),
+
+    // Computer keyboard and on screen piano
+    ComputerKeyPress(iced_keyboard::Key),
+    ComputerKeyRelease(iced_keyboard::Key),
+    PianoPress(u8),
+    PianoRelease(u8),
+    VelocityMode(VelocityMode),
+
+    // Voice allocation
+    VoiceCount(usize),
+    StealPolicy(StealPolicy),
+    NotePriority(NotePriority),
+    Legato(bool),
+    Unison(usize),
+    GlideMode(GlideMode),
+    GlideRate(GlideRate),
+    VelocityCurve(VelocityCurve),
+    VelocityBreakpoint(usize, u8),
+    Arpeggiator(ArpeggiatorUpdate),
+
+    // Sequencer
+    Sequencer(SequencerUpdate),
+    SequencerSelect(usize),
+
+    // Wavetable
+    WavetablePath(String),
+    LoadWavetable,
+    WavetableLoaded(Result<Arc<Wavetable>, String>),
+
+    // Supersaw
+    Supersaw(SupersawUpdate),
+
+    // Noise
+    NoiseSeed(String),
+
+    // Sampler
+    SfzPath(String),
+    LoadInstrument,
+    InstrumentLoaded(Result<Arc<Instrument>, String>),
+
+    // Additive
+    Additive(AdditiveUpdate),
+
+    // Tuning
+    ScalePath(String),
+    KeyboardMapPath(String),
+    LoadTuning,
+    /// The scale and mapping paths the files were read from, and what they held
+    TuningLoaded((String, String), Result<Arc<(Scale, KeyboardMap)>, String>),
+    ResetTuning,
+    ReferencePitch(f32),
 
-    // Testing
-    DelaySlider(f32),
+    // Presets
+    ProgramChange(u8),
+    PresetBank(u16),
+    PresetName(String),
+    SavePreset,
+
+    // Transport
+    ClockSource(ClockSource),
+    BeatsPerBar(u8),
+    DelaySync(TempoSync),
+    PwmSync(TempoSync),
+
+    // MIDI file player
+    MidiFilePath(String),
+    LoadMidiFile,
+    MidiFileLoaded(Result<Arc<Sequence>, String>),
+    Player(PlayerCommand),
+    PlayerPosition(f64),
+
+    // Parameters
+    ParameterChange(Target, f32),
+    Learn(Target),
+    CancelLearn,
+    MappingMin(usize, f32),
+    MappingMax(usize, f32),
+    MappingCurve(usize, Curve),
+    MappingRemove(usize),
+    SaveSession,
 }
 
 pub struct Synth {
     audio_thread_connection: Option<Sender<audio::Input>>,
     midi_thread_connection: Option<Sender<midi::Input>>,
+    midi_ports: Vec<midi::Port>,
+    virtual_port: bool,
+    receive_channel: midi::ReceiveChannel,
+    mpe_zone: MpeZone,
+    midi_output_ports: Vec<String>,
+    midi_output_port: midi::OutputPort,
+    /// Zero based
+    midi_output_channel: u8,
+    keyboard: Keyboard,
+    oscillator_shape: WaveShape,
+    oscillator_sync: bool,
+
+    // Voice allocation
+    voice_count: usize,
+    steal_policy: StealPolicy,
+    note_priority: NotePriority,
+    legato: bool,
+    unison: usize,
+    glide_mode: GlideMode,
+    glide_rate: GlideRate,
+    velocity_curve: VelocityCurve,
+    velocity_breakpoints: [u8; VELOCITY_BREAKPOINT_COUNT],
+
+    // Arpeggiator
+    arp_enabled: bool,
+    arp_order: ArpOrder,
+    arp_octaves: u8,
+    arp_gate: f32,
+    arp_division: NoteDivision,
+    arp_latch: bool,
+
+    sequencer: SequencerEditor,
+
+    // Wavetable
+    wavetable_path: String,
+    wavetable_status: String,
+    /// The table playing and the one it replaced, held here so the audio thread never frees one
+    wavetables: [Option<Arc<Wavetable>>; 2],
+
+    // Supersaw
+    supersaw_shape: WaveShape,
+    supersaw_voices: usize,
+    supersaw_curve: DetuneCurve,
+    supersaw_random_phase: bool,
+
+    // Noise
+    noise_seed: String,
+
+    // Sampler
+    sfz_path: String,
+    sfz_status: String,
+    /// The instrument playing and the one it replaced, held here so the audio thread never frees one
+    instruments: [Option<Arc<Instrument>>; 2],
+
+    // Additive
+    additive_spectrum: Spectrum,
+    additive_partial_count: usize,
+    additive_partials: [f32; EDITABLE_PARTIALS],
 
-    // Testing
-    delay_slider_value: f32,
+    // Tuning
+    scale_path: String,
+    keyboard_map_path: String,
+    tuning_status: String,
+    scale: Scale,
+    keyboard_map: KeyboardMap,
+    reference_pitch: f32,
+    /// Scale and mapping files of the tuning in use, kept in the session
+    tuning_paths: Option<(String, String)>,
+    /// The tuning the modules have and the one it replaced, held here so the audio thread never frees one
+    tunings: [Option<Arc<Tuning>>; 2],
+
+    // Presets
+    /// Bank select MSB and LSB
+    bank_select: (u8, u8),
+    program: u8,
+    preset_name: String,
+    preset_status: String,
+
+    // Transport
+    clock_source: ClockSource,
+    beats_per_bar: u8,
+    delay_sync: TempoSync,
+    pwm_sync: TempoSync,
+
+    // MIDI file player
+    midi_file_path: String,
+    midi_file_status: String,
+    /// The sequence playing and the one it replaced, held here so the audio thread never frees one
+    sequences: [Option<Arc<Sequence>>; 2],
+    sequence_length: f64,
+    player_position: f64,
+    player_looping: bool,
+    player_tempo_scale: f32,
+
+    // Parameters
+    parameters: HashMap<Target, f32>,
+    mappings: MappingTable,
 }
 
 impl Synth {
     pub fn new() -> (Self, Task<Message>) {
+        let session = Session::load();
+
         (
             Self {
                 audio_thread_connection: None,
                 midi_thread_connection: None,
+                midi_ports: Vec::new(),
+                virtual_port: session.virtual_port || std::env::args().any(|arg| arg == "--virtual-port"),
+                receive_channel: midi::ReceiveChannel::Omni,
+                mpe_zone: MpeZone::Off,
+                midi_output_ports: Vec::new(),
+                midi_output_port: session.midi_output.map_or(midi::OutputPort::None, midi::OutputPort::Port),
+                midi_output_channel: 0,
+                keyboard: Keyboard::new(),
+                oscillator_shape: WaveShape::Saw,
+                oscillator_sync: false,
+
+                // Voice allocation
+                voice_count: POLY_VOICE_COUNT,
+                steal_policy: StealPolicy::Oldest,
+                note_priority: NotePriority::Last,
+                legato: false,
+                unison: 1,
+                glide_mode: GlideMode::Always,
+                glide_rate: GlideRate::ConstantTime,
+                velocity_curve: VelocityCurve::Linear,
+                velocity_breakpoints: LINEAR_BREAKPOINTS,
+
+                // Arpeggiator
+                arp_enabled: false,
+                arp_order: ArpOrder::Up,
+                arp_octaves: 1,
+                arp_gate: 0.5,
+                arp_division: NoteDivision::new(Division::Sixteenth, Feel::Straight),
+                arp_latch: false,
+
+                sequencer: SequencerEditor::new(),
+
+                // Wavetable
+                wavetable_path: String::new(),
+                wavetable_status: String::from("Saw"),
+                wavetables: [None, None],
+
+                // Supersaw
+                supersaw_shape: WaveShape::Saw,
+                supersaw_voices: 7,
+                supersaw_curve: DetuneCurve::Linear,
+                supersaw_random_phase: true,
+
+                // Noise
+                noise_seed: String::new(),
 
-                // Testing
-                delay_slider_value: 0.0,
+                // Sampler
+                sfz_path: String::new(),
+                sfz_status: String::from("No instrument"),
+                instruments: [None, None],
+
+                // Additive
+                additive_spectrum: Spectrum::Saw,
+                additive_partial_count: 64,
+                additive_partials: std::array::from_fn(|index| if index == 0 {1.0} else {0.0}),
+
+                // Tuning
+                scale_path: session.scale.unwrap_or_default(),
+                keyboard_map_path: session.keyboard_map.unwrap_or_default(),
+                tuning_status: String::from("12-TET"),
+                scale: Scale::equal(),
+                keyboard_map: KeyboardMap::standard(),
+                reference_pitch: session.reference_pitch.unwrap_or(440.0),
+                tuning_paths: None,
+                tunings: [None, None],
+
+                // Presets
+                bank_select: (0, 0),
+                program: 0,
+                preset_name: String::new(),
+                preset_status: String::new(),
+
+                // Transport
+                clock_source: ClockSource::Internal,
+                beats_per_bar: 4,
+                delay_sync: TempoSync::Off,
+                pwm_sync: TempoSync::Off,
+
+                // MIDI file player
+                midi_file_path: String::new(),
+                midi_file_status: String::new(),
+                sequences: [None, None],
+                sequence_length: 0.0,
+                player_position: 0.0,
+                player_looping: false,
+                player_tempo_scale: 1.0,
+
+                // Parameters
+                parameters: HashMap::new(),
+                mappings: MappingTable::new(session.mappings),
             },
             Task::none()
         )
@@ -56,9 +392,18 @@ impl Synth {
         match msg {
             Message::AudioThreadReady(connection) => {
                 self.audio_thread_connection = Some(connection);
-                Task::none()
+                self.connect_midi_output();
+                self.send_modulator_level();
+                // A scale path at this point comes from the session
+                if self.scale_path.is_empty() {
+                    self.send_tuning();
+                    Task::none()
+                } else {
+                    self.load_tuning()
+                }
             },
             Message::Close(id) => {
+                self.save_session();
                 if let Some(connection) = &mut self.audio_thread_connection {
                     let _ = connection.try_send(audio::Input::Close).map_err(|err| println!("{err:?}"));
                 }
@@ -73,51 +418,685 @@ impl Synth {
                 }
                 Task::none()
             },
-            Message::MidiThreadReady(connection) => {
+            Message::OscillatorShape(shape) => {
+                self.oscillator_shape = shape;
+                for module in OSCILLATOR_MODULES {
+                    self.send_module(module, ModuleMessageUnion {analog: AnalogOscillatorUpdate::Shape(shape)});
+                }
+                Task::none()
+            },
+            Message::OscillatorSync(sync) => {
+                self.oscillator_sync = sync;
+                for module in OSCILLATOR_MODULES {
+                    self.send_module(module, ModuleMessageUnion {analog: AnalogOscillatorUpdate::Sync(sync)});
+                }
+                self.send_modulator_level();
+                Task::none()
+            },
+            Message::MidiThreadReady(mut connection) => {
+                if self.virtual_port {
+                    let _ = connection.try_send(midi::Input::SetVirtualPort(true));
+                }
+                let _ = connection.try_send(midi::Input::SetOutputPort(self.midi_output_port.clone()));
                 self.midi_thread_connection = Some(connection);
+                self.connect_midi_output();
+                Task::none()
+            },
+            Message::MidiPorts(ports) => {
+                self.midi_ports = ports;
+                Task::none()
+            },
+            Message::MidiPortToggled(name) => {
+                if let Some(connection) = &mut self.midi_thread_connection {
+                    let _ = connection.try_send(midi::Input::TogglePort(name));
+                }
+                Task::none()
+            },
+            Message::VirtualPortToggled(enabled) => {
+                if let Some(connection) = &mut self.midi_thread_connection {
+                    let _ = connection.try_send(midi::Input::SetVirtualPort(enabled));
+                }
+                self.virtual_port = enabled;
+                self.save_session();
+                Task::none()
+            },
+            Message::MidiOutputPorts(ports) => {
+                self.midi_output_ports = ports;
+                Task::none()
+            },
+            Message::MidiOutputPort(port) => {
+                if let Some(connection) = &mut self.midi_thread_connection {
+                    let _ = connection.try_send(midi::Input::SetOutputPort(port.clone()));
+                }
+                self.midi_output_port = port;
+                self.save_session();
+                self.send_feedback();
+                Task::none()
+            },
+            Message::MidiOutputChannel(channel) => {
+                if let Some(connection) = &mut self.midi_thread_connection {
+                    let _ = connection.try_send(midi::Input::SetOutputChannel(channel));
+                }
+                self.midi_output_channel = channel;
                 Task::none()
             },
 
             // MIDI
-            Message::KeyPress(note, velocity) => {
+            Message::KeyPress(note, velocity) => self.send_midi(MidiUpdate::KeyPress(note, velocity)),
+            Message::KeyRelease(note) => self.send_midi(MidiUpdate::KeyRelease(note)),
+            Message::PedalPress => self.send_midi(MidiUpdate::PedalPress),
+            Message::PedalRelease => self.send_midi(MidiUpdate::PedalRelease),
+            Message::ControlChange(controller, value) => {
+                match controller {
+                    BANK_SELECT_MSB_CC => self.bank_select.0 = value,
+                    BANK_SELECT_LSB_CC => self.bank_select.1 = value,
+                    _ => (),
+                }
+                if self.mappings.bind(controller) {
+                    self.save_session();
+                }
+                let changes: Vec<(Target, f32)> = self.mappings.apply(controller, value).collect();
+                for (target, value) in changes {
+                    self.set_parameter(target, value);
+                }
+                self.send_midi(MidiUpdate::ControlChange(controller, value))
+            },
+            Message::PitchBend(value) => self.send_midi(MidiUpdate::PitchBend(value)),
+            Message::ChannelPressure(pressure) => self.send_midi(MidiUpdate::ChannelPressure(pressure)),
+            Message::PolyPressure(note, pressure) => self.send_midi(MidiUpdate::PolyPressure(note, pressure)),
+            Message::Mpe(update) => self.send_midi(MidiUpdate::Mpe(update)),
+            Message::Panic => {
                 if let Some(connection) = &mut self.audio_thread_connection {
-                    let input = ModuleMessage::ComponentChange(ModuleMessageUnion {midi: MidiUpdate::KeyPress(note, velocity)});
-                    let _ = connection.try_send(audio::Input::ModuleMessage(0, input));
+                    let _ = connection.try_send(audio::Input::Panic);
                 }
                 Task::none()
             },
-            Message::KeyRelease(note) => {
-                if let Some(connection) = &mut self.audio_thread_connection {
-                    let input = ModuleMessage::ComponentChange(ModuleMessageUnion {midi: MidiUpdate::KeyRelease(note)});
-                    let _ = connection.try_send(audio::Input::ModuleMessage(0, input));
+            Message::ReceiveChannel(channel) => {
+                if let Some(connection) = &mut self.midi_thread_connection {
+                    let _ = connection.try_send(midi::Input::SetChannel(channel));
                 }
+                self.receive_channel = channel;
                 Task::none()
             },
-            Message::PedalPress => {
-                if let Some(connection) = &mut self.audio_thread_connection {
-                    let input = ModuleMessage::ComponentChange(ModuleMessageUnion {midi: MidiUpdate::PedalPress});
-                    let _ = connection.try_send(audio::Input::ModuleMessage(0, input));
+            Message::MpeZone(zone) => {
+                if let Some(connection) = &mut self.midi_thread_connection {
+                    let _ = connection.try_send(midi::Input::SetMpe(zone != MpeZone::Off));
                 }
+                self.mpe_zone = zone;
+                self.send_midi(MidiUpdate::Mpe(MpeUpdate::Zone(zone)))
+            },
+            Message::Clock(update) => {
+                self.send_module(CLOCK_MODULE, ModuleMessageUnion {clock: update});
                 Task::none()
             },
-            Message::PedalRelease => {
+
+            // Computer keyboard and on screen piano
+            Message::ComputerKeyPress(key) => match self.keyboard.key_press(key) {
+                Some(msg) => self.update(msg),
+                None => Task::none(),
+            },
+            Message::ComputerKeyRelease(key) => match self.keyboard.key_release(key) {
+                Some(msg) => self.update(msg),
+                None => Task::none(),
+            },
+            Message::PianoPress(note) => match self.keyboard.piano_press(note) {
+                Some(msg) => self.update(msg),
+                None => Task::none(),
+            },
+            Message::PianoRelease(note) => match self.keyboard.piano_release(note) {
+                Some(msg) => self.update(msg),
+                None => Task::none(),
+            },
+            Message::VelocityMode(velocity_mode) => {
+                self.keyboard.set_velocity_mode(velocity_mode);
+                Task::none()
+            },
+
+            // Voice allocation
+            Message::VoiceCount(count) => {
+                self.voice_count = count;
+                self.send_midi(MidiUpdate::VoiceCount(count))
+            },
+            Message::StealPolicy(policy) => {
+                self.steal_policy = policy;
+                self.send_midi(MidiUpdate::StealPolicy(policy))
+            },
+            Message::NotePriority(priority) => {
+                self.note_priority = priority;
+                self.send_midi(MidiUpdate::NotePriority(priority))
+            },
+            Message::Legato(legato) => {
+                self.legato = legato;
+                self.send_midi(MidiUpdate::Legato(legato))
+            },
+            Message::Unison(unison) => {
+                self.unison = unison;
+                self.send_midi(MidiUpdate::Unison(unison))
+            },
+            Message::GlideMode(mode) => {
+                self.glide_mode = mode;
+                self.send_midi(MidiUpdate::GlideMode(mode))
+            },
+            Message::GlideRate(rate) => {
+                self.glide_rate = rate;
+                self.send_midi(MidiUpdate::GlideRate(rate))
+            },
+            Message::VelocityCurve(curve) => {
+                self.velocity_curve = curve;
+                self.send_midi(MidiUpdate::VelocityCurve(curve))
+            },
+            Message::VelocityBreakpoint(index, velocity) => {
+                self.velocity_breakpoints[index] = velocity;
+                self.send_midi(MidiUpdate::VelocityBreakpoint(index, velocity))
+            },
+            Message::Arpeggiator(update) => {
+                match update {
+                    ArpeggiatorUpdate::Enabled(enabled) => self.arp_enabled = enabled,
+                    ArpeggiatorUpdate::Order(order) => self.arp_order = order,
+                    ArpeggiatorUpdate::Octaves(octaves) => self.arp_octaves = octaves,
+                    ArpeggiatorUpdate::Gate(gate) => self.arp_gate = gate,
+                    ArpeggiatorUpdate::Division(division) => self.arp_division = division,
+                    ArpeggiatorUpdate::Latch(latch) => self.arp_latch = latch,
+                }
                 if let Some(connection) = &mut self.audio_thread_connection {
-                    let input = ModuleMessage::ComponentChange(ModuleMessageUnion {midi: MidiUpdate::PedalRelease});
-                    let _ = connection.try_send(audio::Input::ModuleMessage(0, input));
+                    let _ = connection.try_send(audio::Input::Arpeggiator(update));
+                }
+                Task::none()
+            },
+
+            // Sequencer
+            Message::Sequencer(update) => {
+                self.sequencer.update(update);
+                self.send_module(SEQUENCER_MODULE, ModuleMessageUnion {sequencer: update});
+                Task::none()
+            },
+            Message::SequencerSelect(index) => {
+                self.sequencer.select(index);
+                Task::none()
+            },
+
+            // Wavetable
+            Message::WavetablePath(path) => {
+                self.wavetable_path = path;
+                Task::none()
+            },
+            Message::LoadWavetable => {
+                self.wavetable_status = String::from("Loading");
+                let path = self.wavetable_path.clone();
+                Task::perform(load(move || Wavetable::load(&path)), Message::WavetableLoaded)
+            },
+            Message::WavetableLoaded(result) => {
+                match result {
+                    Ok(table) => {
+                        self.wavetable_status = format!("{} frames", table.frame_count());
+                        if let Some(connection) = &mut self.audio_thread_connection {
+                            let _ = connection.try_send(audio::Input::ModuleMessage(WAVETABLE_MODULE, ModuleMessage::Wavetable(table.clone())));
+                        }
+                        self.wavetables = [Some(table), self.wavetables[0].take()];
+                    },
+                    Err(err) => self.wavetable_status = format!("Failed to load: {err}"),
+                }
+                Task::none()
+            },
+
+            // Supersaw
+            Message::Supersaw(update) => {
+                match update {
+                    SupersawUpdate::Shape(shape) => self.supersaw_shape = shape,
+                    SupersawUpdate::Voices(count) => self.supersaw_voices = count,
+                    SupersawUpdate::DetuneCurve(curve) => self.supersaw_curve = curve,
+                    SupersawUpdate::RandomPhase(random) => self.supersaw_random_phase = random,
+                    _ => (),
+                }
+                self.send_module(SUPERSAW_MODULE, ModuleMessageUnion {supersaw: update});
+                Task::none()
+            },
+
+            // Noise
+            Message::NoiseSeed(seed) => {
+                if let Ok(value) = seed.parse() {
+                    self.send_module(NOISE_MODULE, ModuleMessageUnion {noise: NoiseUpdate::Seed(value)});
+                }
+                self.noise_seed = seed;
+                Task::none()
+            },
+
+            // Sampler
+            Message::SfzPath(path) => {
+                self.sfz_path = path;
+                Task::none()
+            },
+            Message::LoadInstrument => {
+                self.sfz_status = String::from("Loading");
+                let path = self.sfz_path.clone();
+                Task::perform(load(move || Instrument::load(&path)), Message::InstrumentLoaded)
+            },
+            Message::InstrumentLoaded(result) => {
+                match result {
+                    Ok(instrument) => {
+                        self.sfz_status = format!("{} regions", instrument.regions.len());
+                        if let Some(connection) = &mut self.audio_thread_connection {
+                            let messages = SAMPLER_MODULES
+                                .map(|module| (module, ModuleMessage::Instrument(instrument.clone())))
+                                .collect();
+                            let _ = connection.try_send(audio::Input::ModuleMessages(messages));
+                        }
+                        self.instruments = [Some(instrument), self.instruments[0].take()];
+                    },
+                    Err(err) => self.sfz_status = format!("Failed to load: {err}"),
                 }
                 Task::none()
             },
 
-            // Testing
-            Message::DelaySlider(time) => {
+            // Additive
+            Message::Additive(update) => {
+                match update {
+                    AdditiveUpdate::Spectrum(spectrum) => self.additive_spectrum = spectrum,
+                    AdditiveUpdate::Partials(count) => self.additive_partial_count = count,
+                    AdditiveUpdate::Partial(index, amplitude) => self.additive_partials[index] = amplitude,
+                    _ => (),
+                }
                 if let Some(connection) = &mut self.audio_thread_connection {
-                    let input = ModuleMessage::ComponentChange(ModuleMessageUnion {delay: audio::module::delay::DelayUpdate::Time(time / 100.0)});
-                    let _ = connection.try_send(audio::Input::ModuleMessage(50, input));
+                    let messages = ADDITIVE_MODULES
+                        .map(|module| (module, ModuleMessage::ComponentChange(ModuleMessageUnion {additive: update})))
+                        .collect();
+                    let _ = connection.try_send(audio::Input::ModuleMessages(messages));
+                }
+                Task::none()
+            },
+
+            // Tuning
+            Message::ScalePath(path) => {
+                self.scale_path = path;
+                Task::none()
+            },
+            Message::KeyboardMapPath(path) => {
+                self.keyboard_map_path = path;
+                Task::none()
+            },
+            Message::LoadTuning => self.load_tuning(),
+            Message::TuningLoaded(paths, result) => {
+                match result {
+                    Ok(tuning) => {
+                        let (scale, keyboard_map) = Arc::unwrap_or_clone(tuning);
+                        self.tuning_status = format!("{} notes per period", scale.size());
+                        self.scale = scale;
+                        self.keyboard_map = keyboard_map;
+                        self.tuning_paths = Some(paths);
+                        self.send_tuning();
+                        self.save_session();
+                    },
+                    Err(err) => self.tuning_status = format!("Failed to load: {err}"),
+                }
+                Task::none()
+            },
+            Message::ResetTuning => {
+                self.tuning_status = String::from("12-TET");
+                self.scale = Scale::equal();
+                self.keyboard_map = KeyboardMap::standard();
+                self.tuning_paths = None;
+                self.send_tuning();
+                self.save_session();
+                Task::none()
+            },
+            Message::ReferencePitch(pitch) => {
+                self.reference_pitch = pitch;
+                self.send_tuning();
+                Task::none()
+            },
+
+            // Presets
+            Message::ProgramChange(program) => {
+                self.program = program;
+                self.load_preset();
+                Task::none()
+            },
+            Message::PresetBank(bank) => {
+                self.bank_select = ((bank >> 7) as u8, (bank & 0x7F) as u8);
+                self.load_preset();
+                Task::none()
+            },
+            Message::PresetName(name) => {
+                self.preset_name = name;
+                Task::none()
+            },
+            Message::SavePreset => {
+                self.save_preset();
+                Task::none()
+            },
+
+            // Transport
+            Message::ClockSource(source) => {
+                self.send_module(CLOCK_MODULE, ModuleMessageUnion {clock: ClockUpdate::Source(source)});
+                self.clock_source = source;
+                Task::none()
+            },
+            Message::BeatsPerBar(beats_per_bar) => {
+                self.send_module(CLOCK_MODULE, ModuleMessageUnion {clock: ClockUpdate::BeatsPerBar(beats_per_bar)});
+                self.beats_per_bar = beats_per_bar;
+                Task::none()
+            },
+            Message::DelaySync(sync) => {
+                self.send_module(DELAY_MODULE, ModuleMessageUnion {delay: DelayUpdate::Sync(sync)});
+                self.delay_sync = sync;
+                Task::none()
+            },
+            Message::PwmSync(sync) => {
+                self.send_module(PWM_LFO_MODULE, ModuleMessageUnion {lfo: LfoUpdate::Sync(sync)});
+                self.pwm_sync = sync;
+                Task::none()
+            },
+
+            // MIDI file player
+            Message::MidiFilePath(path) => {
+                self.midi_file_path = path;
+                Task::none()
+            },
+            Message::LoadMidiFile => {
+                self.midi_file_status = String::from("Loading");
+                let path = self.midi_file_path.clone();
+                Task::perform(load(move || {
+                    std::fs::read(&path)
+                        .map_err(|err| err.to_string())
+                        .and_then(|bytes| midi::smf::parse(&bytes))
+                }), Message::MidiFileLoaded)
+            },
+            Message::MidiFileLoaded(result) => {
+                match result {
+                    Ok(sequence) => {
+                        self.midi_file_status = format!("{} events, {:.1}s", sequence.events.len(), sequence.length);
+                        self.sequence_length = sequence.length;
+                        self.player_position = 0.0;
+                        self.send_player(PlayerCommand::Load(sequence.clone()));
+                        self.sequences = [Some(sequence), self.sequences[0].take()];
+                    },
+                    Err(err) => self.midi_file_status = format!("Failed to load: {err}"),
+                }
+                Task::none()
+            },
+            Message::Player(command) => {
+                match command {
+                    PlayerCommand::Loop(looping) => self.player_looping = looping,
+                    PlayerCommand::Seek(position) => self.player_position = position,
+                    PlayerCommand::TempoScale(tempo_scale) => self.player_tempo_scale = tempo_scale,
+                    _ => (),
+                }
+                self.send_player(command);
+                Task::none()
+            },
+            Message::PlayerPosition(position) => {
+                self.player_position = position.min(self.sequence_length);
+                Task::none()
+            },
+
+            // Parameters
+            Message::ParameterChange(target, value) => {
+                self.set_parameter(target, value);
+                Task::none()
+            },
+            Message::Learn(target) => {
+                self.mappings.learn(target);
+                Task::none()
+            },
+            Message::CancelLearn => {
+                self.mappings.cancel_learn();
+                Task::none()
+            },
+            Message::MappingMin(index, min) => {
+                if let Some(mapping) = self.mappings.get_mut(index) {
+                    mapping.min = min;
                 }
-                self.delay_slider_value = time;
                 Task::none()
+            },
+            Message::MappingMax(index, max) => {
+                if let Some(mapping) = self.mappings.get_mut(index) {
+                    mapping.max = max;
+                }
+                Task::none()
+            },
+            Message::MappingCurve(index, curve) => {
+                if let Some(mapping) = self.mappings.get_mut(index) {
+                    mapping.curve = curve;
+                }
+                self.save_session();
+                Task::none()
+            },
+            Message::MappingRemove(index) => {
+                self.mappings.remove(index);
+                self.save_session();
+                Task::none()
+            },
+            Message::SaveSession => {
+                self.save_session();
+                Task::none()
+            },
+        }
+    }
+
+    fn set_parameter(&mut self, target: Target, value: f32) {
+        if !target.is_valid() {
+            eprintln!("Ignoring {target}, the module doesn't take it");
+            return;
+        }
+        if let Some(connection) = &mut self.audio_thread_connection {
+            let _ = connection.try_send(audio::Input::ModuleMessages(parameter_messages(target, value).collect()));
+        }
+        self.parameters.insert(target, value);
+        if target.parameter == Parameter::OscillatorFm {
+            self.send_modulator_level();
+        }
+        self.send_parameter_feedback(target, value);
+    }
+
+    /// The modulators only feed the oscillators' sync and FM inputs, so they are silenced while neither is used
+    fn send_modulator_level(&mut self) {
+        let used = self.oscillator_sync || self.parameters.iter()
+            .any(|(target, value)| target.parameter == Parameter::OscillatorFm && *value != 0.0);
+        let level = if used {1.0} else {0.0};
+        for module in MODULATOR_MODULES {
+            self.send_module(module, ModuleMessageUnion {analog: AnalogOscillatorUpdate::Level(level)});
+        }
+    }
+
+    /// Sends the value of a parameter back out as the CCs mapped to it, so controllers can show it
+    fn send_parameter_feedback(&mut self, target: Target, value: f32) {
+        if let Some(connection) = &mut self.midi_thread_connection {
+            for (controller, value) in self.mappings.feedback(target, value) {
+                let _ = connection.try_send(midi::Input::Send(midi::MidiMessage::ControlChange { channel: 0, controller, value }));
+            }
+        }
+    }
+
+    /// Feedback for every parameter that has been set
+    fn send_feedback(&mut self) {
+        for (target, value) in self.parameters.clone() {
+            self.send_parameter_feedback(target, value);
+        }
+    }
+
+    /// The audio thread sends generated notes and the clock straight to the MIDI thread
+    fn connect_midi_output(&mut self) {
+        if let (Some(audio), Some(midi)) = (&mut self.audio_thread_connection, &self.midi_thread_connection) {
+            let _ = audio.try_send(audio::Input::MidiOutput(midi.clone()));
+        }
+    }
+
+    fn bank(&self) -> u16 {
+        (self.bank_select.0 as u16) << 7 | self.bank_select.1 as u16
+    }
+
+    /// Sets every parameter from the selected preset, parameters it doesn't store go back to their default
+    fn load_preset(&mut self) {
+        let bank = match Bank::load(self.bank()) {
+            Ok(bank) => bank,
+            Err(err) => {
+                self.preset_status = format!("Failed to load bank {}: {err}", self.bank());
+                return;
+            },
+        };
+        let Some(preset) = bank.get(self.program) else {
+            self.preset_status = format!("No preset {} in bank {}", self.program + 1, self.bank());
+            return;
+        };
+
+        for target in self.parameters.keys().copied().collect::<Vec<_>>() {
+            self.parameters.insert(target, target.parameter.default_value());
+        }
+        self.parameters.extend(preset.parameters.iter().copied());
+
+        let messages = self.parameters.iter()
+            .flat_map(|(target, value)| parameter_messages(*target, *value))
+            .collect();
+        if let Some(connection) = &mut self.audio_thread_connection {
+            let _ = connection.try_send(audio::Input::ModuleMessages(messages));
+        }
+        self.send_modulator_level();
+
+        // Settings the preset doesn't store are left as they are
+        for (setting, value) in &preset.settings {
+            match preset_setting(setting, value) {
+                Some(msg) => {
+                    let _ = self.update(msg);
+                },
+                None => eprintln!("Invalid setting in preset: {setting} {value}"),
+            }
+        }
+
+        self.preset_name = preset.name.clone();
+        self.preset_status = format!("Loaded {}", preset.name);
+        self.send_feedback();
+    }
+
+    fn save_preset(&mut self) {
+        let mut bank = match Bank::load(self.bank()) {
+            Ok(bank) => bank,
+            Err(err) => {
+                self.preset_status = format!("Failed to load bank {}: {err}", self.bank());
+                return;
+            },
+        };
+
+        let mut parameters: Vec<(Target, f32)> = self.parameters.iter().map(|(target, value)| (*target, *value)).collect();
+        parameters.sort_by_key(|(target, _)| (target.destination, target.parameter.key()));
+        bank.insert(Preset {
+            program: self.program,
+            name: self.preset_name.clone(),
+            parameters,
+            settings: self.preset_settings(),
+        });
+
+        self.preset_status = match bank.save(self.bank()) {
+            Ok(()) => format!("Saved {}", self.preset_name),
+            Err(err) => format!("Failed to save: {err}"),
+        };
+    }
+
+    /// Choices a preset stores next to the parameters, each read back by `preset_setting`
+    fn preset_settings(&self) -> Vec<(String, String)> {
+        let on_off = |enabled: bool| String::from(if enabled {"on"} else {"off"});
+        let mut settings = vec![
+            ("oscillator-shape", self.oscillator_shape.to_string()),
+            ("oscillator-sync", on_off(self.oscillator_sync)),
+            ("pwm-sync", self.pwm_sync.to_string()),
+            ("delay-sync", self.delay_sync.to_string()),
+            ("voices", self.voice_count.to_string()),
+            ("steal-policy", self.steal_policy.to_string()),
+            ("note-priority", self.note_priority.to_string()),
+            ("legato", on_off(self.legato)),
+            ("unison", self.unison.to_string()),
+            ("glide-mode", self.glide_mode.to_string()),
+            ("glide-rate", self.glide_rate.to_string()),
+            ("velocity-curve", self.velocity_curve.to_string()),
+            ("arp-order", self.arp_order.to_string()),
+            ("arp-octaves", self.arp_octaves.to_string()),
+            ("arp-gate", self.arp_gate.to_string()),
+            ("arp-division", self.arp_division.to_string()),
+            ("arp-latch", on_off(self.arp_latch)),
+            ("arpeggiator", on_off(self.arp_enabled)),
+            ("supersaw-shape", self.supersaw_shape.to_string()),
+            ("supersaw-voices", self.supersaw_voices.to_string()),
+            ("supersaw-detune-curve", self.supersaw_curve.to_string()),
+            ("supersaw-random-phase", on_off(self.supersaw_random_phase)),
+            ("additive-spectrum", self.additive_spectrum.to_string()),
+            ("additive-partials", self.additive_partial_count.to_string()),
+        ];
+        settings.extend(self.velocity_breakpoints.iter().enumerate().map(|(index, velocity)| ("velocity-breakpoint", format!("{index} {velocity}"))));
+        settings.extend(self.additive_partials.iter().enumerate().map(|(index, amplitude)| ("additive-partial", format!("{index} {amplitude}"))));
+        if !self.noise_seed.is_empty() {
+            settings.push(("noise-seed", self.noise_seed.clone()));
+        }
+
+        settings.into_iter().map(|(setting, value)| (setting.to_string(), value)).collect()
+    }
+
+    fn save_session(&self) {
+        Session {
+            mappings: self.mappings.mappings().to_vec(),
+            virtual_port: self.virtual_port,
+            midi_output: match &self.midi_output_port {
+                midi::OutputPort::None => None,
+                midi::OutputPort::Port(name) => Some(name.clone()),
+            },
+            scale: self.tuning_paths.as_ref().map(|(scale, _)| scale.clone()),
+            keyboard_map: self.tuning_paths.as_ref().map(|(_, keyboard_map)| keyboard_map.clone()).filter(|path| !path.is_empty()),
+            reference_pitch: Some(self.reference_pitch),
+        }.save();
+    }
+
+    /// Reads the scale and mapping files, returns true if the tuning changed
+    fn load_tuning(&mut self) -> Task<Message> {
+        self.tuning_status = String::from("Loading");
+        let paths = (self.scale_path.clone(), self.keyboard_map_path.clone());
+        let (scale_path, keyboard_map_path) = paths.clone();
+        Task::perform(load(move || {
+            let scale = std::fs::read_to_string(&scale_path)
+                .map_err(|err| err.to_string())
+                .and_then(|contents| scala::parse_scale(&contents))?;
+            // Without a mapping file the scale starts on middle C
+            let keyboard_map = if keyboard_map_path.is_empty() {
+                KeyboardMap::standard()
+            } else {
+                std::fs::read_to_string(&keyboard_map_path)
+                    .map_err(|err| err.to_string())
+                    .and_then(|contents| scala::parse_keyboard_map(&contents))?
+            };
+            Ok((scale, keyboard_map))
+        }), move |result| Message::TuningLoaded(paths.clone(), result))
+    }
+
+    fn send_module(&mut self, id: usize, msg_union: ModuleMessageUnion) {
+        if let Some(connection) = &mut self.audio_thread_connection {
+            let _ = connection.try_send(audio::Input::ModuleMessage(id, ModuleMessage::ComponentChange(msg_union)));
+        }
+    }
+
+    /// Every module that turns keys into pitch gets the same tuning
+    fn send_tuning(&mut self) {
+        let tuning = match scala::tuning(&self.scale, &self.keyboard_map, self.reference_pitch) {
+            Ok(tuning) => Arc::new(tuning),
+            Err(err) => {
+                self.tuning_status = format!("Failed to load: {err}");
+                return;
+            },
+        };
+
+        if let Some(connection) = &mut self.audio_thread_connection {
+            for id in [MIDI_MODULE, SEQUENCER_MODULE] {
+                let _ = connection.try_send(audio::Input::ModuleMessage(id, ModuleMessage::Tuning(tuning.clone())));
             }
         }
+        self.tunings = [Some(tuning), self.tunings[0].take()];
+    }
+
+    fn send_player(&mut self, command: PlayerCommand) {
+        if let Some(connection) = &mut self.audio_thread_connection {
+            let _ = connection.try_send(audio::Input::Player(command));
+        }
+    }
+
+    fn send_midi(&mut self, update: MidiUpdate) -> Task<Message> {
+        self.send_module(MIDI_MODULE, ModuleMessageUnion {midi: update});
+        Task::none()
     }
 
     pub fn view(&self) -> Element<Message> {
@@ -126,54 +1105,538 @@ impl Synth {
                 button("Sine!")
                     .width(Length::Fill)
                     .height(Length::Fill)
-                    .on_press(Message::ComponentChange(
-                        1,
-                        ModuleMessage::ComponentChange(
-                            ModuleMessageUnion {analog: audio::module::analog::AnalogOscillatorUpdate::Shape(audio::module::analog::WaveShape::Sine)}
-                        ),
-                    )),
+                    .on_press(Message::OscillatorShape(WaveShape::Sine)),
                 button("Saw!")
                     .width(Length::Fill)
                     .height(Length::Fill)
-                    .on_press(Message::ComponentChange(
-                        1,
-                        ModuleMessage::ComponentChange(
-                            ModuleMessageUnion {analog: audio::module::analog::AnalogOscillatorUpdate::Shape(audio::module::analog::WaveShape::Saw)}
-                        ),
-                    )),
+                    .on_press(Message::OscillatorShape(WaveShape::Saw)),
             ],
             row![
                 button("Square!")
                     .width(Length::Fill)
                     .height(Length::Fill)
-                    .on_press(Message::ComponentChange(
-                        1,
-                        ModuleMessage::ComponentChange(
-                            ModuleMessageUnion {analog: audio::module::analog::AnalogOscillatorUpdate::Shape(audio::module::analog::WaveShape::Square)}
-                        ),
-                    )),
+                    .on_press(Message::OscillatorShape(WaveShape::Square)),
                 button("Triangle!")
                     .width(Length::Fill)
                     .height(Length::Fill)
-                    .on_press(Message::ComponentChange(
-                        1,
-                        ModuleMessage::ComponentChange(
-                            ModuleMessageUnion {analog: audio::module::analog::AnalogOscillatorUpdate::Shape(audio::module::analog::WaveShape::Triangle)}
-                        ),
-                    )),
+                    .on_press(Message::OscillatorShape(WaveShape::Triangle)),
             ],
-            slider(0.0..=600.0, self.delay_slider_value, Message::DelaySlider)
-                .width(Length::Fill),
+            row![
+                pick_list(ClockSource::ALL, Some(self.clock_source), Message::ClockSource),
+                pick_list([2, 3, 4, 5, 6, 7], Some(self.beats_per_bar), Message::BeatsPerBar),
+                button("Start").on_press(Message::ComponentChange(CLOCK_MODULE, ModuleMessage::ComponentChange(ModuleMessageUnion {clock: ClockUpdate::Start}))),
+                button("Stop").on_press(Message::ComponentChange(CLOCK_MODULE, ModuleMessage::ComponentChange(ModuleMessageUnion {clock: ClockUpdate::Stop}))),
+                button("Continue").on_press(Message::ComponentChange(CLOCK_MODULE, ModuleMessage::ComponentChange(ModuleMessageUnion {clock: ClockUpdate::Continue}))),
+            ].spacing(10),
+            self.parameter_slider(Target::new(CLOCK_MODULE, Parameter::ClockBpm)),
+            row![
+                self.parameter_slider(Target::new(DELAY_MODULE, Parameter::DelayTime)),
+                pick_list(TempoSync::all(), Some(self.delay_sync), Message::DelaySync),
+            ].spacing(10),
+            self.mapping_list(),
+            self.voice_controls(),
+            self.response_controls(),
+            self.oscillator_controls(),
+            self.arpeggiator_controls(),
+            self.sequencer.view(),
+            self.wavetable_controls(),
+            self.supersaw_controls(),
+            self.noise_controls(),
+            self.fm_controls(),
+            self.sampler_controls(),
+            self.additive_controls(),
+            self.tuning_controls(),
+            self.preset_controls(),
+            self.keyboard_controls(),
+            self.midi_file_player(),
+            row![
+                text("Receive"),
+                pick_list(midi::ReceiveChannel::ALL, Some(self.receive_channel), Message::ReceiveChannel),
+                pick_list(MpeZone::ALL, Some(self.mpe_zone), Message::MpeZone),
+            ].spacing(10),
+            checkbox("Virtual MIDI input port", self.virtual_port)
+                .on_toggle(Message::VirtualPortToggled),
+            self.midi_port_list(),
+            self.midi_output_controls(),
         ].into()
     }
 
+    /// A slider for a parameter, right click to map it to a MIDI CC
+    fn parameter_slider(&self, target: Target) -> Element<'_, Message> {
+        let range = target.parameter.range();
+        let value = self.parameter(target);
+        let step = (range.end() - range.start()) / 1000.0;
+
+        row![
+            text(target.parameter.to_string()).width(150),
+            mouse_area(
+                slider(range, value, move |value| Message::ParameterChange(target, value))
+                    .step(step)
+                    .width(Length::Fill)
+            ).on_right_press(Message::Learn(target)),
+        ].spacing(10).into()
+    }
+
+    fn parameter(&self, target: Target) -> f32 {
+        self.parameters.get(&target).copied().unwrap_or(target.parameter.default_value())
+    }
+
+    fn mapping_list(&self) -> Element<'_, Message> {
+        let learning = self.mappings.learning().map(|target| {
+            row![
+                text(format!("Move a controller to map {target}")),
+                button("Cancel").on_press(Message::CancelLearn),
+            ].spacing(10).into()
+        });
+
+        let mappings = self.mappings.mappings().iter().enumerate().map(|(index, mapping)| {
+            let range = mapping.target.parameter.range();
+            let step = (range.end() - range.start()) / 1000.0;
+
+            row![
+                text(format!("CC {} -> {}", mapping.controller, mapping.target)).width(250),
+                slider(range.clone(), mapping.min, move |min| Message::MappingMin(index, min))
+                    .step(step)
+                    .on_release(Message::SaveSession),
+                slider(range, mapping.max, move |max| Message::MappingMax(index, max))
+                    .step(step)
+                    .on_release(Message::SaveSession),
+                pick_list(Curve::ALL, Some(mapping.curve), move |curve| Message::MappingCurve(index, curve)),
+                button("Remove").on_press(Message::MappingRemove(index)),
+            ].spacing(10).into()
+        });
+
+        Column::with_children(learning.into_iter().chain(mappings)).into()
+    }
+
+    fn voice_controls(&self) -> Element<'_, Message> {
+        column![
+            row![
+                text("Voices"),
+                pick_list((1..=POLY_VOICE_COUNT).collect::<Vec<_>>(), Some(self.voice_count), Message::VoiceCount),
+                pick_list(StealPolicy::ALL, Some(self.steal_policy), Message::StealPolicy),
+                pick_list(NotePriority::ALL, Some(self.note_priority), Message::NotePriority),
+                checkbox("Legato", self.legato).on_toggle(Message::Legato),
+                text("Unison"),
+                pick_list((1..=8).collect::<Vec<_>>(), Some(self.unison), Message::Unison),
+                button("Panic").on_press(Message::Panic),
+            ].spacing(10),
+            self.parameter_slider(Target::new(MIDI_MODULE, Parameter::UnisonDetune)),
+            row![
+                self.parameter_slider(Target::new(MIDI_MODULE, Parameter::GlideTime)),
+                pick_list(GlideMode::ALL, Some(self.glide_mode), Message::GlideMode),
+                pick_list(GlideRate::ALL, Some(self.glide_rate), Message::GlideRate),
+            ].spacing(10),
+        ].spacing(5).into()
+    }
+
+    /// Velocity curve and pitch bend range, for matching the feel of a keyboard
+    fn response_controls(&self) -> Element<'_, Message> {
+        let curve: Element<'_, Message> = match self.velocity_curve {
+            VelocityCurve::Custom => Row::with_children(
+                self.velocity_breakpoints.iter().enumerate().map(|(index, velocity)| {
+                    slider(0..=127, *velocity, move |velocity| Message::VelocityBreakpoint(index, velocity))
+                        .width(80)
+                        .into()
+                })
+            ).spacing(10).into(),
+            VelocityCurve::Fixed => self.parameter_slider(Target::new(MIDI_MODULE, Parameter::FixedVelocity)),
+            _ => Row::new().into(),
+        };
+
+        column![
+            row![
+                pick_list(VelocityCurve::ALL, Some(self.velocity_curve), Message::VelocityCurve),
+                curve,
+            ].spacing(10),
+            row![
+                self.parameter_slider(Target::new(MIDI_MODULE, Parameter::BendUp)),
+                self.parameter_slider(Target::new(MIDI_MODULE, Parameter::BendDown)),
+            ].spacing(10),
+        ].spacing(5).into()
+    }
+
+    fn arpeggiator_controls(&self) -> Element<'_, Message> {
+        row![
+            checkbox("Arpeggiator", self.arp_enabled)
+                .on_toggle(|enabled| Message::Arpeggiator(ArpeggiatorUpdate::Enabled(enabled))),
+            pick_list(ArpOrder::ALL, Some(self.arp_order), |order| Message::Arpeggiator(ArpeggiatorUpdate::Order(order))),
+            pick_list([1, 2, 3, 4], Some(self.arp_octaves), |octaves| Message::Arpeggiator(ArpeggiatorUpdate::Octaves(octaves))),
+            text("octaves"),
+            pick_list(NoteDivision::all(), Some(self.arp_division), |division| Message::Arpeggiator(ArpeggiatorUpdate::Division(division))),
+            text("Gate"),
+            slider(0.05..=1.0, self.arp_gate, |gate| Message::Arpeggiator(ArpeggiatorUpdate::Gate(gate)))
+                .step(0.01)
+                .width(150),
+            checkbox("Latch", self.arp_latch)
+                .on_toggle(|latch| Message::Arpeggiator(ArpeggiatorUpdate::Latch(latch))),
+        ].spacing(10).into()
+    }
+
+    fn oscillator_controls(&self) -> Element<'_, Message> {
+        let oscillators = |parameter| Target::voices(VoiceGroup::Oscillators, parameter);
+        column![
+            self.parameter_slider(oscillators(Parameter::PulseWidth)),
+            row![
+                self.parameter_slider(oscillators(Parameter::PwmDepth)),
+                self.parameter_slider(Target::new(PWM_LFO_MODULE, Parameter::PwmRate)),
+                pick_list(TempoSync::all(), Some(self.pwm_sync), Message::PwmSync),
+            ].spacing(10),
+            row![
+                checkbox("Sync", self.oscillator_sync).on_toggle(Message::OscillatorSync),
+                self.parameter_slider(Target::voices(VoiceGroup::Modulators, Parameter::ModulatorPitch)),
+                self.parameter_slider(oscillators(Parameter::OscillatorFm)),
+            ].spacing(10),
+        ].spacing(5).into()
+    }
+
+    /// The wavetable voice follows the mono output, the mod wheel moves through the frames
+    fn wavetable_controls(&self) -> Element<'_, Message> {
+        column![
+            row![
+                text_input("Path to a .wav wavetable", &self.wavetable_path)
+                    .on_input(Message::WavetablePath)
+                    .on_submit(Message::LoadWavetable),
+                button("Load").on_press(Message::LoadWavetable),
+                text(&self.wavetable_status),
+            ].spacing(10),
+            self.parameter_slider(Target::new(WAVETABLE_MODULE, Parameter::WavetablePosition)),
+            self.parameter_slider(Target::new(WAVETABLE_MODULE, Parameter::WavetableGain)),
+        ].spacing(5).into()
+    }
+
+    /// Detuned copies that follow the mono output with the wavetable voice's envelope
+    fn supersaw_controls(&self) -> Element<'_, Message> {
+        column![
+            row![
+                pick_list(WaveShape::ALL, Some(self.supersaw_shape), |shape| Message::Supersaw(SupersawUpdate::Shape(shape))),
+                pick_list((1..=MAX_SUPERSAW_VOICES).collect::<Vec<usize>>(), Some(self.supersaw_voices), |count| Message::Supersaw(SupersawUpdate::Voices(count))),
+                pick_list(DetuneCurve::ALL, Some(self.supersaw_curve), |curve| Message::Supersaw(SupersawUpdate::DetuneCurve(curve))),
+                checkbox("Random phase", self.supersaw_random_phase)
+                    .on_toggle(|random| Message::Supersaw(SupersawUpdate::RandomPhase(random))),
+            ].spacing(10),
+            row![
+                self.parameter_slider(Target::new(SUPERSAW_MODULE, Parameter::SupersawDetune)),
+                self.parameter_slider(Target::new(SUPERSAW_MODULE, Parameter::SupersawMix)),
+            ].spacing(10),
+            row![
+                self.parameter_slider(Target::new(SUPERSAW_MODULE, Parameter::SupersawSpread)),
+                self.parameter_slider(Target::new(SUPERSAW_MODULE, Parameter::SupersawGain)),
+            ].spacing(10),
+        ].spacing(5).into()
+    }
+
+    /// Noise goes through the wavetable voice, the random value steps on every beat and moves the wavetable position
+    fn noise_controls(&self) -> Element<'_, Message> {
+        column![
+            row![
+                self.parameter_slider(Target::new(NOISE_MODULE, Parameter::NoiseGain)),
+                text_input("Seed", &self.noise_seed)
+                    .on_input(Message::NoiseSeed)
+                    .width(120),
+            ].spacing(10),
+            row![
+                self.parameter_slider(Target::new(NOISE_MODULE, Parameter::NoiseRandomDepth)),
+                self.parameter_slider(Target::new(NOISE_MODULE, Parameter::NoiseSmoothing)),
+            ].spacing(10),
+        ].spacing(5).into()
+    }
+
+    /// Every poly voice plays the FM voice alongside its oscillator, all set alike
+    fn fm_controls(&self) -> Element<'_, Message> {
+        let target = |parameter| Target::voices(VoiceGroup::Fm, parameter);
+        let algorithm = self.parameter(target(Parameter::FmAlgorithm)) as usize;
+
+        let operator = |index: usize| -> Element<'_, Message> {
+            let fixed = self.parameter(target(Parameter::FmFixed(index))) >= 0.5;
+            let frequency = if fixed {Parameter::FmFixedFrequency(index)} else {Parameter::FmRatio(index)};
+
+            column![
+                row![
+                    checkbox("Fixed", fixed)
+                        .on_toggle(move |fixed| Message::ParameterChange(target(Parameter::FmFixed(index)), if fixed {1.0} else {0.0})),
+                    self.parameter_slider(target(frequency)),
+                    self.parameter_slider(target(Parameter::FmLevel(index))),
+                    self.parameter_slider(target(Parameter::FmFeedback(index))),
+                ].spacing(10),
+                row![
+                    self.parameter_slider(target(Parameter::FmAttack(index))),
+                    self.parameter_slider(target(Parameter::FmDecay(index))),
+                    self.parameter_slider(target(Parameter::FmSustain(index))),
+                    self.parameter_slider(target(Parameter::FmRelease(index))),
+                ].spacing(10),
+            ].spacing(2).into()
+        };
+
+        column![
+            row![
+                text("FM algorithm"),
+                pick_list((1..=ALGORITHMS.len()).collect::<Vec<_>>(), Some(algorithm + 1), move |algorithm| {
+                    Message::ParameterChange(target(Parameter::FmAlgorithm), (algorithm - 1) as f32)
+                }),
+                text(ALGORITHMS[algorithm.min(ALGORITHMS.len() - 1)].name),
+            ].spacing(10),
+            self.parameter_slider(target(Parameter::FmGain)),
+            Column::with_children((0..OPERATOR_COUNT).map(operator)).spacing(5),
+        ].spacing(5).into()
+    }
+
+    /// Every poly voice plays the instrument alongside its oscillator
+    fn sampler_controls(&self) -> Element<'_, Message> {
+        column![
+            row![
+                text_input("Path to a .sfz instrument", &self.sfz_path)
+                    .on_input(Message::SfzPath)
+                    .on_submit(Message::LoadInstrument),
+                button("Load").on_press(Message::LoadInstrument),
+                text(&self.sfz_status),
+            ].spacing(10),
+            self.parameter_slider(Target::voices(VoiceGroup::Samplers, Parameter::SamplerGain)),
+        ].spacing(5).into()
+    }
+
+    /// Every poly voice sums its partials alongside its oscillator, with the same envelope and filter
+    fn additive_controls(&self) -> Element<'_, Message> {
+        let additive = |parameter| Target::voices(VoiceGroup::Additive, parameter);
+        let partials: Element<'_, Message> = match self.additive_spectrum {
+            Spectrum::Custom => Row::with_children(
+                self.additive_partials.iter().enumerate().map(|(index, amplitude)| {
+                    slider(0.0..=1.0, *amplitude, move |amplitude| Message::Additive(AdditiveUpdate::Partial(index, amplitude)))
+                        .step(0.01)
+                        .width(40)
+                        .into()
+                })
+            ).spacing(5).into(),
+            _ => Row::new().into(),
+        };
+
+        column![
+            row![
+                pick_list(Spectrum::ALL, Some(self.additive_spectrum), |spectrum| Message::Additive(AdditiveUpdate::Spectrum(spectrum))),
+                pick_list(
+                    [16, 32, 64, 128, MAX_PARTIALS],
+                    Some(self.additive_partial_count),
+                    |count| Message::Additive(AdditiveUpdate::Partials(count)),
+                ),
+                text("partials"),
+                partials,
+            ].spacing(10),
+            row![
+                self.parameter_slider(additive(Parameter::AdditiveBrightness)),
+                self.parameter_slider(additive(Parameter::AdditiveOddEven)),
+            ].spacing(10),
+            row![
+                self.parameter_slider(additive(Parameter::AdditiveStretch)),
+                self.parameter_slider(additive(Parameter::AdditiveGain)),
+            ].spacing(10),
+        ].spacing(5).into()
+    }
+
+    fn tuning_controls(&self) -> Element<'_, Message> {
+        column![
+            row![
+                text_input("Path to a .scl file", &self.scale_path)
+                    .on_input(Message::ScalePath)
+                    .on_submit(Message::LoadTuning),
+                text_input("Optional .kbm file", &self.keyboard_map_path)
+                    .on_input(Message::KeyboardMapPath)
+                    .on_submit(Message::LoadTuning),
+                button("Load").on_press(Message::LoadTuning),
+                button("12-TET").on_press(Message::ResetTuning),
+                text(&self.tuning_status),
+            ].spacing(10),
+            row![
+                text(format!("A4 {:.1} Hz", self.reference_pitch)).width(150),
+                slider(415.0..=466.0, self.reference_pitch, Message::ReferencePitch)
+                    .step(0.1)
+                    .width(Length::Fill),
+            ].spacing(10),
+        ].spacing(5).into()
+    }
+
+    fn preset_controls(&self) -> Element<'_, Message> {
+        row![
+            text("Bank"),
+            pick_list((0..16).collect::<Vec<u16>>(), Some(self.bank()), Message::PresetBank),
+            text("Program"),
+            pick_list((1..=128).collect::<Vec<u8>>(), Some(self.program + 1), |program| Message::ProgramChange(program - 1)),
+            text_input("Preset name", &self.preset_name)
+                .on_input(Message::PresetName)
+                .on_submit(Message::SavePreset)
+                .width(200),
+            button("Save").on_press(Message::SavePreset),
+            text(&self.preset_status),
+        ].spacing(10).into()
+    }
+
+    fn keyboard_controls(&self) -> Element<'_, Message> {
+        column![
+            row![
+                text(format!(
+                    "Octave {} (z/x), velocity {} (c/v)",
+                    self.keyboard.octave(),
+                    self.keyboard.velocity(),
+                )),
+                pick_list(VelocityMode::ALL, Some(self.keyboard.velocity_mode()), Message::VelocityMode),
+            ].spacing(10),
+            self.keyboard.piano(),
+        ].spacing(5).into()
+    }
+
+    fn midi_file_player(&self) -> Element<'_, Message> {
+        column![
+            row![
+                text_input("Path to a .mid file", &self.midi_file_path)
+                    .on_input(Message::MidiFilePath)
+                    .on_submit(Message::LoadMidiFile),
+                button("Load").on_press(Message::LoadMidiFile),
+                text(&self.midi_file_status),
+            ].spacing(10),
+            row![
+                button("Play").on_press(Message::Player(PlayerCommand::Play)),
+                button("Stop").on_press(Message::Player(PlayerCommand::Stop)),
+                checkbox("Loop", self.player_looping)
+                    .on_toggle(|looping| Message::Player(PlayerCommand::Loop(looping))),
+                text(format!("Speed {:.2}x", self.player_tempo_scale)),
+                slider(0.25..=2.0, self.player_tempo_scale, |tempo_scale| Message::Player(PlayerCommand::TempoScale(tempo_scale)))
+                    .step(0.05)
+                    .width(150),
+            ].spacing(10),
+            slider(0.0..=self.sequence_length, self.player_position, |position| Message::Player(PlayerCommand::Seek(position)))
+                .step(0.1),
+        ].spacing(5).into()
+    }
+
+    fn midi_output_controls(&self) -> Element<'_, Message> {
+        let ports: Vec<midi::OutputPort> = std::iter::once(midi::OutputPort::None)
+            .chain(self.midi_output_ports.iter().cloned().map(midi::OutputPort::Port))
+            .collect();
+
+        row![
+            text("Send"),
+            pick_list(ports, Some(self.midi_output_port.clone()), Message::MidiOutputPort),
+            pick_list((1..=16).collect::<Vec<u8>>(), Some(self.midi_output_channel + 1), |channel| Message::MidiOutputChannel(channel - 1)),
+        ].spacing(10).into()
+    }
+
+    fn midi_port_list(&self) -> Element<'_, Message> {
+        if self.midi_ports.is_empty() {
+            return text("No MIDI inputs found").into();
+        }
+
+        Column::with_children(
+            self.midi_ports.iter().map(|port| {
+                let name = port.name.clone();
+                checkbox(port.name.as_str(), port.enabled)
+                    .on_toggle(move |_| Message::MidiPortToggled(name.clone()))
+                    .into()
+            })
+        ).into()
+    }
+
     pub fn subscription(&self) -> Subscription<Message> {
         Subscription::batch(
             [
                 Subscription::run(audio::render_audio),
                 Subscription::run(midi::listen),
                 window::close_requests().map(Message::Close),
+                iced_keyboard::on_key_press(|key, modifiers| {
+                    (!modifiers.command()).then_some(Message::ComputerKeyPress(key))
+                }),
+                iced_keyboard::on_key_release(|key, _| Some(Message::ComputerKeyRelease(key))),
             ]
         )
     }
 }
+
+/// Reads a file on a blocking thread, so a large one doesn't hold up the GUI
+async fn load<T: Send + Sync + 'static>(read: impl FnOnce() -> Result<T, String> + Send + 'static) -> Result<Arc<T>, String> {
+    tokio::task::spawn_blocking(move || read().map(Arc::new))
+        .await
+        .map_err(|err| err.to_string())?
+}
+
+/// The message that restores a setting saved by `preset_settings`, `None` if the value is out of range
+fn preset_setting(setting: &str, value: &str) -> Option<Message> {
+    let on = || match value {
+        "on" => Some(true),
+        "off" => Some(false),
+        _ => None,
+    };
+    let count = |range: RangeInclusive<usize>| value.parse().ok().filter(|count| range.contains(count));
+    let indexed = || value.split_once(' ').map(|(index, value)| (index.parse::<usize>().ok(), value));
+
+    let message = match setting {
+        "oscillator-shape" => Message::OscillatorShape(named(WaveShape::ALL, value)?),
+        "oscillator-sync" => Message::OscillatorSync(on()?),
+        "pwm-sync" => Message::PwmSync(named(TempoSync::all(), value)?),
+        "delay-sync" => Message::DelaySync(named(TempoSync::all(), value)?),
+        "voices" => Message::VoiceCount(count(1..=POLY_VOICE_COUNT)?),
+        "steal-policy" => Message::StealPolicy(named(StealPolicy::ALL, value)?),
+        "note-priority" => Message::NotePriority(named(NotePriority::ALL, value)?),
+        "legato" => Message::Legato(on()?),
+        "unison" => Message::Unison(count(1..=8)?),
+        "glide-mode" => Message::GlideMode(named(GlideMode::ALL, value)?),
+        "glide-rate" => Message::GlideRate(named(GlideRate::ALL, value)?),
+        "velocity-curve" => Message::VelocityCurve(named(VelocityCurve::ALL, value)?),
+        "velocity-breakpoint" => match indexed()? {
+            (Some(index), velocity) if index < VELOCITY_BREAKPOINT_COUNT => {
+                Message::VelocityBreakpoint(index, velocity.parse().ok().filter(|velocity| *velocity <= 127)?)
+            },
+            _ => return None,
+        },
+        "arp-order" => Message::Arpeggiator(ArpeggiatorUpdate::Order(named(ArpOrder::ALL, value)?)),
+        "arp-octaves" => Message::Arpeggiator(ArpeggiatorUpdate::Octaves(value.parse().ok().filter(|octaves| (1..=4).contains(octaves))?)),
+        "arp-gate" => Message::Arpeggiator(ArpeggiatorUpdate::Gate(value.parse().ok().filter(|gate| (0.0..=1.0).contains(gate))?)),
+        "arp-division" => Message::Arpeggiator(ArpeggiatorUpdate::Division(named(NoteDivision::all(), value)?)),
+        "arp-latch" => Message::Arpeggiator(ArpeggiatorUpdate::Latch(on()?)),
+        "arpeggiator" => Message::Arpeggiator(ArpeggiatorUpdate::Enabled(on()?)),
+        "supersaw-shape" => Message::Supersaw(SupersawUpdate::Shape(named(WaveShape::ALL, value)?)),
+        "supersaw-voices" => Message::Supersaw(SupersawUpdate::Voices(count(1..=MAX_SUPERSAW_VOICES)?)),
+        "supersaw-detune-curve" => Message::Supersaw(SupersawUpdate::DetuneCurve(named(DetuneCurve::ALL, value)?)),
+        "supersaw-random-phase" => Message::Supersaw(SupersawUpdate::RandomPhase(on()?)),
+        "additive-spectrum" => Message::Additive(AdditiveUpdate::Spectrum(named(Spectrum::ALL, value)?)),
+        "additive-partials" => Message::Additive(AdditiveUpdate::Partials(count(1..=MAX_PARTIALS)?)),
+        "additive-partial" => match indexed()? {
+            (Some(index), amplitude) if index < EDITABLE_PARTIALS => {
+                Message::Additive(AdditiveUpdate::Partial(index, amplitude.parse().ok().filter(|amplitude| (0.0..=1.0).contains(amplitude))?))
+            },
+            _ => return None,
+        },
+        "noise-seed" => Message::NoiseSeed(value.to_string()),
+        _ => return None,
+    };
+    Some(message)
+}
+
+/// The option with the name shown for it in the GUI, which is how presets store choices
+fn named<T: ToString>(options: impl IntoIterator<Item = T>, name: &str) -> Option<T> {
+    options.into_iter().find(|option| option.to_string() == name)
+}
+
+/// The messages that set a parameter, once for every module it targets
+fn parameter_messages(target: Target, value: f32) -> impl Iterator<Item = (usize, ModuleMessage)> {
+    target.modules().map(move |module| (module, target.parameter.message(value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_settings_are_checked() {
+        assert!(matches!(preset_setting("oscillator-shape", "Square"), Some(Message::OscillatorShape(WaveShape::Square))));
+        assert!(matches!(preset_setting("arp-order", "As played"), Some(Message::Arpeggiator(ArpeggiatorUpdate::Order(ArpOrder::AsPlayed)))));
+        assert!(matches!(preset_setting("velocity-breakpoint", "2 90"), Some(Message::VelocityBreakpoint(2, 90))));
+        assert!(matches!(preset_setting("legato", "on"), Some(Message::Legato(true))));
+
+        assert!(preset_setting("oscillator-shape", "Squiggle").is_none());
+        assert!(preset_setting("voices", "0").is_none());
+        assert!(preset_setting("voices", "17").is_none());
+        assert!(preset_setting("velocity-breakpoint", "9 90").is_none());
+        assert!(preset_setting("velocity-breakpoint", "2 200").is_none());
+        assert!(preset_setting("additive-partial", "16 0.5").is_none());
+        assert!(preset_setting("legato", "yes").is_none());
+        assert!(preset_setting("no-such-setting", "on").is_none());
+    }
+}
@@ -0,0 +1,579 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::audio::ModuleKind;
+use crate::audio::module::{ModuleMessage, ModuleMessageUnion};
+use crate::audio::module::additive::AdditiveUpdate;
+use crate::audio::module::analog::AnalogOscillatorUpdate;
+use crate::audio::module::butterworth::ButterworthUpdate;
+use crate::audio::module::chorus::ChorusUpdate;
+use crate::audio::module::clock::ClockUpdate;
+use crate::audio::module::delay::DelayUpdate;
+use crate::audio::module::envelope::EnvelopeUpdate;
+use crate::audio::module::fm::{FmUpdate, ALGORITHMS};
+use crate::audio::module::lfo::LfoUpdate;
+use crate::audio::module::midi::MidiUpdate;
+use crate::audio::module::noise::NoiseUpdate;
+use crate::audio::module::reverb::ReverbUpdate;
+use crate::audio::module::sampler::SamplerUpdate;
+use crate::audio::module::supersaw::SupersawUpdate;
+use crate::audio::module::wavetable::WavetableUpdate;
+
+/// A continuous module parameter that can be set from a single value
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Parameter {
+    AdditiveBrightness,
+    AdditiveGain,
+    AdditiveOddEven,
+    AdditiveStretch,
+    AnalogFrequency,
+    AnalogPhase,
+    BendDown,
+    BendUp,
+    ButterworthFrequency,
+    ChorusTime,
+    ChorusRatio,
+    ClockBpm,
+    DelayTime,
+    DelayRatio,
+    EnvelopeAttack,
+    EnvelopeDecay,
+    EnvelopeRelease,
+    EnvelopeSustain,
+    FixedVelocity,
+    FmAlgorithm,
+    FmAttack(usize),
+    FmDecay(usize),
+    FmFeedback(usize),
+    FmFixed(usize),
+    FmFixedFrequency(usize),
+    FmGain,
+    FmLevel(usize),
+    FmRatio(usize),
+    FmRelease(usize),
+    FmSustain(usize),
+    GlideTime,
+    ModulatorPitch,
+    NoiseGain,
+    NoiseRandomDepth,
+    NoiseSmoothing,
+    OscillatorFm,
+    PulseWidth,
+    PwmDepth,
+    PwmRate,
+    ReverbWet,
+    SamplerGain,
+    SupersawDetune,
+    SupersawGain,
+    SupersawMix,
+    SupersawSpread,
+    UnisonDetune,
+    WavetableGain,
+    WavetablePosition,
+}
+
+impl Parameter {
+    pub const ALL: [Parameter; 75] = [
+        Parameter::AdditiveBrightness,
+        Parameter::AdditiveGain,
+        Parameter::AdditiveOddEven,
+        Parameter::AdditiveStretch,
+        Parameter::AnalogFrequency,
+        Parameter::AnalogPhase,
+        Parameter::BendDown,
+        Parameter::BendUp,
+        Parameter::ButterworthFrequency,
+        Parameter::ChorusTime,
+        Parameter::ChorusRatio,
+        Parameter::ClockBpm,
+        Parameter::DelayTime,
+        Parameter::DelayRatio,
+        Parameter::EnvelopeAttack,
+        Parameter::EnvelopeDecay,
+        Parameter::EnvelopeRelease,
+        Parameter::EnvelopeSustain,
+        Parameter::FixedVelocity,
+        Parameter::FmAlgorithm,
+        Parameter::FmAttack(0), Parameter::FmAttack(1), Parameter::FmAttack(2), Parameter::FmAttack(3),
+        Parameter::FmDecay(0), Parameter::FmDecay(1), Parameter::FmDecay(2), Parameter::FmDecay(3),
+        Parameter::FmFeedback(0), Parameter::FmFeedback(1), Parameter::FmFeedback(2), Parameter::FmFeedback(3),
+        Parameter::FmFixed(0), Parameter::FmFixed(1), Parameter::FmFixed(2), Parameter::FmFixed(3),
+        Parameter::FmFixedFrequency(0), Parameter::FmFixedFrequency(1), Parameter::FmFixedFrequency(2), Parameter::FmFixedFrequency(3),
+        Parameter::FmGain,
+        Parameter::FmLevel(0), Parameter::FmLevel(1), Parameter::FmLevel(2), Parameter::FmLevel(3),
+        Parameter::FmRatio(0), Parameter::FmRatio(1), Parameter::FmRatio(2), Parameter::FmRatio(3),
+        Parameter::FmRelease(0), Parameter::FmRelease(1), Parameter::FmRelease(2), Parameter::FmRelease(3),
+        Parameter::FmSustain(0), Parameter::FmSustain(1), Parameter::FmSustain(2), Parameter::FmSustain(3),
+        Parameter::GlideTime,
+        Parameter::ModulatorPitch,
+        Parameter::NoiseGain,
+        Parameter::NoiseRandomDepth,
+        Parameter::NoiseSmoothing,
+        Parameter::OscillatorFm,
+        Parameter::PulseWidth,
+        Parameter::PwmDepth,
+        Parameter::PwmRate,
+        Parameter::ReverbWet,
+        Parameter::SamplerGain,
+        Parameter::SupersawDetune,
+        Parameter::SupersawGain,
+        Parameter::SupersawMix,
+        Parameter::SupersawSpread,
+        Parameter::UnisonDetune,
+        Parameter::WavetableGain,
+        Parameter::WavetablePosition,
+    ];
+
+    pub fn message(&self, value: f32) -> ModuleMessage {
+        let msg_union = match self {
+            Parameter::AdditiveBrightness => ModuleMessageUnion {additive: AdditiveUpdate::Brightness(value)},
+            Parameter::AdditiveGain => ModuleMessageUnion {additive: AdditiveUpdate::Gain(value)},
+            Parameter::AdditiveOddEven => ModuleMessageUnion {additive: AdditiveUpdate::OddEven(value)},
+            Parameter::AdditiveStretch => ModuleMessageUnion {additive: AdditiveUpdate::Stretch(value)},
+            Parameter::AnalogFrequency => ModuleMessageUnion {analog: AnalogOscillatorUpdate::Frequency(value)},
+            Parameter::AnalogPhase => ModuleMessageUnion {analog: AnalogOscillatorUpdate::Phase(value)},
+            Parameter::BendDown => ModuleMessageUnion {midi: MidiUpdate::BendDown(value)},
+            Parameter::BendUp => ModuleMessageUnion {midi: MidiUpdate::BendUp(value)},
+            Parameter::ButterworthFrequency => ModuleMessageUnion {butterworth: ButterworthUpdate::Frequency(value)},
+            Parameter::ChorusTime => ModuleMessageUnion {chorus: ChorusUpdate::Time(value)},
+            Parameter::ChorusRatio => ModuleMessageUnion {chorus: ChorusUpdate::Ratio(value)},
+            Parameter::ClockBpm => ModuleMessageUnion {clock: ClockUpdate::Bpm(value)},
+            Parameter::DelayTime => ModuleMessageUnion {delay: DelayUpdate::Time(value)},
+            Parameter::DelayRatio => ModuleMessageUnion {delay: DelayUpdate::Ratio(value)},
+            Parameter::EnvelopeAttack => ModuleMessageUnion {envelope: EnvelopeUpdate::Attack(value)},
+            Parameter::EnvelopeDecay => ModuleMessageUnion {envelope: EnvelopeUpdate::Decay(value)},
+            Parameter::EnvelopeRelease => ModuleMessageUnion {envelope: EnvelopeUpdate::Release(value)},
+            Parameter::EnvelopeSustain => ModuleMessageUnion {envelope: EnvelopeUpdate::Sustain(value)},
+            Parameter::FixedVelocity => ModuleMessageUnion {midi: MidiUpdate::FixedVelocity(value.round() as u8)},
+            Parameter::FmAlgorithm => ModuleMessageUnion {fm: FmUpdate::Algorithm(value.round() as usize)},
+            Parameter::FmAttack(operator) => ModuleMessageUnion {fm: FmUpdate::Envelope(*operator, EnvelopeUpdate::Attack(value))},
+            Parameter::FmDecay(operator) => ModuleMessageUnion {fm: FmUpdate::Envelope(*operator, EnvelopeUpdate::Decay(value))},
+            Parameter::FmFeedback(operator) => ModuleMessageUnion {fm: FmUpdate::Feedback(*operator, value)},
+            Parameter::FmFixed(operator) => ModuleMessageUnion {fm: FmUpdate::Fixed(*operator, value >= 0.5)},
+            Parameter::FmFixedFrequency(operator) => ModuleMessageUnion {fm: FmUpdate::FixedFrequency(*operator, value)},
+            Parameter::FmGain => ModuleMessageUnion {fm: FmUpdate::Gain(value)},
+            Parameter::FmLevel(operator) => ModuleMessageUnion {fm: FmUpdate::Level(*operator, value)},
+            Parameter::FmRatio(operator) => ModuleMessageUnion {fm: FmUpdate::Ratio(*operator, value)},
+            Parameter::FmRelease(operator) => ModuleMessageUnion {fm: FmUpdate::Envelope(*operator, EnvelopeUpdate::Release(value))},
+            Parameter::FmSustain(operator) => ModuleMessageUnion {fm: FmUpdate::Envelope(*operator, EnvelopeUpdate::Sustain(value))},
+            Parameter::GlideTime => ModuleMessageUnion {midi: MidiUpdate::GlideTime(value)},
+            Parameter::ModulatorPitch => ModuleMessageUnion {analog: AnalogOscillatorUpdate::Frequency(value / 127.0)},
+            Parameter::NoiseGain => ModuleMessageUnion {noise: NoiseUpdate::Gain(value)},
+            Parameter::NoiseRandomDepth => ModuleMessageUnion {noise: NoiseUpdate::RandomDepth(value)},
+            Parameter::NoiseSmoothing => ModuleMessageUnion {noise: NoiseUpdate::Smoothing(value)},
+            Parameter::OscillatorFm => ModuleMessageUnion {analog: AnalogOscillatorUpdate::FmDepth(value)},
+            Parameter::PulseWidth => ModuleMessageUnion {analog: AnalogOscillatorUpdate::PulseWidth(value)},
+            Parameter::PwmDepth => ModuleMessageUnion {analog: AnalogOscillatorUpdate::PwmDepth(value)},
+            Parameter::PwmRate => ModuleMessageUnion {lfo: LfoUpdate::Frequency(value)},
+            Parameter::ReverbWet => ModuleMessageUnion {reverb: ReverbUpdate::Wet(value)},
+            Parameter::SamplerGain => ModuleMessageUnion {sampler: SamplerUpdate::Gain(value)},
+            Parameter::SupersawDetune => ModuleMessageUnion {supersaw: SupersawUpdate::Detune(value)},
+            Parameter::SupersawGain => ModuleMessageUnion {supersaw: SupersawUpdate::Gain(value)},
+            Parameter::SupersawMix => ModuleMessageUnion {supersaw: SupersawUpdate::Mix(value)},
+            Parameter::SupersawSpread => ModuleMessageUnion {supersaw: SupersawUpdate::Spread(value)},
+            Parameter::UnisonDetune => ModuleMessageUnion {midi: MidiUpdate::UnisonDetune(value)},
+            Parameter::WavetableGain => ModuleMessageUnion {wavetable: WavetableUpdate::Gain(value)},
+            Parameter::WavetablePosition => ModuleMessageUnion {wavetable: WavetableUpdate::Position(value)},
+        };
+        ModuleMessage::ComponentChange(msg_union)
+    }
+
+    /// Whether the module at an index takes this parameter, any other module would misread the message
+    pub fn accepts(&self, module: usize) -> bool {
+        let kind = match self {
+            Parameter::AdditiveBrightness
+            | Parameter::AdditiveGain
+            | Parameter::AdditiveOddEven
+            | Parameter::AdditiveStretch => ModuleKind::Additive,
+            Parameter::AnalogFrequency
+            | Parameter::AnalogPhase
+            | Parameter::ModulatorPitch
+            | Parameter::OscillatorFm
+            | Parameter::PulseWidth
+            | Parameter::PwmDepth => ModuleKind::AnalogOscillator,
+            Parameter::BendDown
+            | Parameter::BendUp
+            | Parameter::FixedVelocity
+            | Parameter::GlideTime
+            | Parameter::UnisonDetune => ModuleKind::Midi,
+            Parameter::ButterworthFrequency => ModuleKind::Butterworth,
+            Parameter::ChorusTime | Parameter::ChorusRatio => ModuleKind::Chorus,
+            Parameter::ClockBpm => ModuleKind::Clock,
+            Parameter::DelayTime | Parameter::DelayRatio => ModuleKind::Delay,
+            Parameter::EnvelopeAttack
+            | Parameter::EnvelopeDecay
+            | Parameter::EnvelopeRelease
+            | Parameter::EnvelopeSustain => ModuleKind::Envelope,
+            Parameter::FmAlgorithm
+            | Parameter::FmAttack(_)
+            | Parameter::FmDecay(_)
+            | Parameter::FmFeedback(_)
+            | Parameter::FmFixed(_)
+            | Parameter::FmFixedFrequency(_)
+            | Parameter::FmGain
+            | Parameter::FmLevel(_)
+            | Parameter::FmRatio(_)
+            | Parameter::FmRelease(_)
+            | Parameter::FmSustain(_) => ModuleKind::Fm,
+            Parameter::NoiseGain | Parameter::NoiseRandomDepth | Parameter::NoiseSmoothing => ModuleKind::Noise,
+            Parameter::PwmRate => ModuleKind::Lfo,
+            Parameter::ReverbWet => ModuleKind::Reverb,
+            Parameter::SamplerGain => ModuleKind::Sampler,
+            Parameter::SupersawDetune
+            | Parameter::SupersawGain
+            | Parameter::SupersawMix
+            | Parameter::SupersawSpread => ModuleKind::Supersaw,
+            Parameter::WavetableGain | Parameter::WavetablePosition => ModuleKind::WavetableOscillator,
+        };
+        ModuleKind::of(module) == Some(kind)
+    }
+
+    /// The full range of values the module accepts
+    pub fn range(&self) -> RangeInclusive<f32> {
+        match self {
+            Parameter::AdditiveBrightness => -2.0..=1.0,
+            Parameter::AdditiveGain => 0.0..=1.0,
+            Parameter::AdditiveOddEven => -1.0..=1.0,
+            Parameter::AdditiveStretch => -0.2..=0.2,
+            Parameter::AnalogFrequency => 0.0..=1.0,
+            Parameter::AnalogPhase => 0.0..=1.0,
+            Parameter::BendDown => 0.0..=48.0,
+            Parameter::BendUp => 0.0..=48.0,
+            Parameter::ButterworthFrequency => 0.0..=1.0,
+            Parameter::ChorusTime => 0.0..=255.0,
+            Parameter::ChorusRatio => 0.0..=0.99,
+            Parameter::ClockBpm => 20.0..=300.0,
+            Parameter::DelayTime => 0.0..=6.0,
+            Parameter::DelayRatio => 0.0..=0.99,
+            Parameter::EnvelopeAttack => 0.001..=10.0,
+            Parameter::EnvelopeDecay => 0.001..=10.0,
+            Parameter::EnvelopeRelease => 0.001..=10.0,
+            Parameter::EnvelopeSustain => 0.0..=1.0,
+            Parameter::FixedVelocity => 1.0..=127.0,
+            Parameter::FmAlgorithm => 0.0..=(ALGORITHMS.len() - 1) as f32,
+            Parameter::FmAttack(_) => 0.001..=10.0,
+            Parameter::FmDecay(_) => 0.001..=10.0,
+            Parameter::FmFeedback(_) => 0.0..=1.0,
+            Parameter::FmFixed(_) => 0.0..=1.0,
+            Parameter::FmFixedFrequency(_) => 1.0..=4000.0,
+            Parameter::FmGain => 0.0..=1.0,
+            Parameter::FmLevel(_) => 0.0..=1.0,
+            Parameter::FmRatio(_) => 0.5..=16.0,
+            Parameter::FmRelease(_) => 0.001..=10.0,
+            Parameter::FmSustain(_) => 0.0..=1.0,
+            Parameter::GlideTime => 0.0..=2.0,
+            Parameter::ModulatorPitch => 0.0..=24.0,
+            Parameter::NoiseGain => 0.0..=1.0,
+            Parameter::NoiseRandomDepth => 0.0..=1.0,
+            Parameter::NoiseSmoothing => 0.0..=2.0,
+            Parameter::OscillatorFm => 0.0..=1.0,
+            Parameter::PulseWidth => 0.05..=0.95,
+            Parameter::PwmDepth => 0.0..=0.45,
+            Parameter::PwmRate => 0.0..=0.4,
+            Parameter::ReverbWet => 0.0..=1.0,
+            Parameter::SamplerGain => 0.0..=1.0,
+            Parameter::SupersawDetune => 0.0..=1.0,
+            Parameter::SupersawGain => 0.0..=1.0,
+            Parameter::SupersawMix => 0.0..=1.0,
+            Parameter::SupersawSpread => 0.0..=1.0,
+            Parameter::UnisonDetune => 0.0..=2.0,
+            Parameter::WavetableGain => 0.0..=1.0,
+            Parameter::WavetablePosition => 0.0..=1.0,
+        }
+    }
+
+    /// The value the module starts with
+    pub fn default_value(&self) -> f32 {
+        match self {
+            Parameter::AdditiveBrightness => 0.0,
+            Parameter::AdditiveGain => 0.0,
+            Parameter::AdditiveOddEven => 0.0,
+            Parameter::AdditiveStretch => 0.0,
+            Parameter::AnalogFrequency => 0.0,
+            Parameter::AnalogPhase => 0.0,
+            Parameter::BendDown => 2.0,
+            Parameter::BendUp => 2.0,
+            Parameter::ButterworthFrequency => 1.0,
+            Parameter::ChorusTime => 128.0,
+            Parameter::ChorusRatio => 0.0,
+            Parameter::ClockBpm => 120.0,
+            Parameter::DelayTime => 0.0,
+            Parameter::DelayRatio => 0.0,
+            Parameter::EnvelopeAttack => 1.5,
+            Parameter::EnvelopeDecay => 1.0,
+            Parameter::EnvelopeRelease => 5.0,
+            Parameter::EnvelopeSustain => 0.6,
+            Parameter::FixedVelocity => 100.0,
+            Parameter::FmAlgorithm => 0.0,
+            Parameter::FmAttack(_) => 1.5,
+            Parameter::FmDecay(_) => 1.0,
+            Parameter::FmFeedback(_) => 0.0,
+            Parameter::FmFixed(_) => 0.0,
+            Parameter::FmFixedFrequency(_) => 440.0,
+            Parameter::FmGain => 0.0,
+            // Operator 2 modulates the carrier a little to start with
+            Parameter::FmLevel(0) => 1.0,
+            Parameter::FmLevel(1) => 0.5,
+            Parameter::FmLevel(_) => 0.0,
+            Parameter::FmRatio(_) => 1.0,
+            Parameter::FmRelease(_) => 5.0,
+            Parameter::FmSustain(_) => 0.6,
+            Parameter::GlideTime => 0.0,
+            Parameter::ModulatorPitch => 0.0,
+            Parameter::NoiseGain => 0.0,
+            Parameter::NoiseRandomDepth => 0.0,
+            Parameter::NoiseSmoothing => 0.0,
+            Parameter::OscillatorFm => 0.0,
+            Parameter::PulseWidth => 0.5,
+            Parameter::PwmDepth => 0.0,
+            Parameter::PwmRate => 0.1,
+            Parameter::ReverbWet => 0.5,
+            Parameter::SamplerGain => 1.0,
+            Parameter::SupersawDetune => 0.3,
+            Parameter::SupersawGain => 0.0,
+            Parameter::SupersawMix => 0.5,
+            Parameter::SupersawSpread => 0.5,
+            Parameter::UnisonDetune => 0.1,
+            Parameter::WavetableGain => 0.0,
+            Parameter::WavetablePosition => 0.0,
+        }
+    }
+
+    /// Stable identifier used when saving
+    pub fn key(&self) -> &'static str {
+        match self {
+            Parameter::AdditiveBrightness => "additive-brightness",
+            Parameter::AdditiveGain => "additive-gain",
+            Parameter::AdditiveOddEven => "additive-odd-even",
+            Parameter::AdditiveStretch => "additive-stretch",
+            Parameter::AnalogFrequency => "analog-frequency",
+            Parameter::AnalogPhase => "analog-phase",
+            Parameter::BendDown => "bend-down",
+            Parameter::BendUp => "bend-up",
+            Parameter::ButterworthFrequency => "butterworth-frequency",
+            Parameter::ChorusTime => "chorus-time",
+            Parameter::ChorusRatio => "chorus-ratio",
+            Parameter::ClockBpm => "clock-bpm",
+            Parameter::DelayTime => "delay-time",
+            Parameter::DelayRatio => "delay-ratio",
+            Parameter::EnvelopeAttack => "envelope-attack",
+            Parameter::EnvelopeDecay => "envelope-decay",
+            Parameter::EnvelopeRelease => "envelope-release",
+            Parameter::EnvelopeSustain => "envelope-sustain",
+            Parameter::FixedVelocity => "fixed-velocity",
+            Parameter::FmAlgorithm => "fm-algorithm",
+            Parameter::FmAttack(operator) => ["fm-attack-1", "fm-attack-2", "fm-attack-3", "fm-attack-4"][*operator],
+            Parameter::FmDecay(operator) => ["fm-decay-1", "fm-decay-2", "fm-decay-3", "fm-decay-4"][*operator],
+            Parameter::FmFeedback(operator) => ["fm-feedback-1", "fm-feedback-2", "fm-feedback-3", "fm-feedback-4"][*operator],
+            Parameter::FmFixed(operator) => ["fm-fixed-1", "fm-fixed-2", "fm-fixed-3", "fm-fixed-4"][*operator],
+            Parameter::FmFixedFrequency(operator) => ["fm-fixed-frequency-1", "fm-fixed-frequency-2", "fm-fixed-frequency-3", "fm-fixed-frequency-4"][*operator],
+            Parameter::FmGain => "fm-gain",
+            Parameter::FmLevel(operator) => ["fm-level-1", "fm-level-2", "fm-level-3", "fm-level-4"][*operator],
+            Parameter::FmRatio(operator) => ["fm-ratio-1", "fm-ratio-2", "fm-ratio-3", "fm-ratio-4"][*operator],
+            Parameter::FmRelease(operator) => ["fm-release-1", "fm-release-2", "fm-release-3", "fm-release-4"][*operator],
+            Parameter::FmSustain(operator) => ["fm-sustain-1", "fm-sustain-2", "fm-sustain-3", "fm-sustain-4"][*operator],
+            Parameter::GlideTime => "glide-time",
+            Parameter::ModulatorPitch => "modulator-pitch",
+            Parameter::NoiseGain => "noise-gain",
+            Parameter::NoiseRandomDepth => "noise-random-depth",
+            Parameter::NoiseSmoothing => "noise-smoothing",
+            Parameter::OscillatorFm => "oscillator-fm",
+            Parameter::PulseWidth => "pulse-width",
+            Parameter::PwmDepth => "pwm-depth",
+            Parameter::PwmRate => "pwm-rate",
+            Parameter::ReverbWet => "reverb-wet",
+            Parameter::SamplerGain => "sampler-gain",
+            Parameter::SupersawDetune => "supersaw-detune",
+            Parameter::SupersawGain => "supersaw-gain",
+            Parameter::SupersawMix => "supersaw-mix",
+            Parameter::SupersawSpread => "supersaw-spread",
+            Parameter::UnisonDetune => "unison-detune",
+            Parameter::WavetableGain => "wavetable-gain",
+            Parameter::WavetablePosition => "wavetable-position",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Parameter> {
+        Parameter::ALL.into_iter().find(|parameter| parameter.key() == key)
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Parameter::AdditiveBrightness => "Additive brightness",
+            Parameter::AdditiveGain => "Additive level",
+            Parameter::AdditiveOddEven => "Odd/even balance",
+            Parameter::AdditiveStretch => "Partial stretch",
+            Parameter::AnalogFrequency => "Oscillator frequency",
+            Parameter::AnalogPhase => "Oscillator phase",
+            Parameter::BendDown => "Bend down",
+            Parameter::BendUp => "Bend up",
+            Parameter::ButterworthFrequency => "Filter cutoff",
+            Parameter::ChorusTime => "Chorus time",
+            Parameter::ChorusRatio => "Chorus mix",
+            Parameter::ClockBpm => "Tempo",
+            Parameter::DelayTime => "Delay time",
+            Parameter::DelayRatio => "Delay feedback",
+            Parameter::EnvelopeAttack => "Envelope attack",
+            Parameter::EnvelopeDecay => "Envelope decay",
+            Parameter::EnvelopeRelease => "Envelope release",
+            Parameter::EnvelopeSustain => "Envelope sustain",
+            Parameter::FixedVelocity => "Fixed velocity",
+            Parameter::FmAlgorithm => "FM algorithm",
+            Parameter::FmAttack(operator) => return write!(f, "Op {} attack", operator + 1),
+            Parameter::FmDecay(operator) => return write!(f, "Op {} decay", operator + 1),
+            Parameter::FmFeedback(operator) => return write!(f, "Op {} feedback", operator + 1),
+            Parameter::FmFixed(operator) => return write!(f, "Op {} fixed", operator + 1),
+            Parameter::FmFixedFrequency(operator) => return write!(f, "Op {} frequency", operator + 1),
+            Parameter::FmGain => "FM level",
+            Parameter::FmLevel(operator) => return write!(f, "Op {} level", operator + 1),
+            Parameter::FmRatio(operator) => return write!(f, "Op {} ratio", operator + 1),
+            Parameter::FmRelease(operator) => return write!(f, "Op {} release", operator + 1),
+            Parameter::FmSustain(operator) => return write!(f, "Op {} sustain", operator + 1),
+            Parameter::GlideTime => "Glide time",
+            Parameter::ModulatorPitch => "Modulator pitch",
+            Parameter::NoiseGain => "Noise level",
+            Parameter::NoiseRandomDepth => "Random depth",
+            Parameter::NoiseSmoothing => "Random smoothing",
+            Parameter::OscillatorFm => "Oscillator FM depth",
+            Parameter::PulseWidth => "Pulse width",
+            Parameter::PwmDepth => "PWM depth",
+            Parameter::PwmRate => "PWM rate",
+            Parameter::ReverbWet => "Reverb wet",
+            Parameter::SamplerGain => "Sampler level",
+            Parameter::SupersawDetune => "Supersaw detune",
+            Parameter::SupersawGain => "Supersaw level",
+            Parameter::SupersawMix => "Supersaw side mix",
+            Parameter::SupersawSpread => "Supersaw stereo spread",
+            Parameter::UnisonDetune => "Unison detune",
+            Parameter::WavetableGain => "Wavetable level",
+            Parameter::WavetablePosition => "Wavetable position",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Modules repeated in every poly voice, which are all set alike
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum VoiceGroup {
+    Oscillators,
+    Fm,
+    Samplers,
+    Additive,
+    Modulators,
+}
+
+impl VoiceGroup {
+    pub const ALL: [VoiceGroup; 5] = [
+        VoiceGroup::Oscillators,
+        VoiceGroup::Fm,
+        VoiceGroup::Samplers,
+        VoiceGroup::Additive,
+        VoiceGroup::Modulators,
+    ];
+
+    pub fn modules(&self) -> RangeInclusive<usize> {
+        match self {
+            VoiceGroup::Oscillators => super::OSCILLATOR_MODULES,
+            VoiceGroup::Fm => super::FM_MODULES,
+            VoiceGroup::Samplers => super::SAMPLER_MODULES,
+            VoiceGroup::Additive => super::ADDITIVE_MODULES,
+            VoiceGroup::Modulators => super::MODULATOR_MODULES,
+        }
+    }
+
+    /// Stable identifier used when saving, in place of a module index
+    pub fn key(&self) -> &'static str {
+        match self {
+            VoiceGroup::Oscillators => "oscillators",
+            VoiceGroup::Fm => "fm",
+            VoiceGroup::Samplers => "samplers",
+            VoiceGroup::Additive => "additive",
+            VoiceGroup::Modulators => "modulators",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<VoiceGroup> {
+        VoiceGroup::ALL.into_iter().find(|group| group.key() == key)
+    }
+}
+
+/// Where a parameter is set
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Destination {
+    /// A single module in the table
+    Module(usize),
+    /// The same module in every poly voice
+    Voices(VoiceGroup),
+}
+
+impl Destination {
+    /// Reads a module index or a voice group key
+    pub fn from_key(key: &str) -> Option<Destination> {
+        match key.parse() {
+            Ok(module) => Some(Destination::Module(module)),
+            Err(_) => VoiceGroup::from_key(key).map(Destination::Voices),
+        }
+    }
+}
+
+/// Written the way `from_key` reads it
+impl fmt::Display for Destination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Destination::Module(module) => write!(f, "{module}"),
+            Destination::Voices(group) => write!(f, "{}", group.key()),
+        }
+    }
+}
+
+/// A parameter on a module in the table, or on a group of them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Target {
+    pub destination: Destination,
+    pub parameter: Parameter,
+}
+
+impl Target {
+    pub fn new(module: usize, parameter: Parameter) -> Self {
+        Self {
+            destination: Destination::Module(module),
+            parameter,
+        }
+    }
+
+    pub fn voices(group: VoiceGroup, parameter: Parameter) -> Self {
+        Self {
+            destination: Destination::Voices(group),
+            parameter,
+        }
+    }
+
+    /// Reads a target saved as its destination and parameter key, `None` unless every module takes the parameter
+    pub fn from_keys(destination: &str, parameter: &str) -> Option<Target> {
+        let target = Self {
+            destination: Destination::from_key(destination)?,
+            parameter: Parameter::from_key(parameter)?,
+        };
+        Some(target).filter(Target::is_valid)
+    }
+
+    pub fn modules(&self) -> RangeInclusive<usize> {
+        match self.destination {
+            Destination::Module(module) => module..=module,
+            Destination::Voices(group) => group.modules(),
+        }
+    }
+
+    /// Targets read from files are only sent if this holds
+    pub fn is_valid(&self) -> bool {
+        self.modules().all(|module| self.parameter.accepts(module))
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.parameter, self.destination)
+    }
+}
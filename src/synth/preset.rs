@@ -0,0 +1,155 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use super::parameter::Target;
+
+const PRESET_DIR: &str = "presets";
+
+pub struct Preset {
+    pub program: u8,
+    pub name: String,
+    pub parameters: Vec<(Target, f32)>,
+    /// Choices that aren't parameters, like the wave shape or voice count, as setting and value
+    pub settings: Vec<(String, String)>,
+}
+
+/// Up to 128 presets chosen by program change, one file per bank.
+///
+/// `preset <program> <name>` starts a preset, followed by its values as
+/// `param <module or voice group> <parameter> <value>` and `set <setting> <value>`
+#[derive(Default)]
+pub struct Bank {
+    presets: Vec<Preset>,
+}
+
+impl Bank {
+    /// A bank without a file is empty
+    pub fn load(bank: u16) -> Result<Self, String> {
+        match fs::read_to_string(path(bank)) {
+            Ok(contents) => Ok(Self::parse(&contents)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+
+    pub fn save(&self, bank: u16) -> Result<(), String> {
+        fs::create_dir_all(PRESET_DIR).map_err(|err| err.to_string())?;
+        fs::write(path(bank), self.serialize()).map_err(|err| err.to_string())
+    }
+
+    pub fn get(&self, program: u8) -> Option<&Preset> {
+        self.presets.iter().find(|preset| preset.program == program)
+    }
+
+    /// Replaces the preset with the same program
+    pub fn insert(&mut self, preset: Preset) {
+        self.presets.retain(|existing| existing.program != preset.program);
+        self.presets.push(preset);
+        self.presets.sort_by_key(|preset| preset.program);
+    }
+
+    fn parse(contents: &str) -> Self {
+        let mut bank = Self::default();
+
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["preset", program, name @ ..] => match program.parse() {
+                    Ok(program) if program < 128 => bank.presets.push(Preset {
+                        program,
+                        name: name.join(" "),
+                        parameters: Vec::new(),
+                        settings: Vec::new(),
+                    }),
+                    _ => eprintln!("Invalid preset in bank: {line}"),
+                },
+                ["param", destination, parameter, value] => {
+                    let parameter = (|| Some((
+                        Target::from_keys(destination, parameter)?,
+                        value.parse().ok()?,
+                    )))();
+
+                    match (bank.presets.last_mut(), parameter) {
+                        (Some(preset), Some(parameter)) => preset.parameters.push(parameter),
+                        _ => eprintln!("Invalid parameter in bank: {line}"),
+                    }
+                },
+                ["set", setting, value @ ..] if !value.is_empty() => match bank.presets.last_mut() {
+                    Some(preset) => preset.settings.push((setting.to_string(), value.join(" "))),
+                    None => eprintln!("Invalid setting in bank: {line}"),
+                },
+                [] => (),
+                _ => eprintln!("Unknown bank entry: {line}"),
+            }
+        }
+
+        bank
+    }
+
+    fn serialize(&self) -> String {
+        let mut contents = String::new();
+
+        for preset in &self.presets {
+            contents += &format!("preset {} {}\n", preset.program, preset.name);
+            for (target, value) in &preset.parameters {
+                contents += &format!("param {} {} {}\n", target.destination, target.parameter.key(), value);
+            }
+            for (setting, value) in &preset.settings {
+                contents += &format!("set {setting} {value}\n");
+            }
+        }
+
+        contents
+    }
+}
+
+fn path(bank: u16) -> PathBuf {
+    PathBuf::from(PRESET_DIR).join(format!("bank-{bank}.txt"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::parameter::Parameter;
+
+    #[test]
+    fn parses_presets() {
+        let bank = Bank::parse("preset 3 Bright lead\nparam 50 delay-time 0.25\nset oscillator-shape Square\nset arp-order As played\npreset 7 Pad\n");
+
+        let preset = bank.get(3).unwrap();
+        assert_eq!(preset.name, "Bright lead");
+        assert_eq!(preset.parameters, [(Target::new(50, Parameter::DelayTime), 0.25)]);
+        assert_eq!(preset.settings, [
+            (String::from("oscillator-shape"), String::from("Square")),
+            (String::from("arp-order"), String::from("As played")),
+        ]);
+        assert!(bank.get(7).unwrap().parameters.is_empty());
+        assert!(bank.get(4).is_none());
+    }
+
+    #[test]
+    fn drops_parameters_the_module_cannot_take() {
+        let bank = Bank::parse("\
+            param 50 delay-time 1\n\
+            preset 0 Init\n\
+            param 3 delay-time 1\n\
+            param 0 reverb-wet 1\n\
+            param 500 delay-time 1\n\
+            param 50 no-such-parameter 1\n\
+            param 50 delay-time loud\n\
+            param 51 reverb-wet 0.5\n\
+            preset 128 Out of range\n\
+        ");
+
+        assert_eq!(bank.presets.len(), 1);
+        assert_eq!(bank.get(0).unwrap().parameters, [(Target::new(51, Parameter::ReverbWet), 0.5)]);
+    }
+
+    #[test]
+    fn serialize_round_trips() {
+        let contents = "preset 0 Init\nparam 51 reverb-wet 0.5\nset voices 8\npreset 1 Second\n";
+
+        assert_eq!(Bank::parse(contents).serialize(), contents);
+    }
+}
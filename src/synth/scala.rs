@@ -0,0 +1,258 @@
+use crate::audio::tuning::Tuning;
+
+/// A4 in the standard keyboard mapping
+const STANDARD_REFERENCE_FREQUENCY: f32 = 440.0;
+
+/// A Scala scale, every degree in cents above the root with the period last
+#[derive(Clone, Debug)]
+pub struct Scale {
+    cents: Vec<f32>,
+}
+
+impl Scale {
+    pub fn equal() -> Self {
+        Self {
+            cents: (1..=12).map(|degree| degree as f32 * 100.0).collect(),
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.cents.len()
+    }
+
+    /// Cents above the root, degrees past the period repeat it
+    fn degree_cents(&self, degree: i32) -> f32 {
+        let size = self.cents.len() as i32;
+        let period = self.cents[self.cents.len() - 1];
+        let index = degree.rem_euclid(size);
+        let root = degree.div_euclid(size) as f32 * period;
+
+        if index == 0 {
+            root
+        } else {
+            root + self.cents[index as usize - 1]
+        }
+    }
+}
+
+/// A Scala keyboard mapping, which key plays which scale degree and the reference frequency
+#[derive(Clone, Debug)]
+pub struct KeyboardMap {
+    first: u8,
+    last: u8,
+    middle: u8,
+    reference_key: u8,
+    reference_frequency: f32,
+    /// Scale degree each repeat of the mapping moves by, zero uses the scale size
+    octave_degree: i32,
+    /// Empty maps keys to consecutive degrees
+    mapping: Vec<Option<i32>>,
+}
+
+impl KeyboardMap {
+    /// Consecutive degrees from middle C with A4 at 440 Hz
+    pub fn standard() -> Self {
+        Self {
+            first: 0,
+            last: 127,
+            middle: 60,
+            reference_key: 69,
+            reference_frequency: STANDARD_REFERENCE_FREQUENCY,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+
+    fn degree(&self, key: u8, scale_size: usize) -> Option<i32> {
+        if key < self.first || key > self.last {
+            return None;
+        }
+
+        let offset = key as i32 - self.middle as i32;
+        if self.mapping.is_empty() {
+            return Some(offset);
+        }
+
+        let size = self.mapping.len() as i32;
+        let octave_degree = if self.octave_degree == 0 {
+            scale_size as i32
+        } else {
+            self.octave_degree
+        };
+        self.mapping[offset.rem_euclid(size) as usize].map(|degree| degree + offset.div_euclid(size) * octave_degree)
+    }
+}
+
+/// Builds the tuning, `reference_pitch` moves every key by its ratio to A4 = 440 Hz
+pub fn tuning(scale: &Scale, map: &KeyboardMap, reference_pitch: f32) -> Result<Tuning, String> {
+    let reference_degree = map.degree(map.reference_key, scale.size())
+        .ok_or_else(|| format!("Reference key {} is not mapped", map.reference_key))?;
+    let reference_cents = scale.degree_cents(reference_degree);
+    let transpose = reference_pitch / STANDARD_REFERENCE_FREQUENCY;
+
+    Ok(Tuning::from_frequencies(|key| {
+        let cents = scale.degree_cents(map.degree(key, scale.size())?);
+        Some(map.reference_frequency * 2.0_f32.powf((cents - reference_cents) / 1200.0) * transpose)
+    }))
+}
+
+/// Lines that aren't comments, `!` starts a comment
+fn lines(contents: &str) -> impl Iterator<Item = &str> {
+    contents.lines().map(str::trim).filter(|line| !line.starts_with('!'))
+}
+
+fn first_field(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+pub fn parse_scale(contents: &str) -> Result<Scale, String> {
+    let mut lines = lines(contents);
+    let _description = lines.next().ok_or_else(|| String::from("Missing description"))?;
+    let mut lines = lines.filter(|line| !line.is_empty());
+    let count: usize = lines.next()
+        .and_then(|line| first_field(line).parse().ok())
+        .ok_or_else(|| String::from("Missing note count"))?;
+
+    let cents = lines
+        .take(count)
+        .map(|line| parse_pitch(first_field(line)).ok_or_else(|| format!("Invalid pitch: {line}")))
+        .collect::<Result<Vec<f32>, String>>()?;
+
+    if cents.is_empty() || cents.len() < count {
+        return Err(format!("Expected {count} pitches, found {}", cents.len()));
+    }
+    Ok(Scale {
+        cents,
+    })
+}
+
+/// Cents if the value has a period, otherwise a ratio or a whole number
+fn parse_pitch(pitch: &str) -> Option<f32> {
+    if pitch.contains('.') {
+        return pitch.parse().ok();
+    }
+
+    let (numerator, denominator) = pitch.split_once('/').unwrap_or((pitch, "1"));
+    let numerator: f32 = numerator.parse().ok()?;
+    let denominator: f32 = denominator.parse().ok()?;
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return None;
+    }
+    Some(1200.0 * (numerator / denominator).log2())
+}
+
+pub fn parse_keyboard_map(contents: &str) -> Result<KeyboardMap, String> {
+    let mut lines = lines(contents).filter(|line| !line.is_empty());
+    let mut header = [0.0; 7];
+    for value in &mut header {
+        *value = lines.next()
+            .and_then(|line| first_field(line).parse().ok())
+            .ok_or_else(|| String::from("Incomplete keyboard mapping header"))?;
+    }
+    let [size, first, last, middle, reference_key, reference_frequency, octave_degree] = header;
+    if !(0.0..=128.0).contains(&size) {
+        return Err(format!("Invalid mapping size: {size}"));
+    }
+
+    let key = |value: f32| (value as i32).clamp(0, 127) as u8;
+    // Missing entries at the end are unmapped
+    let mut mapping: Vec<Option<i32>> = lines
+        .take(size as usize)
+        .map(|line| first_field(line).parse().ok())
+        .collect();
+    mapping.resize(size as usize, None);
+
+    Ok(KeyboardMap {
+        first: key(first),
+        last: key(last),
+        middle: key(middle),
+        reference_key: key(reference_key),
+        reference_frequency,
+        octave_degree: octave_degree as i32,
+        mapping,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-3, "{actual} != {expected}");
+    }
+
+    #[test]
+    fn parses_cents_ratios_and_whole_numbers() {
+        let scale = parse_scale("! meantone.scl\n!\nQuarter-comma meantone\n 3\n!\n 193.157 cents\n\n 5/4\n 2\n").unwrap();
+
+        assert_eq!(scale.size(), 3);
+        assert_close(scale.cents[0], 193.157);
+        assert_close(scale.cents[1], 386.314);
+        assert_close(scale.cents[2], 1200.0);
+        assert_close(scale.degree_cents(4), 1393.157);
+        assert_close(scale.degree_cents(-1), -813.686);
+    }
+
+    #[test]
+    fn rejects_invalid_scales() {
+        assert!(parse_scale("").is_err());
+        assert!(parse_scale("Missing count\n").is_err());
+        assert!(parse_scale("Too few pitches\n 3\n 100.0\n 200.0\n").is_err());
+        assert!(parse_scale("Bad pitch\n 1\n abc\n").is_err());
+        assert!(parse_scale("Zero ratio\n 1\n 0/1\n").is_err());
+        assert!(parse_scale("Empty\n 0\n").is_err());
+    }
+
+    #[test]
+    fn parses_keyboard_map() {
+        let map = parse_keyboard_map("! test.kbm\n 2\n 0\n 100\n 60\n 60\n 261.6\n 1\n! mapping\n 0\n x\n").unwrap();
+
+        assert_eq!(map.degree(60, 12), Some(0));
+        assert_eq!(map.degree(61, 12), None);
+        assert_eq!(map.degree(62, 12), Some(1));
+        assert_eq!(map.degree(58, 12), Some(-1));
+        assert_eq!(map.degree(101, 12), None);
+        assert_close(map.reference_frequency, 261.6);
+    }
+
+    #[test]
+    fn missing_mapping_entries_are_unmapped() {
+        let map = parse_keyboard_map("3\n 0\n 127\n 60\n 60\n 440.0\n 0\n 0\n").unwrap();
+
+        assert_eq!(map.mapping, [Some(0), None, None]);
+    }
+
+    #[test]
+    fn rejects_invalid_keyboard_maps() {
+        assert!(parse_keyboard_map("12\n 0\n 127\n 60\n 69\n").is_err());
+        assert!(parse_keyboard_map("129\n 0\n 127\n 60\n 69\n 440.0\n 12\n").is_err());
+        assert!(parse_keyboard_map("-1\n 0\n 127\n 60\n 69\n 440.0\n 12\n").is_err());
+    }
+
+    #[test]
+    fn equal_scale_matches_standard_tuning() {
+        let tuning = tuning(&Scale::equal(), &KeyboardMap::standard(), 440.0).unwrap();
+        let equal = Tuning::equal();
+
+        for key in 0..128 {
+            assert!((tuning.note(key) - equal.note(key)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn reference_pitch_moves_every_key() {
+        let tuning = tuning(&Scale::equal(), &KeyboardMap::standard(), 432.0).unwrap();
+        let shift = 12.0 * (432.0_f32 / 440.0).log2() / 127.0;
+
+        for key in [0, 60, 69, 127] {
+            assert!((tuning.note(key) - (key as f32 / 127.0 + shift)).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn unmapped_reference_key_is_an_error() {
+        let map = parse_keyboard_map("2\n 0\n 127\n 60\n 61\n 440.0\n 1\n 0\n x\n").unwrap();
+
+        assert!(tuning(&Scale::equal(), &map, 440.0).is_err());
+    }
+}
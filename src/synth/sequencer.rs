@@ -0,0 +1,131 @@
+use super::Message;
+
+use crate::audio::module::clock::{Division, Feel, NoteDivision};
+use crate::audio::module::sequencer::{Pattern, SequencerUpdate, Step, MAX_STEPS, PATTERN_COUNT};
+
+use iced::widget::{button, checkbox, column, mouse_area, pick_list, row, slider, text, Column, Row};
+use iced::{Element, Length};
+
+const STEPS_PER_ROW: usize = 16;
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// The GUI's copy of the sequencer patterns, edited here and mirrored to the module
+pub struct SequencerEditor {
+    patterns: [Pattern; PATTERN_COUNT],
+    pattern: usize,
+    division: NoteDivision,
+    swing: f32,
+    selected: usize,
+}
+
+impl SequencerEditor {
+    pub fn new() -> Self {
+        Self {
+            patterns: [Pattern::default(); PATTERN_COUNT],
+            pattern: 0,
+            division: NoteDivision::new(Division::Sixteenth, Feel::Straight),
+            swing: 0.0,
+            selected: 0,
+        }
+    }
+
+    pub fn update(&mut self, update: SequencerUpdate) {
+        match update {
+            SequencerUpdate::Pattern(pattern) => self.pattern = pattern,
+            SequencerUpdate::Length(length) => self.patterns[self.pattern].length = length,
+            SequencerUpdate::Step(index, step) => self.patterns[self.pattern].steps[index] = step,
+            SequencerUpdate::Division(division) => self.division = division,
+            SequencerUpdate::Swing(swing) => self.swing = swing,
+        }
+    }
+
+    pub fn select(&mut self, index: usize) {
+        self.selected = index;
+    }
+
+    fn step(&self, index: usize) -> Step {
+        self.patterns[self.pattern].steps[index]
+    }
+
+    /// Left click toggles a step, right click selects it for editing
+    pub fn view(&self) -> Element<'_, Message> {
+        let pattern = &self.patterns[self.pattern];
+
+        let settings = row![
+            text("Sequencer"),
+            pick_list((1..=PATTERN_COUNT).collect::<Vec<_>>(), Some(self.pattern + 1), |pattern| {
+                Message::Sequencer(SequencerUpdate::Pattern(pattern - 1))
+            }),
+            pick_list((1..=MAX_STEPS).collect::<Vec<_>>(), Some(pattern.length), |length| {
+                Message::Sequencer(SequencerUpdate::Length(length))
+            }),
+            text("steps"),
+            pick_list(NoteDivision::all(), Some(self.division), |division| {
+                Message::Sequencer(SequencerUpdate::Division(division))
+            }),
+            text("Swing"),
+            slider(0.0..=0.5, self.swing, |swing| Message::Sequencer(SequencerUpdate::Swing(swing)))
+                .step(0.01)
+                .width(150),
+        ].spacing(10);
+
+        let rows = (0..pattern.length).step_by(STEPS_PER_ROW).map(|start| {
+            Row::with_children((start..(start + STEPS_PER_ROW).min(pattern.length)).map(|index| self.step_cell(index)))
+                .spacing(2)
+                .into()
+        });
+
+        column![
+            settings,
+            Column::with_children(rows).spacing(2),
+            self.step_editor(),
+        ].spacing(5).into()
+    }
+
+    fn step_cell(&self, index: usize) -> Element<'_, Message> {
+        let step = self.step(index);
+        let label = if step.gate {
+            note_name(step.note)
+        } else {
+            String::from("-")
+        };
+
+        let cell = button(text(label).size(12))
+            .width(Length::Fixed(40.0))
+            .on_press(Message::Sequencer(SequencerUpdate::Step(index, Step { gate: !step.gate, ..step })));
+        let cell = if index == self.selected {
+            cell.style(button::success)
+        } else if step.gate {
+            cell.style(button::primary)
+        } else {
+            cell.style(button::secondary)
+        };
+
+        mouse_area(cell)
+            .on_right_press(Message::SequencerSelect(index))
+            .into()
+    }
+
+    fn step_editor(&self) -> Element<'_, Message> {
+        let index = self.selected.min(self.patterns[self.pattern].length - 1);
+        let step = self.step(index);
+        let change = move |step: Step| Message::Sequencer(SequencerUpdate::Step(index, step));
+
+        row![
+            text(format!("Step {}", index + 1)),
+            text(format!("Note {}", note_name(step.note))).width(70),
+            slider(0..=127, step.note, move |note| change(Step { note, ..step })).width(150),
+            text(format!("Velocity {}", step.velocity)).width(90),
+            slider(1..=127, step.velocity, move |velocity| change(Step { velocity, ..step })).width(100),
+            text(format!("Chance {:.0}%", step.probability * 100.0)).width(100),
+            slider(0.0..=1.0, step.probability, move |probability| change(Step { probability, ..step }))
+                .step(0.01)
+                .width(100),
+            checkbox("Tie", step.tie).on_toggle(move |tie| change(Step { tie, ..step })),
+        ].spacing(10).into()
+    }
+}
+
+fn note_name(note: u8) -> String {
+    format!("{}{}", NOTE_NAMES[note as usize % 12], note as i32 / 12 - 1)
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::io;
+
+use super::mapping::{Curve, Mapping};
+use super::parameter::Target;
+
+const SESSION_PATH: &str = "cav-synth.session";
+
+/// Settings kept between runs, stored as one entry per line.
+///
+/// `map <controller> <module or voice group> <parameter> <min> <max> <curve>`
+/// `virtual-port <on|off>`
+/// `midi-output <port name>`
+/// `scale <path>`
+/// `keyboard-map <path>`
+/// `reference-pitch <hz>`
+#[derive(Default)]
+pub struct Session {
+    pub mappings: Vec<Mapping>,
+    pub virtual_port: bool,
+    pub midi_output: Option<String>,
+    /// Scala files of the loaded tuning
+    pub scale: Option<String>,
+    pub keyboard_map: Option<String>,
+    pub reference_pitch: Option<f32>,
+}
+
+impl Session {
+    pub fn load() -> Self {
+        match fs::read_to_string(SESSION_PATH) {
+            Ok(contents) => Self::parse(&contents),
+            Err(err) => {
+                if err.kind() != io::ErrorKind::NotFound {
+                    eprintln!("Failed to load session: {err}");
+                }
+                Self::default()
+            },
+        }
+    }
+
+    pub fn save(&self) {
+        if let Err(err) = fs::write(SESSION_PATH, self.serialize()) {
+            eprintln!("Failed to save session: {err}");
+        }
+    }
+
+    fn parse(contents: &str) -> Self {
+        let mut session = Self::default();
+
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["map", controller, destination, parameter, min, max, curve] => {
+                    let mapping = (|| Some(Mapping {
+                        controller: controller.parse().ok()?,
+                        target: Target::from_keys(destination, parameter)?,
+                        min: min.parse().ok()?,
+                        max: max.parse().ok()?,
+                        curve: Curve::from_key(curve)?,
+                    }))();
+
+                    match mapping {
+                        Some(mapping) => session.mappings.push(mapping),
+                        None => eprintln!("Invalid mapping in session: {line}"),
+                    }
+                },
+                ["virtual-port", enabled] => session.virtual_port = *enabled == "on",
+                ["midi-output", name @ ..] if !name.is_empty() => session.midi_output = Some(name.join(" ")),
+                ["scale", path @ ..] if !path.is_empty() => session.scale = Some(path.join(" ")),
+                ["keyboard-map", path @ ..] if !path.is_empty() => session.keyboard_map = Some(path.join(" ")),
+                ["reference-pitch", pitch] => match pitch.parse() {
+                    Ok(pitch) => session.reference_pitch = Some(pitch),
+                    Err(_) => eprintln!("Invalid reference pitch in session: {line}"),
+                },
+                [] => (),
+                _ => eprintln!("Unknown session entry: {line}"),
+            }
+        }
+
+        session
+    }
+
+    fn serialize(&self) -> String {
+        let mut contents = String::new();
+
+        if self.virtual_port {
+            contents += "virtual-port on\n";
+        }
+        if let Some(name) = &self.midi_output {
+            contents += &format!("midi-output {name}\n");
+        }
+        if let Some(path) = &self.scale {
+            contents += &format!("scale {path}\n");
+        }
+        if let Some(path) = &self.keyboard_map {
+            contents += &format!("keyboard-map {path}\n");
+        }
+        if let Some(pitch) = self.reference_pitch {
+            contents += &format!("reference-pitch {pitch}\n");
+        }
+
+        for mapping in &self.mappings {
+            contents += &format!(
+                "map {} {} {} {} {} {}\n",
+                mapping.controller,
+                mapping.target.destination,
+                mapping.target.parameter.key(),
+                mapping.min,
+                mapping.max,
+                mapping.curve.key(),
+            );
+        }
+
+        contents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::parameter::{Parameter, VoiceGroup};
+
+    #[test]
+    fn parses_entries() {
+        let session = Session::parse("virtual-port on\nmidi-output Some Port 1\nscale tunings/a b.scl\nreference-pitch 432\nmap 7 50 delay-time 0 2 exponential\n");
+
+        assert!(session.virtual_port);
+        assert_eq!(session.midi_output.as_deref(), Some("Some Port 1"));
+        assert_eq!(session.scale.as_deref(), Some("tunings/a b.scl"));
+        assert_eq!(session.keyboard_map, None);
+        assert_eq!(session.reference_pitch, Some(432.0));
+        assert_eq!(session.mappings, [Mapping {
+            controller: 7,
+            target: Target::new(50, Parameter::DelayTime),
+            min: 0.0,
+            max: 2.0,
+            curve: Curve::Exponential,
+        }]);
+    }
+
+    #[test]
+    fn drops_mappings_the_module_cannot_take() {
+        let session = Session::parse("\
+            map 1 3 delay-time 0 1 linear\n\
+            map 1 0 delay-time 0 1 linear\n\
+            map 1 500 delay-time 0 1 linear\n\
+            map 1 50 no-such-parameter 0 1 linear\n\
+            map 1 50 delay-time 0 1 no-such-curve\n\
+            map 300 50 delay-time 0 1 linear\n\
+            map 1 samplers pulse-width 0 1 linear\n\
+            map 1 all pulse-width 0 1 linear\n\
+            map 1 50 delay-time 0 1 linear\n\
+            map 2 oscillators pulse-width 0 1 linear\n\
+        ");
+
+        assert_eq!(session.mappings.len(), 2);
+        assert_eq!(session.mappings[0].target, Target::new(50, Parameter::DelayTime));
+        assert_eq!(session.mappings[1].target, Target::voices(VoiceGroup::Oscillators, Parameter::PulseWidth));
+    }
+
+    #[test]
+    fn serialize_round_trips() {
+        let contents = "virtual-port on\nmidi-output Port\nscale a.scl\nkeyboard-map a.kbm\nreference-pitch 440\nmap 7 0 glide-time 0 1 logarithmic\nmap 8 fm fm-gain 0 1 linear\n";
+
+        assert_eq!(Session::parse(contents).serialize(), contents);
+    }
+}